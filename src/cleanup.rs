@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::config::{normalize_hostname, Config, HostMapping};
+use crate::provider::Providers;
+use crate::record::RecordType;
+use crate::state::StateCache;
+
+/// Deletes records created by ddns6 whose hostname is no longer configured
+/// (as a host or in one of its views) or no longer published to the
+/// provider, A records of hosts that no longer have `ipv4` enabled, AAAA
+/// records of removed uplinks and, with `reverse` enabled, PTR records
+/// pointing to removed hosts. Providers that cannot list their records are
+/// skipped, and a provider that fails to list them does not stop the others.
+/// Returns the hostnames of the deleted records.
+pub async fn collect_garbage(
+    config: &Config,
+    providers: &Providers,
+    state_cache: &StateCache,
+) -> Vec<String> {
+    // Views are published to the providers of their host, never with A records.
+    let configured: HashMap<String, (&HostMapping, bool)> = config
+        .hosts
+        .iter()
+        .flat_map(|h| {
+            std::iter::once((h.hostname.clone(), (h, h.ipv4))).chain(
+                h.views
+                    .iter()
+                    .map(move |v| (v.hostname.clone(), (h, false))),
+            )
+        })
+        .collect();
+
+    let mut deleted = Vec::new();
+
+    let mut record_types = vec![RecordType::Aaaa, RecordType::A];
+    if config.reverse.enabled {
+        record_types.push(RecordType::Ptr);
+    }
+
+    for (name, provider) in providers.iter() {
+        if !provider.supports_cleanup() {
+            continue;
+        }
+
+        for record_type in &record_types {
+            let record_type = *record_type;
+            let records = match provider.list_managed_records(record_type).await {
+                Ok(records) => records,
+                Err(e) => {
+                    error!(
+                        "Cleanup failed to list {} records at {}: {}",
+                        record_type, name, e
+                    );
+                    continue;
+                }
+            };
+
+            for record in records {
+                // PTR records are named after the address and point to the host.
+                let hostname = match record_type {
+                    RecordType::Ptr => record.contents.first().unwrap_or(&record.name),
+                    _ => &record.name,
+                };
+                let host = normalize_hostname(hostname)
+                    .ok()
+                    .and_then(|hostname| configured.get(&hostname));
+                let wanted = match host {
+                    Some((host, ipv4)) if host.uses_provider(name) => match record_type {
+                        RecordType::Aaaa | RecordType::Ptr => record
+                            .uplink
+                            .as_deref()
+                            .is_none_or(|uplink| config.uplink(uplink).is_some()),
+                        RecordType::A => *ipv4,
+                    },
+                    _ => false,
+                };
+                if wanted {
+                    continue;
+                }
+
+                info!(
+                    "Deleting stale {} record {} -> {} at {}",
+                    record_type,
+                    record.name,
+                    record.contents.join(", "),
+                    name
+                );
+
+                match provider.delete_managed_record(record_type, &record).await {
+                    Ok(()) => {
+                        // Hosts moved to other providers keep their records.
+                        if host.is_none_or(|(host, _)| host.uses_provider(name)) {
+                            state_cache.remove_record(&record.name, record_type).await;
+                        }
+                        deleted.push(record.name);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to delete stale record for {} at {}: {}",
+                            record.name, name, e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    deleted
+}
+
+/// Runs a cleanup pass at startup and, if an interval is configured,
+/// periodically afterwards.
+pub fn spawn(config: Arc<Config>, providers: Providers, state_cache: StateCache) {
+    for (name, provider) in providers.iter() {
+        if !provider.supports_cleanup() {
+            warn!(
+                "Cleanup skips provider {}, which cannot list the records ddns6 created",
+                name
+            );
+        }
+    }
+
+    tokio::spawn(async move {
+        let interval = config.cleanup.interval_secs.map(Duration::from_secs);
+
+        loop {
+            let deleted = collect_garbage(&config, &providers, &state_cache).await;
+            if deleted.is_empty() {
+                info!("Cleanup: no stale records found");
+            } else {
+                info!(
+                    "Cleanup: deleted {} stale record(s): {}",
+                    deleted.len(),
+                    deleted.join(", ")
+                );
+            }
+
+            match interval {
+                Some(interval) => tokio::time::sleep(interval).await,
+                None => break,
+            }
+        }
+    });
+}
@@ -1,16 +1,39 @@
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::net::Ipv6Addr;
-use tracing::{debug, error, info};
-
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{
+    is_reverse_zone, longest_zone, normalize_hostname, CloudflareConfig, HostMapping,
+    MarkerLocation, OwnershipConfig, Secret,
+};
 use crate::error::{Ddns6Error, Result};
+use crate::ipv6::reverse_name;
+use crate::provider::http_client;
+use crate::record::RecordType;
 
 #[derive(Debug, Clone)]
 pub struct CloudflareClient {
     client: Client,
-    api_token: String,
+    api_url: String,
+    api_token: Secret,
     zone_id: String,
+    zone_names: Vec<String>,
+    auto_zones: bool,
     ttl: u32,
+    ownership: Option<OwnershipConfig>,
+    zones: Arc<OnceCell<Vec<Zone>>>,
+}
+
+/// A Cloudflare zone. The zone configured through `zone_id` has an empty name
+/// and serves as the fallback for hosts outside every named zone.
+#[derive(Debug, Clone, Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -21,8 +44,13 @@ struct CreateRecordRequest {
     content: String,
     ttl: u32,
     proxied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
+/// Sent with PATCH, so fields that are left out keep their current value.
 #[derive(Debug, Serialize)]
 struct UpdateRecordRequest {
     #[serde(rename = "type")]
@@ -31,6 +59,10 @@ struct UpdateRecordRequest {
     content: String,
     ttl: u32,
     proxied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +72,14 @@ struct CloudflareResponse<T> {
     #[allow(dead_code)]
     messages: Vec<String>,
     result: Option<T>,
+    #[serde(default)]
+    result_info: Option<ResultInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResultInfo {
+    page: u32,
+    total_pages: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,220 +89,554 @@ struct CloudflareError {
 }
 
 #[derive(Debug, Deserialize)]
-struct DnsRecord {
-    id: String,
+pub struct DnsRecord {
+    pub id: String,
+    /// Zone the record was found in; filled in by the client.
+    #[serde(skip)]
+    pub zone_id: String,
     #[allow(dead_code)]
     #[serde(rename = "type")]
     record_type: String,
-    #[allow(dead_code)]
-    name: String,
-    #[allow(dead_code)]
-    content: String,
+    pub name: String,
+    pub content: String,
     #[allow(dead_code)]
     ttl: u32,
+    #[serde(default)]
+    proxied: bool,
+    #[serde(default)]
+    comment: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
-struct ListRecordsResult {
-    result: Vec<DnsRecord>,
+struct DeletedRecord {
+    #[allow(dead_code)]
+    id: String,
 }
 
 impl CloudflareClient {
-    pub fn new(api_token: String, zone_id: String, ttl: u32) -> Self {
+    pub fn new(config: &CloudflareConfig) -> Self {
         Self {
-            client: Client::new(),
-            api_token,
-            zone_id,
-            ttl,
+            client: http_client(),
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            api_token: config.api_token.clone(),
+            zone_id: config.zone_id.clone(),
+            zone_names: config.zones.clone(),
+            auto_zones: config.auto_zones,
+            ttl: config.ttl,
+            ownership: config.ownership.clone(),
+            zones: Arc::new(OnceCell::new()),
         }
     }
 
-    pub async fn update_aaaa_record(&self, hostname: &str, ipv6_address: Ipv6Addr) -> Result<()> {
-        info!("Updating AAAA record for {} to {}", hostname, ipv6_address);
+    /// Creates or updates the A or AAAA record for `host`, depending on the
+    /// address family. With an `uplink`, the AAAA record labelled with that
+    /// uplink is updated, so that a host can have one record per uplink.
+    pub async fn update_address_record(
+        &self,
+        host: &HostMapping,
+        address: IpAddr,
+        uplink: Option<&str>,
+    ) -> Result<()> {
+        let hostname = host.hostname.as_str();
+        let record_type = RecordType::for_address(address);
+        info!(
+            "Updating {} record for {} to {}",
+            record_type, hostname, address
+        );
 
-        let existing_record = self.find_aaaa_record(hostname).await?;
+        let zone = self.zone_for(hostname).await?;
+        debug!("Using zone {} ({}) for {}", zone.name, zone.id, hostname);
+
+        let records = self.find_records(&zone, record_type, hostname).await?;
+        let existing_record = match uplink {
+            None => records.into_iter().next(),
+            Some(uplink) => {
+                // Prefer the uplink's own record, then adopt an unlabelled one.
+                let position = records
+                    .iter()
+                    .position(|record| self.uplink_of(record) == Some(uplink))
+                    .or_else(|| {
+                        records
+                            .iter()
+                            .position(|record| self.uplink_of(record).is_none())
+                    });
+                position.map(|position| records.into_iter().nth(position).unwrap())
+            }
+        };
 
         match existing_record {
             Some(record) => {
                 debug!("Found existing record with ID: {}", record.id);
-                self.update_record(&record.id, hostname, ipv6_address)
-                    .await?;
+                if let Some(ownership) = &self.ownership {
+                    if !is_owned(&record, ownership) {
+                        if !ownership.force {
+                            return Err(Ddns6Error::RecordNotOwned(format!(
+                                "{} (record {} lacks marker '{}')",
+                                hostname,
+                                record.id,
+                                ownership.marker()
+                            )));
+                        }
+                        warn!(
+                            "Taking over unmarked {} record {} for {} (force enabled)",
+                            record_type, record.id, hostname
+                        );
+                    }
+                }
+                self.update_record(
+                    &zone,
+                    &record,
+                    host,
+                    record_type,
+                    hostname,
+                    &address.to_string(),
+                    uplink,
+                )
+                .await?;
             }
             None => {
                 debug!("No existing record found, creating new one");
-                self.create_record(hostname, ipv6_address).await?;
+                self.create_record(
+                    &zone,
+                    host,
+                    record_type,
+                    hostname,
+                    &address.to_string(),
+                    uplink,
+                )
+                .await?;
             }
         }
 
-        info!("Successfully updated AAAA record for {}", hostname);
+        info!(
+            "Successfully updated {} record for {}",
+            record_type, hostname
+        );
         Ok(())
     }
 
-    async fn find_aaaa_record(&self, hostname: &str) -> Result<Option<DnsRecord>> {
-        let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records?type=AAAA&name={}",
-            self.zone_id, hostname
-        );
-
-        debug!("Searching for existing AAAA record: {}", url);
+    /// Deletes the AAAA record of `host` labelled with `uplink`. Returns
+    /// whether a record was deleted.
+    pub async fn withdraw_address_record(&self, host: &HostMapping, uplink: &str) -> Result<bool> {
+        let zone = self.zone_for(&host.hostname).await?;
+        let records = self
+            .find_records(&zone, RecordType::Aaaa, &host.hostname)
+            .await?;
 
-        let response = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.api_token)
-            .send()
+        let mut withdrawn = false;
+        for record in records {
+            if self.uplink_of(&record) != Some(uplink) {
+                continue;
+            }
+            info!(
+                "Withdrawing AAAA record {} for {} ({}) from uplink {}",
+                record.id, host.hostname, record.content, uplink
+            );
+            self.delete_record(&DnsRecord {
+                zone_id: zone.id.clone(),
+                ..record
+            })
             .await?;
+            withdrawn = true;
+        }
 
-        let status = response.status();
-        let body = response.text().await?;
+        Ok(withdrawn)
+    }
 
-        if !status.is_success() {
-            error!("Cloudflare API error (status {}): {}", status, body);
-            return Err(Ddns6Error::CloudflareApi(format!(
-                "Failed to list records: {} - {}",
-                status, body
-            )));
+    /// Points the PTR record of `address` at `host` and deletes the PTR
+    /// records published for other addresses of the host on the same uplink,
+    /// e.g. in the previous prefix.
+    pub async fn update_ptr_record(
+        &self,
+        host: &HostMapping,
+        address: Ipv6Addr,
+        uplink: Option<&str>,
+    ) -> Result<()> {
+        let name = reverse_name(address);
+        let zone = self.reverse_zone_for(&name).await?;
+        debug!("Using zone {} ({}) for {}", zone.name, zone.id, name);
+
+        let records = self.find_records(&zone, RecordType::Ptr, &name).await?;
+        match records.into_iter().next() {
+            Some(record)
+                if normalize_hostname(&record.content).is_ok_and(|name| name == host.hostname) =>
+            {
+                debug!("PTR record {} already points to {}", name, host.hostname);
+            }
+            Some(record) => {
+                if let Some(ownership) = &self.ownership {
+                    if !is_owned(&record, ownership) && !ownership.force {
+                        return Err(Ddns6Error::RecordNotOwned(format!(
+                            "{} (PTR record {} lacks marker '{}')",
+                            name,
+                            record.id,
+                            ownership.marker()
+                        )));
+                    }
+                }
+                info!("Pointing PTR record {} to {}", name, host.hostname);
+                self.update_record(
+                    &zone,
+                    &record,
+                    host,
+                    RecordType::Ptr,
+                    &name,
+                    &host.hostname,
+                    uplink,
+                )
+                .await?;
+            }
+            None => {
+                info!("Creating PTR record {} -> {}", name, host.hostname);
+                self.create_record(&zone, host, RecordType::Ptr, &name, &host.hostname, uplink)
+                    .await?;
+            }
         }
 
-        let list_response: CloudflareResponse<Vec<DnsRecord>> = serde_json::from_str(&body)
-            .map_err(|e| {
-                error!(
-                    "Failed to parse Cloudflare response: {} - Body: {}",
-                    e, body
-                );
-                Ddns6Error::CloudflareApi(format!("Failed to parse response: {}", e))
-            })?;
-
-        if !list_response.success {
-            let error_msg = list_response
-                .errors
+        self.delete_ptr_records(host, Some(&name), uplink).await?;
+        Ok(())
+    }
+
+    /// Deletes the marked PTR records pointing at `host` that were published
+    /// for `uplink`, except the one named `keep`. Returns how many were deleted.
+    pub async fn delete_ptr_records(
+        &self,
+        host: &HostMapping,
+        keep: Option<&str>,
+        uplink: Option<&str>,
+    ) -> Result<usize> {
+        let Some(ownership) = &self.ownership else {
+            return Ok(0);
+        };
+
+        let mut deleted = 0;
+        for zone in self.reverse_zones().await? {
+            let url = format!(
+                "{}/zones/{}/dns_records?type=PTR&content={}&per_page=100",
+                self.api_url, zone.id, host.hostname
+            );
+            let records: Vec<DnsRecord> = self.get_all_pages(&url, "list records").await?;
+
+            for record in records {
+                if Some(record.name.as_str()) == keep
+                    || !is_owned(&record, ownership)
+                    || self.uplink_of(&record) != uplink
+                {
+                    continue;
+                }
+                info!("Deleting PTR record {} -> {}", record.name, record.content);
+                self.delete_record(&DnsRecord {
+                    zone_id: zone.id.clone(),
+                    ..record
+                })
+                .await?;
+                deleted += 1;
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Returns the uplink a record was published for, taken from its
+    /// `uplink=` comment word or `uplink:` tag.
+    pub fn uplink_of<'a>(&self, record: &'a DnsRecord) -> Option<&'a str> {
+        match self.ownership.as_ref()?.location {
+            MarkerLocation::Comment => record
+                .comment
+                .as_deref()?
+                .split_whitespace()
+                .find_map(|word| word.strip_prefix("uplink=")),
+            MarkerLocation::Tag => record
+                .tags
                 .iter()
-                .map(|e| format!("{}: {}", e.code, e.message))
-                .collect::<Vec<_>>()
-                .join(", ");
-            return Err(Ddns6Error::CloudflareApi(format!(
-                "Cloudflare API returned errors: {}",
-                error_msg
-            )));
+                .find_map(|tag| tag.strip_prefix("uplink:")),
+        }
+    }
+
+    /// Whether records are marked, so that the ones created by ddns6 can be
+    /// listed.
+    pub fn marks_records(&self) -> bool {
+        self.ownership.is_some()
+    }
+
+    /// Lists all records of the given type that carry the ownership marker,
+    /// across all zones.
+    pub async fn list_managed_records(&self, record_type: &str) -> Result<Vec<DnsRecord>> {
+        let ownership = self.ownership.as_ref().ok_or_else(|| {
+            Ddns6Error::Config("Listing managed records requires cloudflare.ownership".to_string())
+        })?;
+
+        let mut records = Vec::new();
+
+        for zone in self.zones().await? {
+            let url = format!(
+                "{}/zones/{}/dns_records?type={}&per_page=100",
+                self.api_url, zone.id, record_type
+            );
+
+            debug!("Listing {} records: {}", record_type, url);
+
+            let zone_records: Vec<DnsRecord> = self.get_all_pages(&url, "list records").await?;
+            records.extend(
+                zone_records
+                    .into_iter()
+                    .filter(|record| is_owned(record, ownership))
+                    .map(|record| DnsRecord {
+                        zone_id: zone.id.clone(),
+                        ..record
+                    }),
+            );
         }
 
-        Ok(list_response
-            .result
-            .and_then(|records: Vec<DnsRecord>| records.into_iter().next()))
+        Ok(records)
     }
 
-    async fn create_record(&self, hostname: &str, ipv6_address: Ipv6Addr) -> Result<()> {
+    pub async fn delete_record(&self, record: &DnsRecord) -> Result<()> {
         let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-            self.zone_id
+            "{}/zones/{}/dns_records/{}",
+            self.api_url, record.zone_id, record.id
         );
 
-        let request = CreateRecordRequest {
-            record_type: "AAAA".to_string(),
-            name: hostname.to_string(),
-            content: ipv6_address.to_string(),
-            ttl: self.ttl,
-            proxied: false,
-        };
-
-        debug!("Creating new AAAA record: {:?}", request);
+        debug!("Deleting record {}", record.id);
 
-        let response = self
-            .client
-            .post(&url)
-            .bearer_auth(&self.api_token)
-            .json(&request)
-            .send()
+        let _: CloudflareResponse<DeletedRecord> = self
+            .execute(self.client.delete(&url), "delete record")
             .await?;
 
-        let status = response.status();
-        let body = response.text().await?;
+        Ok(())
+    }
 
-        if !status.is_success() {
-            error!("Cloudflare API error (status {}): {}", status, body);
-            return Err(Ddns6Error::CloudflareApi(format!(
-                "Failed to create record: {} - {}",
-                status, body
-            )));
+    /// Returns the configured zones, resolving zone names to IDs on first use.
+    async fn zones(&self) -> Result<&[Zone]> {
+        self.zones
+            .get_or_try_init(|| self.resolve_zones())
+            .await
+            .map(Vec::as_slice)
+    }
+
+    async fn resolve_zones(&self) -> Result<Vec<Zone>> {
+        let mut zones = Vec::new();
+
+        if !self.zone_id.is_empty() {
+            zones.push(Zone {
+                id: self.zone_id.clone(),
+                name: String::new(),
+            });
         }
 
-        let create_response: CloudflareResponse<DnsRecord> =
-            serde_json::from_str(&body).map_err(|e| {
-                error!(
-                    "Failed to parse Cloudflare response: {} - Body: {}",
-                    e, body
-                );
-                Ddns6Error::CloudflareApi(format!("Failed to parse response: {}", e))
-            })?;
-
-        if !create_response.success {
-            let error_msg = create_response
-                .errors
-                .iter()
-                .map(|e| format!("{}: {}", e.code, e.message))
-                .collect::<Vec<_>>()
-                .join(", ");
-            return Err(Ddns6Error::CloudflareApi(format!(
-                "Cloudflare API returned errors: {}",
-                error_msg
-            )));
+        for name in &self.zone_names {
+            let url = format!("{}/zones?name={}", self.api_url, name);
+
+            let response: CloudflareResponse<Vec<Zone>> =
+                self.execute(self.client.get(&url), "look up zone").await?;
+
+            let zone = response
+                .result
+                .unwrap_or_default()
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    Ddns6Error::ZoneNotFound(format!("No Cloudflare zone named {}", name))
+                })?;
+
+            info!("Resolved zone {} to ID {}", zone.name, zone.id);
+            zones.push(zone);
+        }
+
+        if self.auto_zones {
+            let url = format!("{}/zones?per_page=50", self.api_url);
+            let accessible: Vec<Zone> = self.get_all_pages(&url, "list zones").await?;
+
+            for zone in accessible {
+                if zones.iter().all(|z| z.id != zone.id) {
+                    info!("Discovered zone {} ({})", zone.name, zone.id);
+                    zones.push(zone);
+                }
+            }
         }
 
+        Ok(zones)
+    }
+
+    /// The configured `ip6.arpa` zones.
+    async fn reverse_zones(&self) -> Result<Vec<Zone>> {
+        Ok(self
+            .zones()
+            .await?
+            .iter()
+            .filter(|zone| is_reverse_zone(&zone.name))
+            .cloned()
+            .collect())
+    }
+
+    /// Like `zone_for`, but never falls back to the zone given by `zone_id`,
+    /// which holds the forward records.
+    async fn reverse_zone_for(&self, name: &str) -> Result<Zone> {
+        find_zone(&self.reverse_zones().await?, name)
+            .cloned()
+            .ok_or_else(|| {
+                Ddns6Error::ZoneNotFound(format!("No configured ip6.arpa zone contains {}", name))
+            })
+    }
+
+    async fn zone_for(&self, hostname: &str) -> Result<Zone> {
+        find_zone(self.zones().await?, hostname)
+            .cloned()
+            .ok_or_else(|| {
+                Ddns6Error::ZoneNotFound(format!("No configured zone contains {}", hostname))
+            })
+    }
+
+    async fn find_records(
+        &self,
+        zone: &Zone,
+        record_type: RecordType,
+        hostname: &str,
+    ) -> Result<Vec<DnsRecord>> {
+        let url = format!(
+            "{}/zones/{}/dns_records?type={}&name={}",
+            self.api_url, zone.id, record_type, hostname
+        );
+
+        debug!("Searching for existing {} record: {}", record_type, url);
+
+        let list_response: CloudflareResponse<Vec<DnsRecord>> =
+            self.execute(self.client.get(&url), "list records").await?;
+
+        Ok(list_response.result.unwrap_or_default())
+    }
+
+    /// Creates a record of `host`: its A or AAAA record named after the host,
+    /// or a PTR record named `name` pointing to it.
+    async fn create_record(
+        &self,
+        zone: &Zone,
+        host: &HostMapping,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+        uplink: Option<&str>,
+    ) -> Result<()> {
+        let url = format!("{}/zones/{}/dns_records", self.api_url, zone.id);
+
+        let proxied = record_type != RecordType::Ptr && host.proxied.unwrap_or(false);
+        let request = CreateRecordRequest {
+            record_type: record_type.to_string(),
+            name: name.to_string(),
+            content: content.to_string(),
+            ttl: self.record_ttl(host, proxied),
+            proxied,
+            comment: self.record_comment(host, record_type, None, uplink),
+            tags: self
+                .record_tags(host, record_type, &[], uplink)
+                .unwrap_or_default(),
+        };
+
+        debug!("Creating new {} record: {:?}", record_type, request);
+
+        let _: CloudflareResponse<DnsRecord> = self
+            .execute(self.client.post(&url).json(&request), "create record")
+            .await?;
+
         Ok(())
     }
 
+    /// Updates the content of an existing record. Settings that are not
+    /// configured for the host, such as `proxied` and the comment, are carried
+    /// over from the existing record.
+    #[allow(clippy::too_many_arguments)]
     async fn update_record(
         &self,
-        record_id: &str,
-        hostname: &str,
-        ipv6_address: Ipv6Addr,
+        zone: &Zone,
+        record: &DnsRecord,
+        host: &HostMapping,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+        uplink: Option<&str>,
     ) -> Result<()> {
         let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records/{}",
-            self.zone_id, record_id
+            "{}/zones/{}/dns_records/{}",
+            self.api_url, zone.id, record.id
         );
 
+        let proxied = record_type != RecordType::Ptr && host.proxied.unwrap_or(record.proxied);
         let request = UpdateRecordRequest {
-            record_type: "AAAA".to_string(),
-            name: hostname.to_string(),
-            content: ipv6_address.to_string(),
-            ttl: self.ttl,
-            proxied: false,
+            record_type: record_type.to_string(),
+            name: name.to_string(),
+            content: content.to_string(),
+            ttl: self.record_ttl(host, proxied),
+            proxied,
+            comment: self.record_comment(host, record_type, record.comment.as_deref(), uplink),
+            tags: self.record_tags(host, record_type, &record.tags, uplink),
         };
 
-        debug!("Updating AAAA record {}: {:?}", record_id, request);
+        debug!(
+            "Updating {} record {}: {:?}",
+            record_type, record.id, request
+        );
 
-        let response = self
-            .client
-            .put(&url)
-            .bearer_auth(&self.api_token)
-            .json(&request)
-            .send()
+        let _: CloudflareResponse<DnsRecord> = self
+            .execute(self.client.patch(&url).json(&request), "update record")
             .await?;
 
+        Ok(())
+    }
+
+    /// Fetches every page of a paginated list endpoint. `url` must already
+    /// contain a query string.
+    async fn get_all_pages<T: DeserializeOwned>(&self, url: &str, action: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let page_url = format!("{}&page={}", url, page);
+            let response: CloudflareResponse<Vec<T>> =
+                self.execute(self.client.get(&page_url), action).await?;
+
+            items.extend(response.result.unwrap_or_default());
+
+            match response.result_info {
+                Some(info) if info.page < info.total_pages => page = info.page + 1,
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Sends an authenticated request and checks both the HTTP status and the
+    /// `success` flag of the Cloudflare response envelope.
+    async fn execute<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        action: &str,
+    ) -> Result<CloudflareResponse<T>> {
+        let response = request.bearer_auth(self.api_token.expose()).send().await?;
+
         let status = response.status();
         let body = response.text().await?;
 
         if !status.is_success() {
             error!("Cloudflare API error (status {}): {}", status, body);
             return Err(Ddns6Error::CloudflareApi(format!(
-                "Failed to update record: {} - {}",
-                status, body
+                "Failed to {}: {} - {}",
+                action, status, body
             )));
         }
 
-        let update_response: CloudflareResponse<DnsRecord> =
-            serde_json::from_str(&body).map_err(|e| {
-                error!(
-                    "Failed to parse Cloudflare response: {} - Body: {}",
-                    e, body
-                );
-                Ddns6Error::CloudflareApi(format!("Failed to parse response: {}", e))
-            })?;
-
-        if !update_response.success {
-            let error_msg = update_response
+        let parsed: CloudflareResponse<T> = serde_json::from_str(&body).map_err(|e| {
+            error!(
+                "Failed to parse Cloudflare response: {} - Body: {}",
+                e, body
+            );
+            Ddns6Error::CloudflareApi(format!("Failed to parse response: {}", e))
+        })?;
+
+        if !parsed.success {
+            let error_msg = parsed
                 .errors
                 .iter()
                 .map(|e| format!("{}: {}", e.code, e.message))
@@ -274,6 +648,364 @@ impl CloudflareClient {
             )));
         }
 
-        Ok(())
+        Ok(parsed)
+    }
+
+    /// Proxied records always use Cloudflare's automatic TTL.
+    fn record_ttl(&self, host: &HostMapping, proxied: bool) -> u32 {
+        if proxied {
+            1
+        } else {
+            host.ttl.unwrap_or(self.ttl)
+        }
+    }
+
+    /// Builds the record comment from the host's comment (or the existing
+    /// comment when the host has none) and, in comment mode, the ownership
+    /// marker. AAAA records also record the interface ID, AAAA and PTR
+    /// records the uplink. Returns `None` when there is nothing to set.
+    fn record_comment(
+        &self,
+        host: &HostMapping,
+        record_type: RecordType,
+        existing: Option<&str>,
+        uplink: Option<&str>,
+    ) -> Option<String> {
+        let base = host.comment.as_deref().or(existing);
+
+        let ownership = match &self.ownership {
+            Some(ownership) if ownership.location == MarkerLocation::Comment => ownership,
+            _ => return base.map(str::to_string),
+        };
+
+        let marker = ownership.marker();
+        let mut words: Vec<&str> = base
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter(|word| {
+                *word != marker && !word.starts_with("iid=") && !word.starts_with("uplink=")
+            })
+            .collect();
+        let iid = format!("iid={}", host.interface_id);
+        let uplink = uplink.map(|uplink| format!("uplink={}", uplink));
+        words.push(marker);
+        if record_type == RecordType::Aaaa && !host.interface_id.is_empty() {
+            words.push(&iid);
+        }
+        if record_type != RecordType::A {
+            if let Some(uplink) = &uplink {
+                words.push(uplink);
+            }
+        }
+
+        Some(words.join(" "))
+    }
+
+    /// Builds the record tags in tag mode, keeping unrelated existing tags.
+    /// Returns `None` when the tags should be left untouched.
+    fn record_tags(
+        &self,
+        host: &HostMapping,
+        record_type: RecordType,
+        existing: &[String],
+        uplink: Option<&str>,
+    ) -> Option<Vec<String>> {
+        let ownership = match &self.ownership {
+            Some(ownership) if ownership.location == MarkerLocation::Tag => ownership,
+            _ => return None,
+        };
+
+        let marker = ownership.marker();
+        let mut tags: Vec<String> = existing
+            .iter()
+            .filter(|tag| *tag != marker && !tag.starts_with("iid:") && !tag.starts_with("uplink:"))
+            .cloned()
+            .collect();
+        tags.push(marker.to_string());
+        if record_type == RecordType::Aaaa && !host.interface_id.is_empty() {
+            tags.push(format!("iid:{}", host.interface_id));
+        }
+        if record_type != RecordType::A {
+            if let Some(uplink) = uplink {
+                tags.push(format!("uplink:{}", uplink));
+            }
+        }
+
+        Some(tags)
+    }
+}
+
+/// Picks the zone with the longest name that contains `hostname`. A zone with
+/// an empty name, given by `zone_id`, matches every hostname.
+fn find_zone<'a>(zones: &'a [Zone], hostname: &str) -> Option<&'a Zone> {
+    longest_zone(zones, hostname, |zone| zone.name.as_str())
+        .or_else(|| zones.iter().find(|zone| zone.name.is_empty()))
+}
+
+fn is_owned(record: &DnsRecord, ownership: &OwnershipConfig) -> bool {
+    let marker = ownership.marker();
+    match ownership.location {
+        MarkerLocation::Comment => record
+            .comment
+            .as_deref()
+            .is_some_and(|comment| comment.split_whitespace().any(|word| word == marker)),
+        MarkerLocation::Tag => record.tags.iter().any(|tag| tag == marker),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(comment: Option<&str>, tags: &[&str]) -> DnsRecord {
+        DnsRecord {
+            id: "record-id".to_string(),
+            zone_id: String::new(),
+            record_type: "AAAA".to_string(),
+            name: "device1.example.com".to_string(),
+            content: "2001:db8::1".to_string(),
+            ttl: 300,
+            proxied: false,
+            comment: comment.map(str::to_string),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_is_owned_comment() {
+        let ownership = OwnershipConfig {
+            marker: None,
+            location: MarkerLocation::Comment,
+            force: false,
+        };
+
+        assert!(is_owned(
+            &record(Some("managed-by=ddns6 iid=::10"), &[]),
+            &ownership
+        ));
+        assert!(!is_owned(&record(Some("hand-managed"), &[]), &ownership));
+        assert!(!is_owned(
+            &record(Some("not-managed-by=ddns6x"), &[]),
+            &ownership
+        ));
+        assert!(!is_owned(&record(None, &[]), &ownership));
+    }
+
+    #[test]
+    fn test_is_owned_tag() {
+        let ownership = OwnershipConfig {
+            marker: None,
+            location: MarkerLocation::Tag,
+            force: false,
+        };
+
+        assert!(is_owned(
+            &record(None, &["managed-by:ddns6", "iid:::10"]),
+            &ownership
+        ));
+        assert!(!is_owned(
+            &record(Some("managed-by:ddns6"), &[]),
+            &ownership
+        ));
+    }
+
+    fn host(comment: Option<&str>) -> HostMapping {
+        HostMapping {
+            hostname: "device1.example.com".to_string(),
+            interface_id: "::10".to_string(),
+            ttl: None,
+            proxied: None,
+            comment: comment.map(str::to_string),
+            ipv4: false,
+            mac: None,
+            stable_privacy: None,
+            views: Vec::new(),
+            providers: Vec::new(),
+        }
+    }
+
+    fn test_client(location: Option<MarkerLocation>) -> CloudflareClient {
+        CloudflareClient::new(&CloudflareConfig {
+            api_url: "https://api.example.com/client/v4".to_string(),
+            api_token: "token".into(),
+            api_token_file: None,
+            zone_id: "zone".to_string(),
+            zones: Vec::new(),
+            auto_zones: false,
+            ttl: 300,
+            ownership: location.map(|location| OwnershipConfig {
+                marker: None,
+                location,
+                force: false,
+            }),
+        })
+    }
+
+    #[test]
+    fn test_record_comment() {
+        let client = test_client(Some(MarkerLocation::Comment));
+        assert_eq!(
+            client
+                .record_comment(&host(None), RecordType::Aaaa, None, None)
+                .as_deref(),
+            Some("managed-by=ddns6 iid=::10")
+        );
+        assert_eq!(
+            client
+                .record_comment(&host(Some("NAS")), RecordType::Aaaa, None, None)
+                .as_deref(),
+            Some("NAS managed-by=ddns6 iid=::10")
+        );
+        assert_eq!(
+            client
+                .record_comment(
+                    &host(None),
+                    RecordType::Aaaa,
+                    Some("NAS managed-by=ddns6 iid=::1"),
+                    None
+                )
+                .as_deref(),
+            Some("NAS managed-by=ddns6 iid=::10")
+        );
+
+        let client = test_client(None);
+        assert!(client
+            .record_comment(&host(None), RecordType::Aaaa, None, None)
+            .is_none());
+        assert_eq!(
+            client
+                .record_comment(&host(None), RecordType::Aaaa, Some("hand-written"), None)
+                .as_deref(),
+            Some("hand-written")
+        );
+        assert_eq!(
+            client
+                .record_comment(
+                    &host(Some("NAS")),
+                    RecordType::Aaaa,
+                    Some("hand-written"),
+                    None
+                )
+                .as_deref(),
+            Some("NAS")
+        );
+    }
+
+    #[test]
+    fn test_record_comment_a_record_has_no_iid() {
+        let client = test_client(Some(MarkerLocation::Comment));
+        assert_eq!(
+            client
+                .record_comment(&host(None), RecordType::A, None, None)
+                .as_deref(),
+            Some("managed-by=ddns6")
+        );
+
+        let client = test_client(Some(MarkerLocation::Tag));
+        assert_eq!(
+            client.record_tags(&host(None), RecordType::A, &[], None),
+            Some(vec!["managed-by:ddns6".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_record_tags() {
+        let client = test_client(Some(MarkerLocation::Tag));
+        let existing = vec!["team:infra".to_string(), "iid:::1".to_string()];
+        assert_eq!(
+            client.record_tags(&host(None), RecordType::Aaaa, &existing, None),
+            Some(vec![
+                "team:infra".to_string(),
+                "managed-by:ddns6".to_string(),
+                "iid:::10".to_string()
+            ])
+        );
+
+        assert!(test_client(None)
+            .record_tags(&host(None), RecordType::Aaaa, &existing, None)
+            .is_none());
+        assert!(test_client(Some(MarkerLocation::Comment))
+            .record_tags(&host(None), RecordType::Aaaa, &existing, None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_uplink_labels() {
+        let client = test_client(Some(MarkerLocation::Comment));
+        let comment = client
+            .record_comment(
+                &host(None),
+                RecordType::Aaaa,
+                Some("managed-by=ddns6 iid=::10 uplink=isp1"),
+                Some("isp2"),
+            )
+            .unwrap();
+        assert_eq!(comment, "managed-by=ddns6 iid=::10 uplink=isp2");
+        assert_eq!(client.uplink_of(&record(Some(&comment), &[])), Some("isp2"));
+        assert_eq!(
+            client.uplink_of(&record(Some("managed-by=ddns6"), &[])),
+            None
+        );
+
+        let client = test_client(Some(MarkerLocation::Tag));
+        let tags = client
+            .record_tags(&host(None), RecordType::Aaaa, &[], Some("isp1"))
+            .unwrap();
+        assert_eq!(tags, vec!["managed-by:ddns6", "iid:::10", "uplink:isp1"]);
+        let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+        assert_eq!(client.uplink_of(&record(None, &tags)), Some("isp1"));
+
+        assert_eq!(
+            client
+                .record_tags(&host(None), RecordType::A, &[], Some("isp1"))
+                .unwrap(),
+            vec!["managed-by:ddns6"]
+        );
+    }
+
+    #[test]
+    fn test_record_ttl() {
+        let client = test_client(None);
+        let mut host = host(None);
+        assert_eq!(client.record_ttl(&host, false), 300);
+        assert_eq!(client.record_ttl(&host, true), 1);
+
+        host.ttl = Some(60);
+        assert_eq!(client.record_ttl(&host, false), 60);
+    }
+
+    fn zone(id: &str, name: &str) -> Zone {
+        Zone {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_zone_longest_match() {
+        let zones = vec![
+            zone("com", "example.com"),
+            zone("home", "home.example.com"),
+            zone("net", "example.net"),
+        ];
+
+        let id = |hostname| find_zone(&zones, hostname).map(|z| z.id.as_str());
+
+        assert_eq!(id("nas.home.example.com"), Some("home"));
+        assert_eq!(id("home.example.com"), Some("home"));
+        assert_eq!(id("www.example.com"), Some("com"));
+        assert_eq!(id("WWW.Example.NET."), Some("net"));
+        assert_eq!(id("www.otherexample.com"), None);
+        assert_eq!(id("example.org"), None);
+    }
+
+    #[test]
+    fn test_find_zone_fallback() {
+        let zones = vec![zone("legacy", ""), zone("net", "example.net")];
+
+        let id = |hostname| find_zone(&zones, hostname).map(|z| z.id.as_str());
+
+        assert_eq!(id("www.example.net"), Some("net"));
+        assert_eq!(id("www.example.com"), Some("legacy"));
     }
 }
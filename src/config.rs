@@ -1,17 +1,222 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
-use std::net::Ipv6Addr;
-use std::path::Path;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 
 use crate::error::{Ddns6Error, Result};
+use crate::http_provider::parse_json_path;
+use crate::ipv6::{eui64_interface_id, parse_interface_id, Ipv6Prefix};
+
+/// A configuration value that is kept out of `Debug` output.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"<redacted>\"")
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub server: ServerConfig,
-    pub cloudflare: CloudflareConfig,
-    #[serde(rename = "hosts")]
+    /// Provider the records are published to; optional when `dns` serves them.
+    #[serde(default)]
+    pub cloudflare: Option<CloudflareConfig>,
+    /// Further DNS providers, referred to by name from `hosts.providers`.
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+    /// Embedded authoritative DNS server answering from the published state.
+    #[serde(default)]
+    pub dns: Option<DnsConfig>,
+    #[serde(rename = "hosts", default)]
     pub hosts: Vec<HostMapping>,
+    /// Hosts sharing a domain and record settings, expanded into `hosts`
+    /// when the configuration is loaded.
+    #[serde(default)]
+    pub host_groups: Vec<HostGroup>,
+    /// Files adding `[[hosts]]` and `[[host_groups]]`, relative to this file;
+    /// the file name may contain `*` and `?`.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub cleanup: CleanupConfig,
+    #[serde(default)]
+    pub debounce: DebounceConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub reverse: ReverseConfig,
+    /// Static prefixes in which hosts can publish additional addresses.
+    #[serde(default)]
+    pub views: Vec<ViewConfig>,
+    /// Named uplinks, each delegating its own prefix. When set, every update
+    /// names its uplink and hosts get one AAAA record per active uplink.
+    #[serde(default)]
+    pub uplinks: Vec<UplinkConfig>,
+    /// Endpoints notified with a JSON POST after an update changed something.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Commands run after an update changed the prefix or a record.
+    #[serde(default)]
+    pub on_change: Vec<HookConfig>,
+    /// Broker that receives the prefix and host state as retained messages.
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    /// Local files the published records are written to, e.g. for the LAN
+    /// resolver.
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HookConfig {
+    /// Shell command, run with `/bin/sh -c` and `DDNS6_*` environment variables.
+    pub command: String,
+    /// Run once per changed record instead of once per update.
+    #[serde(default)]
+    pub per_host: bool,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    pub path: String,
+    /// Shell command run after the file was replaced, e.g. `rndc reload`.
+    #[serde(default)]
+    pub reload_command: Option<String>,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+    /// TTL of the records in `zone` and `unbound` files.
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// RFC 1035 zone file fragment for a BIND or NSD `$INCLUDE`.
+    Zone,
+    /// `/etc/hosts` format, also read by dnsmasq's `addn-hosts`.
+    Hosts,
+    /// Unbound `local-data` include.
+    Unbound,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Key for the HMAC-SHA256 signature sent in `X-Ddns6-Signature`.
+    #[serde(default)]
+    pub secret: Option<Secret>,
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout_secs: u64,
+    /// Retries after a failed delivery, with exponential backoff.
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+    /// Delay before the first retry; doubled for every further retry.
+    #[serde(default = "default_webhook_retry_delay")]
+    pub retry_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret>,
+    #[serde(default)]
+    pub tls: bool,
+    /// PEM file with the CA certificate of the broker; the system roots are
+    /// used when unset.
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// State is published below `<topic_prefix>/prefix` and `<topic_prefix>/hosts`.
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    /// Topic on which an update can be requested, like a call to `/update`.
+    #[serde(default)]
+    pub command_topic: Option<String>,
+    #[serde(default = "default_mqtt_keep_alive")]
+    pub keep_alive_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DnsConfig {
+    /// UDP and TCP address to listen on, e.g. `127.0.0.1:5353`.
+    pub bind_address: String,
+    /// Zones answered authoritatively; queries for other names are refused.
+    pub zones: Vec<String>,
+    #[serde(default = "default_dns_ttl")]
+    pub ttl: u32,
+    /// Also answer A queries for hosts with `ipv4` set.
+    #[serde(default)]
+    pub a_records: bool,
+    /// Answer PTR queries in the `ip6.arpa` zones among `zones`.
+    #[serde(default)]
+    pub ptr_records: bool,
+    /// Name server in the SOA and NS records; `ns.<zone>` by default.
+    #[serde(default)]
+    pub nameserver: Option<String>,
+    /// SOA mailbox in DNS notation; `hostmaster.<zone>` by default.
+    #[serde(default)]
+    pub hostmaster: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UplinkConfig {
+    pub name: String,
+    /// Withdraw the uplink's addresses when no update arrived for this long,
+    /// e.g. the valid lifetime of the delegated prefix.
+    #[serde(default)]
+    pub prefix_lifetime_secs: Option<u64>,
+}
+
+/// A named static prefix, typically a ULA prefix for internal resolvers.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ViewConfig {
+    pub name: String,
+    /// Prefix in CIDR notation, e.g. `fd12:3456:789a:1::/64`.
+    pub prefix: String,
+}
+
+impl ViewConfig {
+    pub fn parsed_prefix(&self) -> Result<Ipv6Prefix> {
+        Ipv6Prefix::from_cidr(&self.prefix)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -19,322 +224,2979 @@ pub struct ServerConfig {
     pub bind_address: String,
     #[serde(default = "default_workers")]
     pub workers: usize,
+    /// Accept unique local (fc00::/7) prefixes in update requests.
+    #[serde(default)]
+    pub allow_ula_prefix: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CloudflareConfig {
-    pub api_token: String,
+    /// Base URL of the Cloudflare API, e.g. to route through an API gateway.
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    #[serde(default)]
+    pub api_token: Secret,
+    /// File holding the API token, e.g. from systemd `LoadCredential`;
+    /// replaces `api_token`.
+    #[serde(default)]
+    pub api_token_file: Option<String>,
+    /// Zone used for hosts that are not within any of `zones`.
+    #[serde(default)]
     pub zone_id: String,
+    /// Zones given by name, resolved to zone IDs through the Cloudflare API.
+    #[serde(default)]
+    pub zones: Vec<String>,
+    /// Also consider every zone the API token can access.
+    #[serde(default)]
+    pub auto_zones: bool,
     #[serde(default = "default_ttl")]
     pub ttl: u32,
+    #[serde(default)]
+    pub ownership: Option<OwnershipConfig>,
 }
 
+/// Name under which `[cloudflare]` is referred to in `hosts.providers`.
+pub const CLOUDFLARE_PROVIDER: &str = "cloudflare";
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct HostMapping {
-    pub hostname: String,
-    pub interface_id: String,
+pub struct ProviderConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ProviderKind,
 }
 
-fn default_workers() -> usize {
-    4
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// HTTP API of the PowerDNS Authoritative Server.
+    PowerDns(PowerDnsConfig),
+    /// Any HTTP API, driven by request templates.
+    Http(HttpProviderConfig),
 }
 
-fn default_ttl() -> u32 {
-    300
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PowerDnsConfig {
+    /// Base URL of the API, e.g. `http://127.0.0.1:8081`.
+    pub api_url: String,
+    pub api_key: Secret,
+    #[serde(default = "default_powerdns_server_id")]
+    pub server_id: String,
+    /// Zones hosts are published in; each host goes to the longest matching
+    /// one.
+    pub zones: Vec<String>,
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+    /// Replace record sets that lack the ddns6 comment instead of refusing.
+    #[serde(default)]
+    pub force: bool,
 }
 
-impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(path)
-            .map_err(|e| Ddns6Error::Config(format!("Failed to read config file: {}", e)))?;
+/// A provider calling an HTTP API with requests rendered from templates.
+/// Templates may use `{hostname}`, `{subname}` (relative to the zone, `@` at
+/// the apex), `{zone}`, `{type}`, `{address}`, `{addresses}` (a JSON array
+/// of the addresses on all uplinks) and `{ttl}`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpProviderConfig {
+    /// Zones hosts are published in; each host goes to the longest matching
+    /// one.
+    pub zones: Vec<String>,
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+    /// Request that publishes a record.
+    pub update: HttpRequestTemplate,
+    /// Request that deletes a record, sent when the last uplink of a host
+    /// went down. Without it, the record is left in place.
+    #[serde(default)]
+    pub delete: Option<HttpRequestTemplate>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpRequestTemplate {
+    #[serde(default = "default_http_method")]
+    pub method: String,
+    pub url: String,
+    /// Header values are treated as secrets and never logged.
+    #[serde(default)]
+    pub headers: BTreeMap<String, Secret>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Checks on the JSON response, in addition to a 2xx status.
+    #[serde(default)]
+    pub checks: Vec<ResponseCheck>,
+}
 
-        let config: Config = toml::from_str(&content)
-            .map_err(|e| Ddns6Error::Config(format!("Failed to parse config file: {}", e)))?;
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResponseCheck {
+    /// JSONPath-style location in the response, e.g. `$.records[0]`.
+    pub path: String,
+    /// Expected value; strings may use the template placeholders. Without
+    /// it, the value only has to be present and not null.
+    #[serde(default)]
+    pub equals: Option<serde_json::Value>,
+}
 
-        config.validate()?;
-        Ok(config)
+impl HttpRequestTemplate {
+    fn validate(&self, provider: &str) -> Result<()> {
+        reqwest::Method::from_bytes(self.method.as_bytes()).map_err(|_| {
+            Ddns6Error::Config(format!(
+                "Invalid method {:?} for provider {}",
+                self.method, provider
+            ))
+        })?;
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(Ddns6Error::Config(format!(
+                "url of provider {} must be an http(s) URL: {}",
+                provider, self.url
+            )));
+        }
+        for check in &self.checks {
+            parse_json_path(&check.path).map_err(|e| {
+                Ddns6Error::Config(format!(
+                    "Invalid check path {:?} for provider {}: {}",
+                    check.path, provider, e
+                ))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl ProviderConfig {
+    /// Zones the provider can publish hosts in, if it is limited to some.
+    fn zones(&self) -> Option<&Vec<String>> {
+        match &self.kind {
+            ProviderKind::PowerDns(powerdns) => Some(&powerdns.zones),
+            ProviderKind::Http(http) => Some(&http.zones),
+        }
     }
 
     fn validate(&self) -> Result<()> {
-        if self.server.bind_address.is_empty() {
+        if self.name.is_empty() {
             return Err(Ddns6Error::Config(
-                "bind_address cannot be empty".to_string(),
+                "provider name cannot be empty".to_string(),
             ));
         }
+        if self.name == CLOUDFLARE_PROVIDER {
+            return Err(Ddns6Error::Config(format!(
+                "Provider name {} is reserved for [cloudflare]",
+                CLOUDFLARE_PROVIDER
+            )));
+        }
+
+        match &self.kind {
+            ProviderKind::PowerDns(powerdns) => {
+                if !powerdns.api_url.starts_with("http://")
+                    && !powerdns.api_url.starts_with("https://")
+                {
+                    return Err(Ddns6Error::Config(format!(
+                        "api_url of provider {} must be an http(s) URL: {}",
+                        self.name, powerdns.api_url
+                    )));
+                }
+                if powerdns.api_key.is_empty() {
+                    return Err(Ddns6Error::Config(format!(
+                        "api_key of provider {} cannot be empty",
+                        self.name
+                    )));
+                }
+                if powerdns.server_id.is_empty() {
+                    return Err(Ddns6Error::Config(format!(
+                        "server_id of provider {} cannot be empty",
+                        self.name
+                    )));
+                }
+                self.validate_zones_and_ttl(&powerdns.zones, powerdns.ttl)?;
+            }
+            ProviderKind::Http(http) => {
+                self.validate_zones_and_ttl(&http.zones, http.ttl)?;
+                http.update.validate(&self.name)?;
+                if let Some(delete) = &http.delete {
+                    delete.validate(&self.name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_zones_and_ttl(&self, zones: &[String], ttl: u32) -> Result<()> {
+        if zones.is_empty() {
+            return Err(Ddns6Error::Config(format!(
+                "Provider {} must list at least one zone",
+                self.name
+            )));
+        }
+        for zone in zones {
+            validate_hostname(zone)
+                .map_err(|e| Ddns6Error::Config(format!("Invalid zone name {}: {}", zone, e)))?;
+        }
+        if ttl == 0 || ttl > MAX_TTL {
+            return Err(Ddns6Error::Config(format!(
+                "ttl of provider {} must be between 1 and {}",
+                self.name, MAX_TTL
+            )));
+        }
+        Ok(())
+    }
+}
 
-        if self.cloudflare.api_token.is_empty() {
+impl CloudflareConfig {
+    fn validate(&self) -> Result<()> {
+        if !self.api_url.starts_with("http://") && !self.api_url.starts_with("https://") {
+            return Err(Ddns6Error::Config(format!(
+                "cloudflare.api_url must be an http(s) URL: {}",
+                self.api_url
+            )));
+        }
+
+        if self.api_token.is_empty() {
             return Err(Ddns6Error::Config(
-                "cloudflare.api_token cannot be empty".to_string(),
+                "cloudflare.api_token or cloudflare.api_token_file must be set".to_string(),
             ));
         }
 
-        if self.cloudflare.zone_id.is_empty() {
+        if self.zone_id.is_empty() && self.zones.is_empty() && !self.auto_zones {
             return Err(Ddns6Error::Config(
-                "cloudflare.zone_id cannot be empty".to_string(),
+                "cloudflare.zone_id cannot be empty unless cloudflare.zones or cloudflare.auto_zones is set"
+                    .to_string(),
             ));
         }
 
-        if self.hosts.is_empty() {
+        if self.zones.iter().any(|zone| zone.is_empty()) {
             return Err(Ddns6Error::Config(
-                "At least one host mapping must be configured".to_string(),
+                "cloudflare.zones cannot contain empty zone names".to_string(),
             ));
         }
+        for zone in &self.zones {
+            validate_hostname(zone)
+                .map_err(|e| Ddns6Error::Config(format!("Invalid zone name {}: {}", zone, e)))?;
+        }
 
-        for host in &self.hosts {
-            if host.hostname.is_empty() {
-                return Err(Ddns6Error::Config("hostname cannot be empty".to_string()));
+        validate_ttl(self.ttl, "cloudflare.ttl")?;
+
+        if let Some(ownership) = &self.ownership {
+            let marker = ownership.marker();
+            if marker.is_empty() || marker.contains(char::is_whitespace) {
+                return Err(Ddns6Error::Config(
+                    "cloudflare.ownership.marker must be a single non-empty word".to_string(),
+                ));
+            }
+            if ownership.location == MarkerLocation::Tag && !marker.contains(':') {
+                return Err(Ddns6Error::Config(format!(
+                    "cloudflare.ownership.marker must use the name:value form for tags: {}",
+                    marker
+                )));
             }
+        }
+
+        Ok(())
+    }
+}
+
+impl DnsConfig {
+    fn validate(&self) -> Result<()> {
+        self.bind_address.parse::<SocketAddr>().map_err(|e| {
+            Ddns6Error::Config(format!(
+                "dns.bind_address must be an address and port: {}: {}",
+                self.bind_address, e
+            ))
+        })?;
+
+        if self.zones.is_empty() {
+            return Err(Ddns6Error::Config(
+                "dns.zones must list at least one zone".to_string(),
+            ));
+        }
+        for zone in &self.zones {
+            validate_hostname(zone)
+                .map_err(|e| Ddns6Error::Config(format!("Invalid zone name {}: {}", zone, e)))?;
+        }
 
-            self.validate_interface_id(&host.interface_id)?;
+        if self.ttl > MAX_TTL {
+            return Err(Ddns6Error::Config(format!(
+                "dns.ttl must be at most {}, got {}",
+                MAX_TTL, self.ttl
+            )));
         }
 
-        let mut seen_hostnames = HashMap::new();
-        for host in &self.hosts {
-            if seen_hostnames.contains_key(&host.hostname) {
-                return Err(Ddns6Error::Config(format!(
-                    "Duplicate hostname: {}",
-                    host.hostname
-                )));
+        if self.ptr_records && !self.zones.iter().any(|zone| is_reverse_zone(zone)) {
+            return Err(Ddns6Error::Config(
+                "dns.ptr_records requires an ip6.arpa zone in dns.zones".to_string(),
+            ));
+        }
+
+        let names = [
+            ("dns.nameserver", &self.nameserver),
+            ("dns.hostmaster", &self.hostmaster),
+        ];
+        for (field, name) in names {
+            if let Some(name) = name {
+                validate_hostname(name).map_err(|e| {
+                    Ddns6Error::Config(format!("Invalid {} {}: {}", field, name, e))
+                })?;
             }
-            seen_hostnames.insert(host.hostname.clone(), ());
         }
 
         Ok(())
     }
+}
 
-    fn validate_interface_id(&self, iid: &str) -> Result<()> {
-        if iid.parse::<Ipv6Addr>().is_ok() {
-            return Ok(());
+/// Marks records created by ddns6 so that records managed by hand are never
+/// overwritten by accident.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OwnershipConfig {
+    #[serde(default)]
+    pub marker: Option<String>,
+    #[serde(default)]
+    pub location: MarkerLocation,
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerLocation {
+    #[default]
+    Comment,
+    Tag,
+}
+
+impl OwnershipConfig {
+    /// Returns the configured marker, or the default for the marker location.
+    /// Cloudflare tags must use the `name:value` form.
+    pub fn marker(&self) -> &str {
+        match (&self.marker, self.location) {
+            (Some(marker), _) => marker,
+            (None, MarkerLocation::Comment) => "managed-by=ddns6",
+            (None, MarkerLocation::Tag) => "managed-by:ddns6",
         }
+    }
+}
 
-        let test_addr = format!("2001:db8::{}", iid);
-        if test_addr.parse::<Ipv6Addr>().is_ok() {
-            return Ok(());
+/// Opt-in removal of records for hosts that were removed from the configuration.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CleanupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Repeat the cleanup pass at this interval; by default it only runs at startup.
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+}
+
+/// Protection against prefixes that change several times in a short period,
+/// e.g. during ISP maintenance.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DebounceConfig {
+    /// Publish a new prefix only after it was reported unchanged for this
+    /// long; 0 publishes right away.
+    #[serde(default)]
+    pub settle_secs: u64,
+    /// Warn when the prefix of an uplink changes more often than this within
+    /// an hour.
+    #[serde(default)]
+    pub flap_threshold: Option<usize>,
+}
+
+/// PTR records for the dynamic address of every host, published in the
+/// `ip6.arpa` zone of the delegated prefix.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReverseConfig {
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Retention of the prefix history served by `/api/v1/history`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryConfig {
+    #[serde(default = "default_history_entries")]
+    pub max_entries: usize,
+    /// Forget prefixes last seen longer ago than this.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: default_history_entries(),
+            max_age_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostMapping {
+    pub hostname: String,
+    /// Static Interface ID. May be omitted when `mac` is given.
+    #[serde(default)]
+    pub interface_id: String,
+    /// MAC address from which the modified EUI-64 Interface ID is derived,
+    /// or the hardware address used for stable-privacy Interface IDs.
+    #[serde(default)]
+    pub mac: Option<String>,
+    /// Compute the Interface ID per prefix as Linux does with
+    /// `addr_gen_mode = stable_privacy` (RFC 7217).
+    #[serde(default)]
+    pub stable_privacy: Option<StablePrivacyConfig>,
+    /// Overrides `cloudflare.ttl` for this host.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    /// Proxy traffic through Cloudflare. When unset, existing records keep
+    /// their current setting and new records are not proxied.
+    #[serde(default)]
+    pub proxied: Option<bool>,
+    /// Record comment. When unset, the comment of an existing record is kept.
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Also publish an A record with the IPv4 address from the update request.
+    #[serde(default)]
+    pub ipv4: bool,
+    /// Additional AAAA records in static prefixes, using the same Interface ID.
+    #[serde(default)]
+    pub views: Vec<HostView>,
+    /// Providers the records are published to, by name; `cloudflare` refers
+    /// to `[cloudflare]`. Empty means every configured provider.
+    #[serde(default)]
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostView {
+    /// Name of an entry in the top-level `views`.
+    pub view: String,
+    /// Hostname of the record in this view.
+    pub hostname: String,
+}
+
+/// Hosts published as `<name>.<domain>` with shared record settings.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HostGroup {
+    pub domain: String,
+    /// Overrides `cloudflare.ttl` for every member.
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(default)]
+    pub proxied: Option<bool>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub ipv4: bool,
+    /// Additional records of every member in static prefixes.
+    #[serde(default)]
+    pub views: Vec<GroupView>,
+    /// Providers every member is published to.
+    #[serde(default)]
+    pub providers: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GroupView {
+    /// Name of an entry in the top-level `views`.
+    pub view: String,
+    /// Members are published as `<name>.<domain>` in this view.
+    pub domain: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GroupMember {
+    /// Leftmost label(s) of the hostname; `*` publishes a wildcard record.
+    pub name: String,
+    #[serde(default)]
+    pub interface_id: String,
+    #[serde(default)]
+    pub mac: Option<String>,
+}
+
+impl HostGroup {
+    /// The members of the group as individual hosts.
+    fn expand(&self) -> Result<Vec<HostMapping>> {
+        let domain = self.domain.trim_end_matches('.');
+        if domain.is_empty() || domain.starts_with('.') {
+            return Err(Ddns6Error::Config(format!(
+                "Invalid host group domain: {:?}",
+                self.domain
+            )));
+        }
+
+        self.hosts
+            .iter()
+            .map(|member| {
+                if member.name.is_empty()
+                    || member.name.starts_with('.')
+                    || member.name.ends_with('.')
+                {
+                    return Err(Ddns6Error::Config(format!(
+                        "Invalid host name {:?} in group {}",
+                        member.name, domain
+                    )));
+                }
+
+                Ok(HostMapping {
+                    hostname: format!("{}.{}", member.name, domain),
+                    interface_id: member.interface_id.clone(),
+                    mac: member.mac.clone(),
+                    stable_privacy: None,
+                    ttl: self.ttl,
+                    proxied: self.proxied,
+                    comment: self.comment.clone(),
+                    ipv4: self.ipv4,
+                    views: self
+                        .views
+                        .iter()
+                        .map(|view| HostView {
+                            view: view.view.clone(),
+                            hostname: format!(
+                                "{}.{}",
+                                member.name,
+                                view.domain.trim_end_matches('.')
+                            ),
+                        })
+                        .collect(),
+                    providers: self.providers.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StablePrivacyConfig {
+    /// The host's `net.ipv6.conf.<interface>.stable_secret`.
+    pub secret: Secret,
+    #[serde(default)]
+    pub dad_counter: u8,
+}
+
+impl HostMapping {
+    /// Computes the host's address within `prefix`.
+    pub fn address_in(&self, prefix: &Ipv6Prefix) -> Result<Ipv6Addr> {
+        match (&self.stable_privacy, &self.mac) {
+            (Some(stable_privacy), Some(mac)) => {
+                let secret = parse_stable_secret(stable_privacy.secret.expose())?;
+                prefix.stable_privacy_address(secret, mac, stable_privacy.dad_counter)
+            }
+            _ => prefix.combine_with_interface_id(&self.interface_id),
+        }
+    }
+
+    /// Whether the host is published as a wildcard record, e.g. `*.lab.example.com`.
+    pub fn is_wildcard(&self) -> bool {
+        self.hostname.starts_with("*.")
+    }
+
+    /// Whether the records of this host are published to provider `name`.
+    pub fn uses_provider(&self, name: &str) -> bool {
+        self.providers.is_empty() || self.providers.iter().any(|provider| provider == name)
+    }
+
+    /// The host as published in `view`: same Interface ID and record
+    /// settings, but a different hostname, no A record and never proxied.
+    pub fn in_view(&self, view: &HostView) -> HostMapping {
+        HostMapping {
+            hostname: view.hostname.clone(),
+            proxied: Some(false),
+            ipv4: false,
+            views: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+fn parse_stable_secret(secret: &str) -> Result<Ipv6Addr> {
+    secret.parse::<Ipv6Addr>().map_err(|_| {
+        Ddns6Error::Config(format!(
+            "stable_privacy.secret must be in IPv6 address notation, as in the stable_secret sysctl: {}",
+            secret
+        ))
+    })
+}
+
+fn default_http_method() -> String {
+    "POST".to_string()
+}
+
+fn default_powerdns_server_id() -> String {
+    "localhost".to_string()
+}
+
+fn default_hook_timeout() -> u64 {
+    30
+}
+
+fn default_webhook_timeout() -> u64 {
+    10
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_webhook_retry_delay() -> u64 {
+    1000
+}
+
+fn default_history_entries() -> usize {
+    1000
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "ddns6".to_string()
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "ddns6".to_string()
+}
+
+fn default_mqtt_keep_alive() -> u64 {
+    30
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+fn default_api_url() -> String {
+    "https://api.cloudflare.com/client/v4".to_string()
+}
+
+fn default_dns_ttl() -> u32 {
+    60
+}
+
+fn default_ttl() -> u32 {
+    300
+}
+
+/// Cloudflare TTL used for "automatic", the only TTL allowed on proxied records.
+const AUTOMATIC_TTL: u32 = 1;
+
+/// Largest TTL in DNS: an unsigned 32-bit value limited to 2^31 - 1.
+const MAX_TTL: u32 = i32::MAX as u32;
+
+fn read_config(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| {
+        Ddns6Error::Config(format!(
+            "Failed to read config file {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+fn normalize_host(host: &mut HostMapping) -> Result<()> {
+    host.hostname = normalize_hostname(&host.hostname)?;
+    for view in &mut host.views {
+        view.hostname = normalize_hostname(&view.hostname)?;
+    }
+    Ok(())
+}
+
+/// Lowercases `hostname`, drops a trailing dot and encodes internationalized
+/// labels as punycode, keeping a leading `*.` wildcard label. Names read from
+/// provider APIs go through it too before they are compared with hosts.
+pub fn normalize_hostname(hostname: &str) -> Result<String> {
+    let (wildcard, name) = match hostname.strip_prefix("*.") {
+        Some(name) => ("*.", name),
+        None => ("", hostname),
+    };
+    let name = name.strip_suffix('.').unwrap_or(name);
+
+    let ascii = if name.is_ascii() {
+        name.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii(name).map_err(|e| {
+            Ddns6Error::Config(format!(
+                "Invalid internationalized hostname {}: {}",
+                hostname, e
+            ))
+        })?
+    };
+
+    Ok(format!("{}{}", wildcard, ascii))
+}
+
+/// Checks `hostname` against the RFC 1123 syntax: at most 253 bytes of dot
+/// separated labels of 1 to 63 letters, digits and hyphens, neither starting
+/// nor ending with a hyphen.
+fn validate_hostname(hostname: &str) -> std::result::Result<(), String> {
+    if hostname.len() > 253 {
+        return Err("longer than 253 bytes".to_string());
+    }
+
+    for label in hostname.split('.') {
+        if label.is_empty() {
+            return Err("empty label".to_string());
+        }
+        if label.len() > 63 {
+            return Err(format!("label {} is longer than 63 bytes", label));
+        }
+        if !label
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        {
+            return Err(format!(
+                "label {} may only contain letters, digits and hyphens",
+                label
+            ));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label {} cannot start or end with a hyphen", label));
         }
+    }
+    Ok(())
+}
+
+/// Whether `zone` is `ip6.arpa` or below it.
+pub fn is_reverse_zone(zone: &str) -> bool {
+    in_zone(zone, "ip6.arpa")
+}
+
+/// Whether `hostname` is `zone` or a name below it, ignoring case and
+/// trailing dots.
+pub fn in_zone(hostname: &str, zone: &str) -> bool {
+    let hostname = hostname.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    if hostname.eq_ignore_ascii_case(zone) {
+        return true;
+    }
+    let Some(dot) = hostname.len().checked_sub(zone.len() + 1) else {
+        return false;
+    };
+    hostname.as_bytes()[dot] == b'.'
+        && hostname
+            .get(dot + 1..)
+            .is_some_and(|suffix| suffix.eq_ignore_ascii_case(zone))
+}
+
+/// The zone with the longest name among `zones` that contains `hostname`.
+pub fn longest_zone<'a, T>(
+    zones: &'a [T],
+    hostname: &str,
+    name: impl Fn(&T) -> &str,
+) -> Option<&'a T> {
+    zones
+        .iter()
+        .filter(|zone| in_zone(hostname, name(zone)))
+        .max_by_key(|zone| name(zone).len())
+}
+
+/// `*` may only appear as the whole leftmost label, as in `*.lab.example.com`.
+fn valid_wildcard(hostname: &str) -> bool {
+    hostname
+        .strip_prefix("*.")
+        .is_some_and(|rest| !rest.is_empty() && !rest.contains('*'))
+}
+
+/// Checks a TTL sent to Cloudflare.
+fn validate_ttl(ttl: u32, field: &str) -> Result<()> {
+    if ttl == AUTOMATIC_TTL || (30..=86400).contains(&ttl) {
+        return Ok(());
+    }
+
+    Err(Ddns6Error::Config(format!(
+        "{} must be 1 (automatic) or between 30 and 86400 seconds, got {}",
+        field, ttl
+    )))
+}
+
+/// Prefix of the environment variables ddns6 passes to commands it runs.
+const RESERVED_VARIABLE_PREFIX: &str = "DDNS6_";
+
+/// Replaces `${NAME}` in every string of `value` using `lookup`.
+fn interpolate_value(
+    value: &mut toml::Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        toml::Value::String(s) => *s = interpolate(s, lookup)?,
+        toml::Value::Array(values) => {
+            for value in values {
+                interpolate_value(value, lookup)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                interpolate_value(value, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces `${NAME}` in `s` with `lookup(NAME)`; `$${` is a literal `${`.
+/// Unset variables are an error rather than silently empty. Names starting
+/// with `DDNS6_` are left alone, as they are set by ddns6 for hook commands.
+fn interpolate(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(reference) = rest.strip_prefix("${") {
+            let end = reference.find('}').ok_or_else(|| {
+                Ddns6Error::Config(format!("Unterminated variable reference in {:?}", s))
+            })?;
+            let name = &reference[..end];
+            if name.starts_with(RESERVED_VARIABLE_PREFIX) {
+                result.push_str(&rest[..end + 3]);
+                rest = &reference[end + 1..];
+                continue;
+            }
+            let value = lookup(name).ok_or_else(|| {
+                Ddns6Error::Config(format!("Environment variable {} is not set", name))
+            })?;
+            result.push_str(&value);
+            rest = &reference[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Parses `content`, replacing `${NAME}` in string values with the
+/// environment variable `NAME`. Errors name `origin` and, where the raw text
+/// is at fault, the line and column.
+fn parse<T: DeserializeOwned>(content: &str, origin: &str) -> Result<T> {
+    let parse_error =
+        |e: toml::de::Error| Ddns6Error::Config(format!("Failed to parse {}: {}", origin, e));
+
+    let mut value: toml::Value = toml::from_str(content).map_err(parse_error)?;
+    interpolate_value(&mut value, &|name| std::env::var(name).ok())?;
+
+    value.try_into().map_err(|e: toml::de::Error| {
+        // Errors from the interpolated value carry no position; parsing the
+        // raw text again locates them unless an interpolated value caused it.
+        match toml::from_str::<T>(content) {
+            Err(located) => parse_error(located),
+            Ok(_) => parse_error(e),
+        }
+    })
+}
+
+/// A file pulled in by `include` or `--config-dir`, which may only add hosts.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostsFile {
+    #[serde(default)]
+    hosts: Vec<HostMapping>,
+    #[serde(default)]
+    host_groups: Vec<HostGroup>,
+}
+
+/// Line numbers of the `[[hosts]]` and `[[host_groups]]` entries of a file,
+/// in order.
+#[derive(Debug, Default)]
+struct HostLines {
+    hosts: Vec<usize>,
+    host_groups: Vec<usize>,
+}
+
+impl HostLines {
+    fn of(content: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Spans {
+            #[serde(default)]
+            hosts: Vec<toml::Spanned<toml::Table>>,
+            #[serde(default)]
+            host_groups: Vec<toml::Spanned<toml::Table>>,
+        }
+
+        let line = |table: &toml::Spanned<toml::Table>| {
+            content[..table.span().start].matches('\n').count() + 1
+        };
+        toml::from_str::<Spans>(content)
+            .map(|spans| HostLines {
+                hosts: spans.hosts.iter().map(line).collect(),
+                host_groups: spans.host_groups.iter().map(line).collect(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// File and, if known, line a host was configured at.
+#[derive(Debug, Clone)]
+struct HostOrigin {
+    file: String,
+    line: Option<usize>,
+}
+
+impl HostOrigin {
+    /// Prefixes `error` with the file and line.
+    fn locate(&self, error: Ddns6Error) -> Ddns6Error {
+        match error {
+            Ddns6Error::Config(message) => Ddns6Error::Config(format!("{}: {}", self, message)),
+            error => Ddns6Error::Config(format!("{}: {}", self, error)),
+        }
+    }
+}
+
+impl fmt::Display for HostOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file, line),
+            None => write!(f, "{}", self.file),
+        }
+    }
+}
+
+/// Expands `host_groups` and normalizes the hostnames of the hosts of one
+/// file, pairing each host with the line it was configured at.
+fn located_hosts(
+    hosts: Vec<HostMapping>,
+    host_groups: &[HostGroup],
+    content: &str,
+    file: &str,
+) -> Result<Vec<(HostOrigin, HostMapping)>> {
+    let lines = HostLines::of(content);
+    let origin = |line: Option<usize>| HostOrigin {
+        file: file.to_string(),
+        line,
+    };
+
+    let mut located = Vec::new();
+    for (index, host) in hosts.into_iter().enumerate() {
+        located.push((origin(lines.hosts.get(index).copied()), host));
+    }
+    for (index, group) in host_groups.iter().enumerate() {
+        let origin = origin(lines.host_groups.get(index).copied());
+        let members = group.expand().map_err(|e| origin.locate(e))?;
+        located.extend(members.into_iter().map(|host| (origin.clone(), host)));
+    }
+    for (origin, host) in &mut located {
+        normalize_host(host).map_err(|e| origin.locate(e))?;
+    }
+    Ok(located)
+}
+
+/// The files matching `pattern`, sorted by name. Only the file name may
+/// contain the wildcards `*` and `?`; a pattern without wildcards names a
+/// single file.
+fn expand_include(pattern: &Path) -> Result<Vec<PathBuf>> {
+    let has_wildcard = |s: &str| s.contains(['*', '?']);
+    let file_name = pattern
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            Ddns6Error::Config(format!("Invalid include pattern: {}", pattern.display()))
+        })?;
+    let dir = pattern.parent().unwrap_or(Path::new("."));
+    if has_wildcard(&dir.to_string_lossy()) {
+        return Err(Ddns6Error::Config(format!(
+            "Only the file name of include pattern {} may contain wildcards",
+            pattern.display()
+        )));
+    }
+    if !has_wildcard(file_name) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+
+    let entries = fs::read_dir(dir).map_err(|e| {
+        Ddns6Error::Config(format!(
+            "Failed to read include directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| {
+            Ddns6Error::Config(format!(
+                "Failed to read include directory {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        // Like a shell, leave hidden files such as editor swap files alone.
+        let hidden = name.starts_with('.') && !file_name.starts_with('.');
+        if !hidden && wildcard_match(file_name, name) && entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Matches `name` against `pattern`, where `*` stands for any run of
+/// characters and `?` for a single one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it was tried at.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Config {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_files(path.as_ref(), None)
+    }
+
+    /// Loads the main configuration at `path` and merges the hosts of the
+    /// files it includes and of every `*.toml` file in `config_dir`.
+    pub fn from_files(path: &Path, config_dir: Option<&Path>) -> Result<Self> {
+        let content = read_config(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        Self::load(&content, &path.display().to_string(), base, config_dir)
+    }
+
+    /// Parses a configuration given as a string. Includes are resolved
+    /// relative to the working directory.
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Self::load(content, "config file", Path::new("."), None)
+    }
+
+    fn load(content: &str, origin: &str, base: &Path, config_dir: Option<&Path>) -> Result<Self> {
+        let mut config: Config = parse(content, origin)?;
+
+        let mut patterns: Vec<PathBuf> = config
+            .include
+            .iter()
+            .map(|include| base.join(include))
+            .collect();
+        if let Some(dir) = config_dir {
+            patterns.push(dir.join("*.toml"));
+        }
+
+        config.normalize_zones()?;
+        let hosts = std::mem::take(&mut config.hosts);
+        let host_groups = std::mem::take(&mut config.host_groups);
+        let mut origins = Vec::new();
+        for (origin, host) in located_hosts(hosts, &host_groups, content, origin)? {
+            origins.push(origin);
+            config.hosts.push(host);
+        }
+        for pattern in &patterns {
+            for file in expand_include(pattern)? {
+                config.include_hosts(&file, &mut origins)?;
+            }
+        }
+
+        config.read_secret_files()?;
+        config.validate(&origins)?;
+        config.derive_interface_ids()?;
+        Ok(config)
+    }
+
+    /// Adds the hosts of the included `file`, and where they come from to
+    /// `origins`.
+    fn include_hosts(&mut self, file: &Path, origins: &mut Vec<HostOrigin>) -> Result<()> {
+        let origin = file.display().to_string();
+        let content = read_config(file)?;
+        let included: HostsFile = parse(&content, &origin)?;
+
+        for (origin, host) in
+            located_hosts(included.hosts, &included.host_groups, &content, &origin)?
+        {
+            origins.push(origin);
+            self.hosts.push(host);
+        }
+        Ok(())
+    }
+
+    /// Converts zone names to lowercase ASCII, encoding internationalized
+    /// labels as punycode.
+    fn normalize_zones(&mut self) -> Result<()> {
+        let cloudflare_zones = self.cloudflare.iter_mut().flat_map(|c| c.zones.iter_mut());
+        let dns_zones = self.dns.iter_mut().flat_map(|dns| dns.zones.iter_mut());
+        let provider_zones =
+            self.providers
+                .iter_mut()
+                .flat_map(|provider| match &mut provider.kind {
+                    ProviderKind::PowerDns(powerdns) => powerdns.zones.iter_mut(),
+                    ProviderKind::Http(http) => http.zones.iter_mut(),
+                });
+        for zone in cloudflare_zones.chain(dns_zones).chain(provider_zones) {
+            *zone = normalize_hostname(zone)?;
+        }
+        Ok(())
+    }
+
+    /// Loads `cloudflare.api_token` from `cloudflare.api_token_file`.
+    fn read_secret_files(&mut self) -> Result<()> {
+        let Some(cloudflare) = &mut self.cloudflare else {
+            return Ok(());
+        };
+        let Some(path) = &cloudflare.api_token_file else {
+            return Ok(());
+        };
+        if !cloudflare.api_token.is_empty() {
+            return Err(Ddns6Error::Config(
+                "cloudflare.api_token and cloudflare.api_token_file are mutually exclusive"
+                    .to_string(),
+            ));
+        }
+
+        let token = fs::read_to_string(path).map_err(|e| {
+            Ddns6Error::Config(format!(
+                "Failed to read cloudflare.api_token_file {}: {}",
+                path, e
+            ))
+        })?;
+        cloudflare.api_token = Secret::from(token.trim());
+        Ok(())
+    }
+
+    /// Fills in `interface_id` for EUI-64 hosts configured with a MAC address.
+    fn derive_interface_ids(&mut self) -> Result<()> {
+        for host in &mut self.hosts {
+            if host.stable_privacy.is_some() {
+                continue;
+            }
+            if let Some(mac) = &host.mac {
+                host.interface_id = eui64_interface_id(mac)?.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the configuration. Errors in a host name the file and line in
+    /// `origins`, which lists where each host comes from, if known.
+    fn validate(&self, origins: &[HostOrigin]) -> Result<()> {
+        if self.server.bind_address.is_empty() {
+            return Err(Ddns6Error::Config(
+                "bind_address cannot be empty".to_string(),
+            ));
+        }
+
+        if self.cloudflare.is_none()
+            && self.providers.is_empty()
+            && self.dns.is_none()
+            && self.outputs.is_empty()
+        {
+            return Err(Ddns6Error::Config(
+                "At least one of [cloudflare], [[providers]], [dns] or [[outputs]] must be configured"
+                    .to_string(),
+            ));
+        }
+        if let Some(cloudflare) = &self.cloudflare {
+            cloudflare.validate()?;
+        }
+        let mut provider_names = HashMap::new();
+        for provider in &self.providers {
+            provider.validate()?;
+            if provider_names.insert(provider.name.as_str(), ()).is_some() {
+                return Err(Ddns6Error::Config(format!(
+                    "Duplicate provider name: {}",
+                    provider.name
+                )));
+            }
+        }
+        if let Some(dns) = &self.dns {
+            dns.validate()?;
+        }
+
+        // Only marked records are deleted: Cloudflare marks them with
+        // `ownership`, PowerDNS always does.
+        let cleanup_supported = self.ownership().is_some()
+            || self
+                .providers
+                .iter()
+                .any(|provider| matches!(provider.kind, ProviderKind::PowerDns(_)));
+        if self.cleanup.enabled && !cleanup_supported {
+            return Err(Ddns6Error::Config(
+                "cleanup requires cloudflare.ownership or a PowerDNS provider so that only records created by ddns6 are deleted"
+                    .to_string(),
+            ));
+        }
+
+        if self.cleanup.interval_secs == Some(0) {
+            return Err(Ddns6Error::Config(
+                "cleanup.interval_secs must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.debounce.flap_threshold == Some(0) {
+            return Err(Ddns6Error::Config(
+                "debounce.flap_threshold must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.reverse.enabled {
+            let cloudflare_reverse = self.cloudflare.as_ref().is_some_and(|cloudflare| {
+                cloudflare.auto_zones || cloudflare.zones.iter().any(|zone| is_reverse_zone(zone))
+            });
+            let powerdns_reverse = self.providers.iter().any(|provider| {
+                matches!(&provider.kind, ProviderKind::PowerDns(powerdns)
+                    if powerdns.zones.iter().any(|zone| is_reverse_zone(zone)))
+            });
+            if !cloudflare_reverse && !powerdns_reverse {
+                return Err(Ddns6Error::Config(
+                    "reverse requires an ip6.arpa zone in cloudflare.zones, cloudflare.auto_zones or the zones of a PowerDNS provider"
+                        .to_string(),
+                ));
+            }
+            if cloudflare_reverse && self.ownership().is_none() {
+                return Err(Ddns6Error::Config(
+                    "reverse requires cloudflare.ownership so that only PTR records created by ddns6 are replaced"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if self.history.max_entries == 0 {
+            return Err(Ddns6Error::Config(
+                "history.max_entries must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.hosts.is_empty() {
+            return Err(Ddns6Error::Config(
+                "At least one host mapping must be configured".to_string(),
+            ));
+        }
+
+        for webhook in &self.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                return Err(Ddns6Error::Config(format!(
+                    "webhook url must be an http(s) URL: {}",
+                    webhook.url
+                )));
+            }
+            if webhook.timeout_secs == 0 {
+                return Err(Ddns6Error::Config(format!(
+                    "timeout_secs of webhook {} must be greater than zero",
+                    webhook.url
+                )));
+            }
+        }
+
+        for hook in &self.on_change {
+            if hook.command.trim().is_empty() {
+                return Err(Ddns6Error::Config(
+                    "on_change command cannot be empty".to_string(),
+                ));
+            }
+            if hook.timeout_secs == 0 {
+                return Err(Ddns6Error::Config(format!(
+                    "timeout_secs of on_change command {:?} must be greater than zero",
+                    hook.command
+                )));
+            }
+        }
+
+        let mut output_paths = HashMap::new();
+        for output in &self.outputs {
+            if output.path.is_empty() {
+                return Err(Ddns6Error::Config(
+                    "output path cannot be empty".to_string(),
+                ));
+            }
+            if output_paths.insert(output.path.as_str(), ()).is_some() {
+                return Err(Ddns6Error::Config(format!(
+                    "Duplicate output path: {}",
+                    output.path
+                )));
+            }
+            if output
+                .reload_command
+                .as_ref()
+                .is_some_and(|command| command.trim().is_empty())
+            {
+                return Err(Ddns6Error::Config(format!(
+                    "reload_command of output {} cannot be empty",
+                    output.path
+                )));
+            }
+            if output.timeout_secs == 0 {
+                return Err(Ddns6Error::Config(format!(
+                    "timeout_secs of output {} must be greater than zero",
+                    output.path
+                )));
+            }
+            if output.ttl > MAX_TTL {
+                return Err(Ddns6Error::Config(format!(
+                    "ttl of output {} must be at most {}",
+                    output.path, MAX_TTL
+                )));
+            }
+        }
+
+        if let Some(mqtt) = &self.mqtt {
+            if mqtt.host.is_empty() {
+                return Err(Ddns6Error::Config("mqtt.host cannot be empty".to_string()));
+            }
+            if mqtt.client_id.is_empty() {
+                return Err(Ddns6Error::Config(
+                    "mqtt.client_id cannot be empty".to_string(),
+                ));
+            }
+            if mqtt.keep_alive_secs == 0 {
+                return Err(Ddns6Error::Config(
+                    "mqtt.keep_alive_secs must be greater than zero".to_string(),
+                ));
+            }
+            if mqtt.ca_file.is_some() && !mqtt.tls {
+                return Err(Ddns6Error::Config(
+                    "mqtt.ca_file requires mqtt.tls".to_string(),
+                ));
+            }
+            let topics = std::iter::once(("mqtt.topic_prefix", &mqtt.topic_prefix))
+                .chain(mqtt.command_topic.iter().map(|t| ("mqtt.command_topic", t)));
+            for (field, topic) in topics {
+                if topic.is_empty() || topic.contains(['+', '#']) {
+                    return Err(Ddns6Error::Config(format!(
+                        "{} must be a non-empty topic without wildcards: {:?}",
+                        field, topic
+                    )));
+                }
+            }
+        }
+
+        let mut uplinks = HashMap::new();
+        for uplink in &self.uplinks {
+            if uplink.name.is_empty() || uplink.name.contains(char::is_whitespace) {
+                return Err(Ddns6Error::Config(format!(
+                    "uplink name must be a single non-empty word: {:?}",
+                    uplink.name
+                )));
+            }
+            if uplink.prefix_lifetime_secs == Some(0) {
+                return Err(Ddns6Error::Config(format!(
+                    "prefix_lifetime_secs of uplink {} must be greater than zero",
+                    uplink.name
+                )));
+            }
+            if uplinks.insert(uplink.name.as_str(), ()).is_some() {
+                return Err(Ddns6Error::Config(format!(
+                    "Duplicate uplink name: {}",
+                    uplink.name
+                )));
+            }
+        }
+
+        if !self.uplinks.is_empty() && self.cloudflare.is_some() && self.ownership().is_none() {
+            return Err(Ddns6Error::Config(
+                "uplinks require cloudflare.ownership so that the records of each uplink can be told apart"
+                    .to_string(),
+            ));
+        }
+
+        let mut views = HashMap::new();
+        for view in &self.views {
+            if view.name.is_empty() {
+                return Err(Ddns6Error::Config("view name cannot be empty".to_string()));
+            }
+            view.parsed_prefix().map_err(|e| {
+                Ddns6Error::Config(format!("Invalid prefix for view {}: {}", view.name, e))
+            })?;
+            if views.insert(view.name.as_str(), ()).is_some() {
+                return Err(Ddns6Error::Config(format!(
+                    "Duplicate view name: {}",
+                    view.name
+                )));
+            }
+        }
+
+        let mut seen_hostnames: HashMap<String, Option<&HostOrigin>> = HashMap::new();
+        for (index, host) in self.hosts.iter().enumerate() {
+            let origin = origins.get(index);
+            let locate = |error| match origin {
+                Some(origin) => origin.locate(error),
+                None => error,
+            };
+            self.validate_host(host).map_err(locate)?;
+
+            let hostnames =
+                std::iter::once(&host.hostname).chain(host.views.iter().map(|view| &view.hostname));
+            for hostname in hostnames {
+                if let Some(first) = seen_hostnames.insert(normalize_hostname(hostname)?, origin) {
+                    let message = match first {
+                        Some(first) => format!(
+                            "Duplicate hostname {}, already configured in {}",
+                            hostname, first
+                        ),
+                        None => format!("Duplicate hostname: {}", hostname),
+                    };
+                    return Err(locate(Ddns6Error::Config(message)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings of a single host against the rest of the
+    /// configuration.
+    fn validate_host(&self, host: &HostMapping) -> Result<()> {
+        if host.hostname.is_empty() {
+            return Err(Ddns6Error::Config("hostname cannot be empty".to_string()));
+        }
+        let hostnames =
+            std::iter::once(&host.hostname).chain(host.views.iter().map(|view| &view.hostname));
+        for hostname in hostnames {
+            if hostname.contains('*') && !valid_wildcard(hostname) {
+                return Err(Ddns6Error::Config(format!(
+                    "Wildcard hostname {} must have * as its whole leftmost label",
+                    hostname
+                )));
+            }
+            let name = hostname.strip_prefix("*.").unwrap_or(hostname);
+            validate_hostname(name)
+                .map_err(|e| Ddns6Error::Config(format!("Invalid hostname {}: {}", hostname, e)))?;
+            self.validate_zone_membership(host, hostname)?;
+        }
+
+        for provider in &host.providers {
+            let known = if provider == CLOUDFLARE_PROVIDER {
+                self.cloudflare.is_some()
+            } else {
+                self.providers.iter().any(|known| known.name == *provider)
+            };
+            if !known {
+                return Err(Ddns6Error::Config(format!(
+                    "Host {} refers to unknown provider {}",
+                    host.hostname, provider
+                )));
+            }
+        }
+
+        match &host.mac {
+            Some(mac) if host.stable_privacy.is_some() => {
+                eui64_interface_id(mac)?;
+                if let Some(stable_privacy) = &host.stable_privacy {
+                    parse_stable_secret(stable_privacy.secret.expose())?;
+                }
+                if !host.interface_id.is_empty() {
+                    return Err(Ddns6Error::Config(format!(
+                        "Host {} uses stable_privacy and cannot also set interface_id",
+                        host.hostname
+                    )));
+                }
+            }
+            None if host.stable_privacy.is_some() => {
+                return Err(Ddns6Error::Config(format!(
+                    "Host {} uses stable_privacy and needs the interface's mac",
+                    host.hostname
+                )));
+            }
+            Some(mac) => {
+                let derived = u128::from(eui64_interface_id(mac)?);
+                let configured = parse_interface_id(&host.interface_id)
+                    .map(|iid| u128::from(iid) & u128::from(u64::MAX));
+                if !host.interface_id.is_empty() && configured.ok() != Some(derived) {
+                    return Err(Ddns6Error::Config(format!(
+                        "Host {} sets both interface_id and mac; use only one",
+                        host.hostname
+                    )));
+                }
+            }
+            None if host.interface_id.is_empty() => {
+                return Err(Ddns6Error::Config(format!(
+                    "Host {} needs either interface_id or mac",
+                    host.hostname
+                )));
+            }
+            None => self.validate_interface_id(&host.interface_id)?,
+        }
+
+        for view in &host.views {
+            let config = self.views.iter().find(|config| config.name == view.view);
+            let config = config.ok_or_else(|| {
+                Ddns6Error::Config(format!(
+                    "Host {} refers to unknown view {}",
+                    host.hostname, view.view
+                ))
+            })?;
+            if view.hostname.is_empty() {
+                return Err(Ddns6Error::Config(format!(
+                    "Host {} needs a hostname for view {}",
+                    host.hostname, view.view
+                )));
+            }
+            // An invalid view prefix is reported with the view itself.
+            let prefix_len = config.parsed_prefix().map(|prefix| prefix.prefix_len());
+            if host.stable_privacy.is_some() && prefix_len.is_ok_and(|len| len != 64) {
+                return Err(Ddns6Error::Config(format!(
+                    "Host {} uses stable_privacy, which requires view {} to be a /64",
+                    host.hostname, view.view
+                )));
+            }
+        }
+
+        // Only Cloudflare reads 1 as automatic and limits the range; other
+        // providers take the TTL literally.
+        if let Some(ttl) = host.ttl {
+            let field = format!("ttl for {}", host.hostname);
+            if self.cloudflare.is_some() && host.uses_provider(CLOUDFLARE_PROVIDER) {
+                validate_ttl(ttl, &field)?;
+
+                if host.proxied == Some(true) && ttl != AUTOMATIC_TTL {
+                    return Err(Ddns6Error::Config(format!(
+                        "Proxied host {} must use ttl = 1 (automatic), got {}",
+                        host.hostname, ttl
+                    )));
+                }
+            }
+            if ttl > MAX_TTL {
+                return Err(Ddns6Error::Config(format!(
+                    "{} must be at most {}, got {}",
+                    field, MAX_TTL, ttl
+                )));
+            }
+            let provider = self
+                .providers
+                .iter()
+                .find(|provider| host.uses_provider(&provider.name));
+            if let (0, Some(provider)) = (ttl, provider) {
+                return Err(Ddns6Error::Config(format!(
+                    "{} must be at least 1 for provider {}",
+                    field, provider.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `hostname` of `host` lies within one of `cloudflare.zones`,
+    /// or of `dns.zones` when there is no `[cloudflare]`, and within one of
+    /// the zones of every other provider the host is published to. Zones
+    /// behind `zone_id` or `auto_zones` are only known once the Cloudflare API
+    /// has been asked, so any hostname is accepted then.
+    fn validate_zone_membership(&self, host: &HostMapping, hostname: &str) -> Result<()> {
+        let mut checks = Vec::new();
+        match (&self.cloudflare, &self.dns) {
+            (Some(cloudflare), _) => {
+                if host.uses_provider(CLOUDFLARE_PROVIDER)
+                    && cloudflare.zone_id.is_empty()
+                    && !cloudflare.auto_zones
+                {
+                    checks.push(("cloudflare.zones".to_string(), &cloudflare.zones));
+                }
+            }
+            (None, Some(dns)) => checks.push(("dns.zones".to_string(), &dns.zones)),
+            (None, None) => {}
+        }
+        for provider in &self.providers {
+            if let (true, Some(zones)) = (host.uses_provider(&provider.name), provider.zones()) {
+                checks.push((format!("the zones of provider {}", provider.name), zones));
+            }
+        }
+
+        for (field, zones) in checks {
+            if !zones.iter().any(|zone| in_zone(hostname, zone)) {
+                return Err(Ddns6Error::Config(format!(
+                    "Hostname {} is not within any of {} ({})",
+                    hostname,
+                    field,
+                    zones.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ownership settings of the Cloudflare records, if any.
+    pub fn ownership(&self) -> Option<&OwnershipConfig> {
+        self.cloudflare.as_ref()?.ownership.as_ref()
+    }
+
+    fn validate_interface_id(&self, iid: &str) -> Result<()> {
+        if iid.parse::<Ipv6Addr>().is_ok() {
+            return Ok(());
+        }
+
+        let test_addr = format!("2001:db8::{}", iid);
+        if test_addr.parse::<Ipv6Addr>().is_ok() {
+            return Ok(());
+        }
+
+        Err(Ddns6Error::InvalidInterfaceId(format!(
+            "Invalid interface ID format: {}",
+            iid
+        )))
+    }
+
+    pub fn uplink(&self, name: &str) -> Option<&UplinkConfig> {
+        self.uplinks.iter().find(|uplink| uplink.name == name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_host(&self, hostname: &str) -> Option<&HostMapping> {
+        self.hosts.iter().find(|h| h.hostname == hostname)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_interface_id() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate_interface_id("::1").is_ok());
+        assert!(config.validate_interface_id("::2").is_ok());
+        assert!(config
+            .validate_interface_id("::a1b2:c3d4:e5f6:7890")
+            .is_ok());
+        assert!(config.validate_interface_id("1").is_ok());
+        assert!(config.validate_interface_id("1234:5678:90ab:cdef").is_ok());
+    }
+
+    #[test]
+    fn test_get_host() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![
+                HostMapping {
+                    hostname: "device1.example.com".to_string(),
+                    interface_id: "::1".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
+                },
+                HostMapping {
+                    hostname: "device2.example.com".to_string(),
+                    interface_id: "::2".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
+                },
+            ],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.get_host("device1.example.com").is_some());
+        assert!(config.get_host("device2.example.com").is_some());
+        assert!(config.get_host("nonexistent.example.com").is_none());
+    }
+
+    #[test]
+    fn test_validate_empty_bind_address() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_empty_api_token() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: Secret::default(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_empty_zone_id() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_zones_by_name() {
+        let mut config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: vec!["example.com".to_string(), "example.net".to_string()],
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+        assert!(config.validate(&[]).is_ok());
+
+        config.cloudflare.as_mut().unwrap().zones = vec![];
+        config.cloudflare.as_mut().unwrap().auto_zones = true;
+        assert!(config.validate(&[]).is_ok());
+
+        config.cloudflare.as_mut().unwrap().zones = vec!["".to_string()];
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_no_hosts() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_empty_hostname() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "".to_string(),
+                interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_invalid_interface_id() {
+        let config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "invalid::xyz::123".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_mac_derives_interface_id() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "token"
+            zone_id = "zone"
+
+            [[hosts]]
+            hostname = "printer.example.com"
+            mac = "aa:bb:cc:dd:ee:ff"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.hosts[0].interface_id, "::a8bb:ccff:fedd:eeff");
+    }
+
+    #[test]
+    fn test_validate_mac_and_interface_id() {
+        let mut config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "".to_string(),
+                mac: Some("aa:bb:cc:dd:ee:ff".to_string()),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+        assert!(config.validate(&[]).is_ok());
+
+        config.hosts[0].interface_id = "::a8bb:ccff:fedd:eeff".to_string();
+        assert!(config.validate(&[]).is_ok());
+
+        config.hosts[0].interface_id = "::1".to_string();
+        assert!(config.validate(&[]).is_err());
+
+        config.hosts[0].mac = Some("aa:bb:cc".to_string());
+        assert!(config.validate(&[]).is_err());
+
+        config.hosts[0].mac = None;
+        config.hosts[0].interface_id = "".to_string();
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_stable_privacy_host() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "token"
+            zone_id = "zone"
+
+            [[hosts]]
+            hostname = "laptop.example.com"
+            mac = "00:00:00:00:00:00"
+
+            [hosts.stable_privacy]
+            secret = "2001:db8:1:2:3:4:5:6"
+            "#,
+        )
+        .unwrap();
+
+        let host = &config.hosts[0];
+        assert!(host.interface_id.is_empty());
+
+        let prefix = Ipv6Prefix::from_address("2001:db8:1234:5678::".parse().unwrap(), 64).unwrap();
+        assert_eq!(
+            host.address_in(&prefix).unwrap().to_string(),
+            "2001:db8:1234:5678:5525:fb67:6f69:b9ed"
+        );
+    }
+
+    #[test]
+    fn test_validate_stable_privacy() {
+        let mut config = Config {
+            server: ServerConfig {
+                bind_address: "0.0.0.0:8080".to_string(),
+                workers: 4,
+                allow_ula_prefix: false,
+            },
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
+                ttl: 300,
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
+            hosts: vec![HostMapping {
+                hostname: "test.example.com".to_string(),
+                interface_id: "".to_string(),
+                mac: Some("52:54:00:12:34:56".to_string()),
+                stable_privacy: Some(StablePrivacyConfig {
+                    secret: "2001:db8:1:2:3:4:5:6".into(),
+                    dad_counter: 0,
+                }),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                views: Vec::new(),
+                providers: Vec::new(),
+            }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+        assert!(config.validate(&[]).is_ok());
+
+        config.hosts[0].interface_id = "::1".to_string();
+        assert!(config.validate(&[]).is_err());
+
+        config.hosts[0].interface_id = "".to_string();
+        config.hosts[0].mac = None;
+        assert!(config.validate(&[]).is_err());
+
+        config.hosts[0].mac = Some("52:54:00:12:34:56".to_string());
+        config.hosts[0].stable_privacy = Some(StablePrivacyConfig {
+            secret: "not-a-secret".into(),
+            dad_counter: 0,
+        });
+        assert!(config.validate(&[]).is_err());
+    }
+
+    #[test]
+    fn test_host_views() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "token"
+            zone_id = "zone"
+
+            [[views]]
+            name = "lan"
+            prefix = "fd12:3456:789a:1::/64"
+
+            [[hosts]]
+            hostname = "nas.example.com"
+            interface_id = "::10"
+            proxied = true
+
+            [[hosts.views]]
+            view = "lan"
+            hostname = "nas.lan.example.com"
+            "#,
+        )
+        .unwrap();
+
+        let host = &config.hosts[0];
+        let view = &host.views[0];
+        let lan_host = host.in_view(view);
+        assert_eq!(lan_host.hostname, "nas.lan.example.com");
+        assert_eq!(lan_host.proxied, Some(false));
+        assert!(lan_host.views.is_empty());
+
+        let prefix = config.views[0].parsed_prefix().unwrap();
+        assert_eq!(
+            lan_host.address_in(&prefix).unwrap().to_string(),
+            "fd12:3456:789a:1::10"
+        );
+    }
+
+    #[test]
+    fn test_validate_host_views() {
+        let toml = |view: &str, view_hostname: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [[views]]
+                name = "lan"
+                prefix = "fd12:3456:789a:1::/64"
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+
+                [[hosts.views]]
+                view = "{}"
+                hostname = "{}"
+                "#,
+                view, view_hostname
+            )
+        };
+
+        assert!(Config::from_toml(&toml("lan", "nas.lan.example.com")).is_ok());
+        assert!(Config::from_toml(&toml("wan", "nas.lan.example.com")).is_err());
+        assert!(Config::from_toml(&toml("lan", "")).is_err());
+        assert!(Config::from_toml(&toml("lan", "nas.example.com")).is_err());
+
+        let invalid_prefix = toml("lan", "nas.lan.example.com").replace("::/64", "::/129");
+        assert!(Config::from_toml(&invalid_prefix).is_err());
+    }
+
+    #[test]
+    fn test_validate_uplinks() {
+        let toml = |uplinks: &str, ownership: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+                {}
+
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                ownership, uplinks
+            )
+        };
+        let ownership = "[cloudflare.ownership]";
+
+        let config = Config::from_toml(&toml(
+            "[[uplinks]]\nname = \"isp1\"\n[[uplinks]]\nname = \"isp2\"\nprefix_lifetime_secs = 3600",
+            ownership,
+        ))
+        .unwrap();
+        assert_eq!(config.uplinks.len(), 2);
+        assert_eq!(
+            config.uplink("isp2").unwrap().prefix_lifetime_secs,
+            Some(3600)
+        );
+        assert!(config.uplink("isp3").is_none());
+
+        assert!(Config::from_toml(&toml("[[uplinks]]\nname = \"isp1\"", "")).is_err());
+        assert!(Config::from_toml(&toml(
+            "[[uplinks]]\nname = \"isp1\"\n[[uplinks]]\nname = \"isp1\"",
+            ownership
+        ))
+        .is_err());
+        assert!(Config::from_toml(&toml("[[uplinks]]\nname = \"isp 1\"", ownership)).is_err());
+        assert!(Config::from_toml(&toml(
+            "[[uplinks]]\nname = \"isp1\"\nprefix_lifetime_secs = 0",
+            ownership
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_webhooks() {
+        let toml = |webhook: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [[webhooks]]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                webhook
+            )
+        };
+
+        let config = Config::from_toml(&toml("url = \"https://hooks.example.com/ddns6\"")).unwrap();
+        let webhook = &config.webhooks[0];
+        assert!(webhook.secret.is_none());
+        assert_eq!(webhook.timeout_secs, 10);
+        assert_eq!(webhook.retries, 3);
+        assert_eq!(webhook.retry_delay_ms, 1000);
+
+        assert!(Config::from_toml(&toml("url = \"ftp://hooks.example.com\"")).is_err());
+        assert!(Config::from_toml(&toml(
+            "url = \"https://hooks.example.com\"\ntimeout_secs = 0"
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_on_change_hooks() {
+        let toml = |hook: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [[on_change]]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                hook
+            )
+        };
+
+        let config = Config::from_toml(&toml("command = \"/usr/local/bin/update-nft\"")).unwrap();
+        let hook = &config.on_change[0];
+        assert!(!hook.per_host);
+        assert_eq!(hook.timeout_secs, 30);
+
+        assert!(Config::from_toml(&toml("command = \" \"")).is_err());
+        assert!(Config::from_toml(&toml("command = \"true\"\ntimeout_secs = 0")).is_err());
+
+        // Hook variables are not interpolated at load time.
+        let config = Config::from_toml(&toml(
+            "command = \"logger ${DDNS6_HOST} is now ${DDNS6_ADDRESS}\"\nper_host = true",
+        ))
+        .unwrap();
+        assert_eq!(
+            config.on_change[0].command,
+            "logger ${DDNS6_HOST} is now ${DDNS6_ADDRESS}"
+        );
+    }
+
+    #[test]
+    fn test_mqtt() {
+        let toml = |mqtt: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [mqtt]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                mqtt
+            )
+        };
+
+        let config = Config::from_toml(&toml("host = \"broker.lan\"")).unwrap();
+        let mqtt = config.mqtt.unwrap();
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.client_id, "ddns6");
+        assert_eq!(mqtt.topic_prefix, "ddns6");
+        assert!(!mqtt.tls);
+        assert!(mqtt.command_topic.is_none());
+
+        assert!(Config::from_toml(&toml("host = \"\"")).is_err());
+        assert!(Config::from_toml(&toml("host = \"broker.lan\"\nkeep_alive_secs = 0")).is_err());
+        assert!(
+            Config::from_toml(&toml("host = \"broker.lan\"\nca_file = \"/etc/ca.pem\"")).is_err()
+        );
+        assert!(
+            Config::from_toml(&toml("host = \"broker.lan\"\ntopic_prefix = \"ddns6/#\"")).is_err()
+        );
+        assert!(Config::from_toml(&toml(
+            "host = \"broker.lan\"\ncommand_topic = \"ddns6/+/set\""
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_debounce() {
+        let toml = |debounce: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [debounce]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                debounce
+            )
+        };
+
+        let config = Config::from_toml(&toml("")).unwrap();
+        assert_eq!(config.debounce.settle_secs, 0);
+        assert!(config.debounce.flap_threshold.is_none());
+
+        let config = Config::from_toml(&toml("settle_secs = 120\nflap_threshold = 3")).unwrap();
+        assert_eq!(config.debounce.settle_secs, 120);
+        assert_eq!(config.debounce.flap_threshold, Some(3));
+
+        assert!(Config::from_toml(&toml("flap_threshold = 0")).is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let toml = |history: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [history]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                history
+            )
+        };
+
+        let config = Config::from_toml(&toml("")).unwrap();
+        assert_eq!(config.history.max_entries, 1000);
+        assert!(config.history.max_age_secs.is_none());
+
+        let config = Config::from_toml(&toml("max_age_secs = 31536000")).unwrap();
+        assert_eq!(config.history.max_age_secs, Some(31536000));
 
-        Err(Ddns6Error::InvalidInterfaceId(format!(
-            "Invalid interface ID format: {}",
-            iid
-        )))
+        assert!(Config::from_toml(&toml("max_entries = 0")).is_err());
     }
 
-    #[allow(dead_code)]
-    pub fn get_host(&self, hostname: &str) -> Option<&HostMapping> {
-        self.hosts.iter().find(|h| h.hostname == hostname)
-    }
-}
+    #[test]
+    fn test_validate_reverse() {
+        let toml = |cloudflare: &str| {
+            format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+                {}
 
-    #[test]
-    fn test_validate_interface_id() {
-        let config = Config {
-            server: ServerConfig {
-                bind_address: "0.0.0.0:8080".to_string(),
-                workers: 4,
-            },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "test".to_string(),
-                ttl: 300,
-            },
-            hosts: vec![],
+                [reverse]
+                enabled = true
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::10"
+                "#,
+                cloudflare
+            )
         };
 
-        assert!(config.validate_interface_id("::1").is_ok());
-        assert!(config.validate_interface_id("::2").is_ok());
-        assert!(config
-            .validate_interface_id("::a1b2:c3d4:e5f6:7890")
-            .is_ok());
-        assert!(config.validate_interface_id("1").is_ok());
-        assert!(config.validate_interface_id("1234:5678:90ab:cdef").is_ok());
+        let config = Config::from_toml(&toml(
+            "zones = [\"8.7.6.5.4.3.2.1.8.b.d.0.1.0.0.2.ip6.arpa\"]\n[cloudflare.ownership]",
+        ))
+        .unwrap();
+        assert!(config.reverse.enabled);
+        assert!(Config::from_toml(&toml("auto_zones = true\n[cloudflare.ownership]")).is_ok());
+
+        assert!(Config::from_toml(&toml(
+            "zones = [\"8.7.6.5.4.3.2.1.8.b.d.0.1.0.0.2.ip6.arpa\"]"
+        ))
+        .is_err());
+        assert!(
+            Config::from_toml(&toml("zones = [\"example.com\"]\n[cloudflare.ownership]")).is_err()
+        );
+
+        // PowerDNS needs no ownership settings, as it marks its record sets.
+        let powerdns = |zone: &str| {
+            toml(&format!(
+                r#"
+                [[providers]]
+                name = "internal"
+                type = "powerdns"
+                api_url = "http://127.0.0.1:8081"
+                api_key = "key"
+                zones = ["example.com", "{}"]
+                "#,
+                zone
+            ))
+        };
+        assert!(Config::from_toml(&powerdns("8.b.d.0.1.0.0.2.ip6.arpa")).is_ok());
+        assert!(Config::from_toml(&powerdns("lan.example.com")).is_err());
     }
 
     #[test]
-    fn test_get_host() {
+    fn test_validate_duplicate_hostname() {
         let config = Config {
             server: ServerConfig {
                 bind_address: "0.0.0.0:8080".to_string(),
                 workers: 4,
+                allow_ula_prefix: false,
             },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
                 zone_id: "test".to_string(),
                 ttl: 300,
-            },
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
             hosts: vec![
                 HostMapping {
-                    hostname: "device1.example.com".to_string(),
+                    hostname: "test.example.com".to_string(),
                     interface_id: "::1".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
                 },
                 HostMapping {
-                    hostname: "device2.example.com".to_string(),
+                    hostname: "test.example.com".to_string(),
                     interface_id: "::2".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
                 },
             ],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
         };
 
-        assert!(config.get_host("device1.example.com").is_some());
-        assert!(config.get_host("device2.example.com").is_some());
-        assert!(config.get_host("nonexistent.example.com").is_none());
+        assert!(config.validate(&[]).is_err());
     }
 
     #[test]
-    fn test_validate_empty_bind_address() {
-        let config = Config {
-            server: ServerConfig {
-                bind_address: "".to_string(),
-                workers: 4,
-            },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "test".to_string(),
-                ttl: 300,
-            },
-            hosts: vec![HostMapping {
-                hostname: "test.example.com".to_string(),
-                interface_id: "::1".to_string(),
-            }],
+    fn test_ownership_marker_defaults() {
+        let comment = OwnershipConfig {
+            marker: None,
+            location: MarkerLocation::Comment,
+            force: false,
         };
+        assert_eq!(comment.marker(), "managed-by=ddns6");
 
-        assert!(config.validate().is_err());
+        let tag = OwnershipConfig {
+            marker: None,
+            location: MarkerLocation::Tag,
+            force: false,
+        };
+        assert_eq!(tag.marker(), "managed-by:ddns6");
     }
 
     #[test]
-    fn test_validate_empty_api_token() {
+    fn test_validate_tag_marker_format() {
         let config = Config {
             server: ServerConfig {
                 bind_address: "0.0.0.0:8080".to_string(),
                 workers: 4,
+                allow_ula_prefix: false,
             },
-            cloudflare: CloudflareConfig {
-                api_token: "".to_string(),
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
                 zone_id: "test".to_string(),
                 ttl: 300,
-            },
+                ownership: Some(OwnershipConfig {
+                    marker: Some("managed-by=ddns6".to_string()),
+                    location: MarkerLocation::Tag,
+                    force: false,
+                }),
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
             hosts: vec![HostMapping {
                 hostname: "test.example.com".to_string(),
                 interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
             }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
         };
 
-        assert!(config.validate().is_err());
+        assert!(config.validate(&[]).is_err());
     }
 
     #[test]
-    fn test_validate_empty_zone_id() {
-        let config = Config {
+    fn test_validate_cleanup_requires_ownership() {
+        let mut config = Config {
             server: ServerConfig {
                 bind_address: "0.0.0.0:8080".to_string(),
                 workers: 4,
+                allow_ula_prefix: false,
             },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "".to_string(),
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
+                zone_id: "test".to_string(),
                 ttl: 300,
-            },
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
             hosts: vec![HostMapping {
                 hostname: "test.example.com".to_string(),
                 interface_id: "::1".to_string(),
+                ttl: None,
+                proxied: None,
+                comment: None,
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
             }],
+            cleanup: CleanupConfig {
+                enabled: true,
+                interval_secs: None,
+            },
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
         };
 
-        assert!(config.validate().is_err());
-    }
+        assert!(config.validate(&[]).is_err());
 
-    #[test]
-    fn test_validate_no_hosts() {
-        let config = Config {
-            server: ServerConfig {
-                bind_address: "0.0.0.0:8080".to_string(),
-                workers: 4,
-            },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "test".to_string(),
-                ttl: 300,
-            },
-            hosts: vec![],
-        };
+        config.cloudflare.as_mut().unwrap().ownership = Some(OwnershipConfig {
+            marker: None,
+            location: MarkerLocation::Comment,
+            force: false,
+        });
+        assert!(config.validate(&[]).is_ok());
 
-        assert!(config.validate().is_err());
+        // PowerDNS marks every record set it writes.
+        config.cloudflare.as_mut().unwrap().ownership = None;
+        config.providers.push(ProviderConfig {
+            name: "internal".to_string(),
+            kind: ProviderKind::PowerDns(PowerDnsConfig {
+                api_url: "http://127.0.0.1:8081".to_string(),
+                api_key: "key".into(),
+                server_id: "localhost".to_string(),
+                zones: vec!["example.com".to_string()],
+                ttl: 300,
+                force: false,
+            }),
+        });
+        assert!(config.validate(&[]).is_ok());
     }
 
     #[test]
-    fn test_validate_empty_hostname() {
-        let config = Config {
+    fn test_validate_host_ttl_and_proxied() {
+        let mut config = Config {
             server: ServerConfig {
                 bind_address: "0.0.0.0:8080".to_string(),
                 workers: 4,
+                allow_ula_prefix: false,
             },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
+            cloudflare: Some(CloudflareConfig {
+                api_token: "test".into(),
+                api_token_file: None,
                 zone_id: "test".to_string(),
                 ttl: 300,
-            },
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
             hosts: vec![HostMapping {
-                hostname: "".to_string(),
+                hostname: "test.example.com".to_string(),
                 interface_id: "::1".to_string(),
+                ttl: Some(60),
+                proxied: None,
+                comment: Some("NAS".to_string()),
+                ipv4: false,
+                mac: None,
+                stable_privacy: None,
+                views: Vec::new(),
+                providers: Vec::new(),
             }],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
         };
+        assert!(config.validate(&[]).is_ok());
 
-        assert!(config.validate().is_err());
-    }
+        config.hosts[0].proxied = Some(true);
+        assert!(config.validate(&[]).is_err());
 
-    #[test]
-    fn test_validate_invalid_interface_id() {
-        let config = Config {
-            server: ServerConfig {
-                bind_address: "0.0.0.0:8080".to_string(),
-                workers: 4,
-            },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "test".to_string(),
-                ttl: 300,
-            },
-            hosts: vec![HostMapping {
-                hostname: "test.example.com".to_string(),
-                interface_id: "invalid::xyz::123".to_string(),
-            }],
-        };
+        config.hosts[0].ttl = Some(1);
+        assert!(config.validate(&[]).is_ok());
 
-        assert!(config.validate().is_err());
-    }
+        config.hosts[0].ttl = None;
+        assert!(config.validate(&[]).is_ok());
 
-    #[test]
-    fn test_validate_duplicate_hostname() {
-        let config = Config {
-            server: ServerConfig {
-                bind_address: "0.0.0.0:8080".to_string(),
-                workers: 4,
-            },
-            cloudflare: CloudflareConfig {
-                api_token: "test".to_string(),
-                zone_id: "test".to_string(),
+        config.hosts[0].ttl = Some(10);
+        config.hosts[0].proxied = None;
+        assert!(config.validate(&[]).is_err());
+
+        config.hosts[0].ttl = None;
+        config.cloudflare.as_mut().unwrap().ttl = 100_000;
+        assert!(config.validate(&[]).is_err());
+        config.cloudflare.as_mut().unwrap().ttl = 300;
+
+        // Short TTLs are fine for hosts not published to Cloudflare.
+        config.providers.push(ProviderConfig {
+            name: "internal".to_string(),
+            kind: ProviderKind::PowerDns(PowerDnsConfig {
+                api_url: "http://127.0.0.1:8081".to_string(),
+                api_key: "key".into(),
+                server_id: "localhost".to_string(),
+                zones: vec!["example.com".to_string()],
                 ttl: 300,
-            },
-            hosts: vec![
-                HostMapping {
-                    hostname: "test.example.com".to_string(),
-                    interface_id: "::1".to_string(),
-                },
-                HostMapping {
-                    hostname: "test.example.com".to_string(),
-                    interface_id: "::2".to_string(),
-                },
-            ],
-        };
+                force: false,
+            }),
+        });
+        config.hosts[0].providers = vec!["internal".to_string()];
+        config.hosts[0].ttl = Some(10);
+        assert!(config.validate(&[]).is_ok());
+        config.hosts[0].ttl = Some(1);
+        config.hosts[0].proxied = Some(true);
+        assert!(config.validate(&[]).is_ok());
+        config.hosts[0].ttl = Some(0);
+        assert!(config.validate(&[]).is_err());
 
-        assert!(config.validate().is_err());
+        config.hosts[0].providers = vec!["cloudflare".to_string(), "internal".to_string()];
+        config.hosts[0].ttl = Some(10);
+        config.hosts[0].proxied = None;
+        assert!(config.validate(&[]).is_err());
     }
 
     #[test]
     fn test_defaults() {
         assert_eq!(default_workers(), 4);
         assert_eq!(default_ttl(), 300);
+        assert_eq!(default_api_url(), "https://api.cloudflare.com/client/v4");
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_url = "http://127.0.0.1:9999/client/v4"
+            api_token = "token"
+            zone_id = "zone"
+
+            [[hosts]]
+            hostname = "device1.example.com"
+            interface_id = "::1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.cloudflare.as_ref().unwrap().api_url,
+            "http://127.0.0.1:9999/client/v4"
+        );
+        assert_eq!(config.cloudflare.as_ref().unwrap().ttl, 300);
+        assert_eq!(config.hosts.len(), 1);
+
+        assert!(Config::from_toml("[server]\nbind_address = 1").is_err());
     }
 
     #[test]
@@ -343,24 +3205,709 @@ mod tests {
             server: ServerConfig {
                 bind_address: "127.0.0.1:8080".to_string(),
                 workers: 2,
+                allow_ula_prefix: false,
             },
-            cloudflare: CloudflareConfig {
-                api_token: "my-api-token".to_string(),
+            cloudflare: Some(CloudflareConfig {
+                api_token: "my-api-token".into(),
+                api_token_file: None,
                 zone_id: "my-zone-id".to_string(),
                 ttl: 600,
-            },
+                ownership: None,
+                zones: Vec::new(),
+                auto_zones: false,
+                api_url: default_api_url(),
+            }),
             hosts: vec![
                 HostMapping {
                     hostname: "device1.example.com".to_string(),
                     interface_id: "::1".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
                 },
                 HostMapping {
                     hostname: "device2.example.com".to_string(),
                     interface_id: "::ffff:192.168.1.1".to_string(),
+                    ttl: None,
+                    proxied: None,
+                    comment: None,
+                    ipv4: false,
+                    mac: None,
+                    stable_privacy: None,
+                    views: Vec::new(),
+                    providers: Vec::new(),
                 },
             ],
+            cleanup: CleanupConfig::default(),
+            debounce: DebounceConfig::default(),
+            history: HistoryConfig::default(),
+            reverse: ReverseConfig::default(),
+            views: Vec::new(),
+            uplinks: Vec::new(),
+            webhooks: Vec::new(),
+            on_change: Vec::new(),
+            mqtt: None,
+            dns: None,
+            outputs: Vec::new(),
+            providers: Vec::new(),
+            host_groups: Vec::new(),
+            include: Vec::new(),
+        };
+
+        assert!(config.validate(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let lookup = |name: &str| match name {
+            "TOKEN" => Some("s3cret".to_string()),
+            "ZONE" => Some("example.com".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(interpolate("${TOKEN}", &lookup).unwrap(), "s3cret");
+        assert_eq!(
+            interpolate("nas.${ZONE} and tv.${ZONE}", &lookup).unwrap(),
+            "nas.example.com and tv.example.com"
+        );
+        assert_eq!(interpolate("$${TOKEN} $5", &lookup).unwrap(), "${TOKEN} $5");
+        assert!(interpolate("${MISSING}", &lookup).is_err());
+        assert!(interpolate("${TOKEN", &lookup).is_err());
+        assert_eq!(
+            interpolate("notify ${DDNS6_HOST} ${DDNS6_ADDRESS} ${ZONE}", &lookup).unwrap(),
+            "notify ${DDNS6_HOST} ${DDNS6_ADDRESS} example.com"
+        );
+
+        let mut value: toml::Value = toml::from_str(
+            r#"
+            [cloudflare]
+            api_token = "${TOKEN}"
+            zones = ["${ZONE}"]
+            "#,
+        )
+        .unwrap();
+        interpolate_value(&mut value, &lookup).unwrap();
+        assert_eq!(value["cloudflare"]["api_token"].as_str(), Some("s3cret"));
+        assert_eq!(
+            value["cloudflare"]["zones"][0].as_str(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn test_api_token_file() {
+        let path = std::env::temp_dir().join(format!("ddns6-token-{}", std::process::id()));
+        fs::write(&path, "file-token\n").unwrap();
+        let config = |token: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                {}
+                api_token_file = "{}"
+                zone_id = "zone"
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::1"
+                "#,
+                token,
+                path.display()
+            ))
+        };
+
+        let loaded = config("");
+        let conflicting = config("api_token = \"token\"");
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            loaded.unwrap().cloudflare.unwrap().api_token.expose(),
+            "file-token"
+        );
+        assert!(conflicting.is_err());
+        assert!(config("").is_err());
+    }
+
+    #[test]
+    fn test_secrets_redacted_from_debug() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "plaintext-token"
+            zone_id = "zone"
+
+            [[hosts]]
+            hostname = "nas.example.com"
+            interface_id = "::1"
+
+            [[webhooks]]
+            url = "https://example.com/hook"
+            secret = "webhook-secret"
+            "#,
+        )
+        .unwrap();
+
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("plaintext-token"));
+        assert!(!debug.contains("webhook-secret"));
+        assert!(debug.contains("<redacted>"));
+    }
+
+    #[test]
+    fn test_host_groups() {
+        let config = |groups: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                zone_id = "zone"
+
+                [[views]]
+                name = "lan"
+                prefix = "fd12:3456:789a:1::/64"
+
+                [[hosts]]
+                hostname = "router.example.com"
+                interface_id = "::1"
+
+                {}
+                "#,
+                groups
+            ))
+        };
+
+        let expanded = config(
+            r#"
+            [[host_groups]]
+            domain = "home.example.com"
+            ttl = 120
+            views = [{ view = "lan", domain = "home.lan" }]
+            hosts = [
+                { name = "nas", interface_id = "::10" },
+                { name = "printer", mac = "aa:bb:cc:dd:ee:ff" },
+            ]
+
+            [[host_groups]]
+            domain = "lab.example.com."
+            hosts = [{ name = "*", interface_id = "::20" }]
+            "#,
+        )
+        .unwrap();
+
+        assert!(expanded.host_groups.is_empty());
+        let hostnames: Vec<_> = expanded.hosts.iter().map(|h| h.hostname.as_str()).collect();
+        assert_eq!(
+            hostnames,
+            [
+                "router.example.com",
+                "nas.home.example.com",
+                "printer.home.example.com",
+                "*.lab.example.com"
+            ]
+        );
+        let nas = expanded.get_host("nas.home.example.com").unwrap();
+        assert_eq!(nas.ttl, Some(120));
+        assert_eq!(nas.views[0].hostname, "nas.home.lan");
+        let printer = expanded.get_host("printer.home.example.com").unwrap();
+        assert_eq!(printer.interface_id, "::a8bb:ccff:fedd:eeff");
+        assert!(expanded
+            .get_host("*.lab.example.com")
+            .unwrap()
+            .is_wildcard());
+
+        let collision = config(
+            r#"
+            [[host_groups]]
+            domain = "example.com"
+            hosts = [{ name = "Router", interface_id = "::2" }]
+            "#,
+        );
+        assert!(collision.is_err());
+        assert!(config(
+            r#"
+            [[host_groups]]
+            domain = ""
+            hosts = [{ name = "nas", interface_id = "::2" }]
+            "#,
+        )
+        .is_err());
+        assert!(config(
+            r#"
+            [[host_groups]]
+            domain = "example.com"
+            hosts = [{ name = "nas*", interface_id = "::2" }]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_valid_wildcard() {
+        assert!(valid_wildcard("*.lab.example.com"));
+        assert!(!valid_wildcard("*"));
+        assert!(!valid_wildcard("*."));
+        assert!(!valid_wildcard("a*.lab.example.com"));
+        assert!(!valid_wildcard("*.*.example.com"));
+        assert!(!valid_wildcard("lab.*.example.com"));
+    }
+
+    #[test]
+    fn test_validate_hostname() {
+        assert!(validate_hostname("nas.example.com").is_ok());
+        assert!(validate_hostname("my-nas2.Example.com").is_ok());
+        assert!(validate_hostname("xn--bcher-kva.example.com").is_ok());
+        assert!(validate_hostname("nas.example..com").is_err());
+        assert!(validate_hostname(".example.com").is_err());
+        assert!(validate_hostname("-nas.example.com").is_err());
+        assert!(validate_hostname("nas-.example.com").is_err());
+        assert!(validate_hostname("nas_1.example.com").is_err());
+        assert!(validate_hostname(&format!("{}.example.com", "a".repeat(64))).is_err());
+        assert!(validate_hostname(&format!("{}.example.com", "a".repeat(63))).is_ok());
+        assert!(validate_hostname(&vec!["a".repeat(63); 4].join(".")).is_err());
+    }
+
+    #[test]
+    fn test_normalize_hostname() {
+        assert_eq!(
+            normalize_hostname("NAS.Example.com.").unwrap(),
+            "nas.example.com"
+        );
+        assert_eq!(
+            normalize_hostname("bücher.example.com").unwrap(),
+            "xn--bcher-kva.example.com"
+        );
+        assert_eq!(
+            normalize_hostname("*.Lab.example.com").unwrap(),
+            "*.lab.example.com"
+        );
+    }
+
+    #[test]
+    fn test_zones() {
+        assert!(in_zone("nas.example.com", "example.com"));
+        assert!(in_zone("NAS.Example.com.", "example.com"));
+        assert!(in_zone("example.com", "Example.COM."));
+        assert!(!in_zone("nas.otherexample.com", "example.com"));
+        assert!(!in_zone("example.com", "nas.example.com"));
+
+        assert!(is_reverse_zone("8.b.d.0.1.0.0.2.ip6.arpa."));
+        assert!(is_reverse_zone("IP6.ARPA"));
+        assert!(!is_reverse_zone("fooip6.arpa"));
+
+        let zones = ["example.com".to_string(), "lan.example.com".to_string()];
+        let zone = |hostname| longest_zone(&zones, hostname, String::as_str).map(String::as_str);
+        assert_eq!(zone("nas.lan.example.com"), Some("lan.example.com"));
+        assert_eq!(zone("nas.example.com"), Some("example.com"));
+        assert_eq!(zone("nas.example.net"), None);
+    }
+
+    #[test]
+    fn test_validate_hostnames_on_load() {
+        let config = |zones: &str, hostname: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [cloudflare]
+                api_token = "token"
+                {}
+
+                [[hosts]]
+                hostname = "{}"
+                interface_id = "::1"
+                "#,
+                zones, hostname
+            ))
+        };
+        let zones = r#"zones = ["example.com", "Bücher.example"]"#;
+
+        assert!(config(zones, "nas.example.com").is_ok());
+        assert!(config(zones, "example.com").is_ok());
+        let idn = config(zones, "Straße.Bücher.example").unwrap();
+        assert_eq!(idn.hosts[0].hostname, "xn--strae-oqa.xn--bcher-kva.example");
+        assert!(config(zones, "nas.example..com").is_err());
+        assert!(config(zones, "nas.example.net").is_err());
+        assert!(config(zones, "nasexample.com").is_err());
+        assert!(config("zone_id = \"zone\"", "nas.example.net").is_ok());
+        assert!(config("auto_zones = true", "nas.example.net").is_ok());
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.toml", "nas.toml"));
+        assert!(wildcard_match("*.toml", ".toml"));
+        assert!(wildcard_match("host-?.toml", "host-1.toml"));
+        assert!(wildcard_match("*-*.toml", "team-a.toml"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(!wildcard_match("*.toml", "nas.toml.bak"));
+        assert!(!wildcard_match("host-?.toml", "host-10.toml"));
+        assert!(!wildcard_match("*-*.toml", "team.toml"));
+    }
+
+    #[test]
+    fn test_include_hosts() {
+        let dir = std::env::temp_dir().join(format!("ddns6-include-{}", std::process::id()));
+        let hosts_d = dir.join("hosts.d");
+        let team_d = dir.join("team.d");
+        fs::create_dir_all(&hosts_d).unwrap();
+        fs::create_dir_all(&team_d).unwrap();
+        let write = |path: &Path, content: &str| fs::write(path, content).unwrap();
+
+        write(
+            &dir.join("config.toml"),
+            r#"
+            include = ["hosts.d/*.toml"]
+
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "token"
+            zone_id = "zone"
+
+            [[hosts]]
+            hostname = "router.example.com"
+            interface_id = "::1"
+            "#,
+        );
+        write(
+            &hosts_d.join("b-nas.toml"),
+            "[[hosts]]\nhostname = \"nas.example.com\"\ninterface_id = \"::10\"\n",
+        );
+        write(
+            &hosts_d.join("a-lab.toml"),
+            "[[host_groups]]\ndomain = \"lab.example.com\"\nhosts = [{ name = \"*\", interface_id = \"::20\" }]\n",
+        );
+        write(&hosts_d.join(".b-nas.toml.swp"), "not toml");
+        write(&hosts_d.join("README"), "not toml");
+        write(
+            &team_d.join("tv.toml"),
+            "[[hosts]]\nhostname = \"tv.example.com\"\ninterface_id = \"::30\"\n",
+        );
+
+        let config = Config::from_files(&dir.join("config.toml"), Some(&team_d)).unwrap();
+        let hostnames: Vec<_> = config.hosts.iter().map(|h| h.hostname.as_str()).collect();
+
+        write(
+            &team_d.join("nas.toml"),
+            "[[hosts]]\nhostname = \"NAS.example.com\"\ninterface_id = \"::11\"\n",
+        );
+        let duplicate = Config::from_files(&dir.join("config.toml"), Some(&team_d));
+
+        write(
+            &team_d.join("nas.toml"),
+            "[[hosts]]\nhostname = \"pi.example.com\"\ninterface_id = 17\n",
+        );
+        let invalid = Config::from_files(&dir.join("config.toml"), Some(&team_d));
+
+        write(
+            &team_d.join("nas.toml"),
+            "# Team hosts\n[[hosts]]\nhostname = \"pi.example.com\"\ninterface_id = \"::40\"\n\n\
+             [[hosts]]\nhostname = \"bad_host!.example.com\"\ninterface_id = \"::41\"\n",
+        );
+        let invalid_host = Config::from_files(&dir.join("config.toml"), Some(&team_d));
+
+        write(
+            &team_d.join("nas.toml"),
+            "[[host_groups]]\ndomain = \"lab.example.com\"\nttl = 5\nhosts = [{ name = \"pi\", interface_id = \"::50\" }]\n",
+        );
+        let invalid_group = Config::from_files(&dir.join("config.toml"), Some(&team_d));
+
+        write(
+            &team_d.join("nas.toml"),
+            "[server]\nbind_address = \"127.0.0.1:8081\"\n",
+        );
+        let not_hosts = Config::from_files(&dir.join("config.toml"), Some(&team_d));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            hostnames,
+            [
+                "router.example.com",
+                "*.lab.example.com",
+                "nas.example.com",
+                "tv.example.com"
+            ]
+        );
+
+        let duplicate = duplicate.unwrap_err().to_string();
+        assert!(duplicate.contains("nas.example.com"));
+        assert!(duplicate.contains("nas.toml"));
+        assert!(duplicate.contains("b-nas.toml"));
+
+        let invalid = invalid.unwrap_err().to_string();
+        assert!(invalid.contains("nas.toml"));
+        assert!(invalid.contains("line 3"));
+
+        let invalid_host = invalid_host.unwrap_err().to_string();
+        assert!(invalid_host.contains("nas.toml:6: Invalid hostname bad_host!.example.com"));
+
+        let invalid_group = invalid_group.unwrap_err().to_string();
+        assert!(invalid_group.contains("nas.toml:1: ttl for pi.lab.example.com"));
+
+        assert!(not_hosts.is_err());
+    }
+
+    #[test]
+    fn test_host_errors_name_file_and_line() {
+        let dir = std::env::temp_dir().join(format!("ddns6-located-{}", std::process::id()));
+        let team_d = dir.join("team.d");
+        fs::create_dir_all(&team_d).unwrap();
+        let config_path = dir.join("config.toml");
+        let load = |hosts: &str, team: &str| {
+            fs::write(
+                &config_path,
+                format!(
+                    "[server]\nbind_address = \"127.0.0.1:8080\"\n\n\
+                     [cloudflare]\napi_token = \"token\"\nzone_id = \"zone\"\n\n\
+                     [[views]]\nname = \"lan\"\nprefix = \"fd00::/64\"\n\n{}",
+                    hosts
+                ),
+            )
+            .unwrap();
+            fs::write(team_d.join("team.toml"), team).unwrap();
+            Config::from_files(&config_path, Some(&team_d))
+                .unwrap_err()
+                .to_string()
+        };
+        let main = format!("{}:", config_path.display());
+        let team = format!("{}:", team_d.join("team.toml").display());
+
+        let invalid = load(
+            "[[hosts]]\nhostname = \"nas.example.com\"\ninterface_id = \"::10\"\n\n\
+             [[hosts]]\nhostname = \"bad_host!.example.com\"\ninterface_id = \"::11\"\n",
+            "",
+        );
+        assert!(
+            invalid.contains(&format!("{}16: Invalid hostname bad_host!", main)),
+            "{}",
+            invalid
+        );
+
+        // A view hostname of an included host collides with a main-file host.
+        let duplicate = load(
+            "[[hosts]]\nhostname = \"nas.lan.example.com\"\ninterface_id = \"::10\"\n",
+            "\n[[hosts]]\nhostname = \"nas.example.com\"\ninterface_id = \"::10\"\n\
+             views = [{ view = \"lan\", hostname = \"nas.lan.example.com\" }]\n",
+        );
+        assert!(
+            duplicate.contains(&format!(
+                "{}2: Duplicate hostname nas.lan.example.com, already configured in {}12",
+                team, main
+            )),
+            "{}",
+            duplicate
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dns() {
+        let config = |sections: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::1"
+                "#,
+                sections
+            ))
+        };
+        let dns = |extra: &str| {
+            format!(
+                "[dns]\nbind_address = \"127.0.0.1:5353\"\nzones = [\"Example.com\", \"8.b.d.0.1.0.0.2.ip6.arpa\"]\n{}",
+                extra
+            )
+        };
+
+        let loaded = config(&dns("a_records = true\nptr_records = true")).unwrap();
+        assert!(loaded.cloudflare.is_none());
+        let loaded = loaded.dns.unwrap();
+        assert_eq!(loaded.zones[0], "example.com");
+        assert_eq!(loaded.ttl, 60);
+
+        assert!(config("").is_err());
+        assert!(config("[dns]\nbind_address = \"localhost\"\nzones = [\"example.com\"]").is_err());
+        assert!(config("[dns]\nbind_address = \"127.0.0.1:53\"\nzones = []").is_err());
+        assert!(config(
+            "[dns]\nbind_address = \"127.0.0.1:53\"\nzones = [\"example.com\"]\nptr_records = true"
+        )
+        .is_err());
+        assert!(
+            config("[dns]\nbind_address = \"127.0.0.1:53\"\nzones = [\"example.net\"]").is_err()
+        );
+        assert!(config(&format!("{}\n[reverse]\nenabled = true", dns(""))).is_err());
+        assert!(config(&format!(
+            "{}\n[cloudflare]\napi_token = \"token\"\nzone_id = \"zone\"",
+            dns("")
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_outputs() {
+        let config = |outputs: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::1"
+                "#,
+                outputs
+            ))
+        };
+
+        let loaded =
+            config("[[outputs]]\nformat = \"hosts\"\npath = \"/run/ddns6/hosts\"").unwrap();
+        assert!(loaded.cloudflare.is_none());
+        assert_eq!(loaded.outputs[0].format, OutputFormat::Hosts);
+        assert_eq!(loaded.outputs[0].ttl, 300);
+        assert_eq!(loaded.outputs[0].timeout_secs, 30);
+
+        assert!(config("[[outputs]]\nformat = \"bind\"\npath = \"/run/ddns6/zone\"").is_err());
+        assert!(config("[[outputs]]\nformat = \"zone\"\npath = \"\"").is_err());
+        assert!(config(
+            "[[outputs]]\nformat = \"zone\"\npath = \"/run/ddns6/zone\"\nreload_command = \" \""
+        )
+        .is_err());
+        assert!(config(
+            "[[outputs]]\nformat = \"zone\"\npath = \"/run/ddns6/zone\"\ntimeout_secs = 0"
+        )
+        .is_err());
+        assert!(config(
+            "[[outputs]]\nformat = \"zone\"\npath = \"/run/a\"\n\
+             [[outputs]]\nformat = \"hosts\"\npath = \"/run/a\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_providers() {
+        let config = |cloudflare: &str, hosts: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                {}
+
+                [[providers]]
+                name = "internal"
+                type = "powerdns"
+                api_url = "http://127.0.0.1:8081"
+                api_key = "key"
+                zones = ["Lan.Example.com"]
+
+                {}
+                "#,
+                cloudflare, hosts
+            ))
+        };
+        let cloudflare = "[cloudflare]\napi_token = \"token\"\nzones = [\"example.com\"]";
+        let host = |hostname: &str, providers: &str| {
+            format!(
+                "[[hosts]]\nhostname = \"{}\"\ninterface_id = \"::1\"\nproviders = {}",
+                hostname, providers
+            )
+        };
+
+        let loaded = config(cloudflare, &host("nas.lan.example.com", "[]")).unwrap();
+        let ProviderKind::PowerDns(powerdns) = &loaded.providers[0].kind else {
+            panic!("expected a PowerDNS provider");
+        };
+        assert_eq!(powerdns.zones, vec!["lan.example.com"]);
+        assert_eq!(powerdns.server_id, "localhost");
+        assert!(loaded.hosts[0].uses_provider("internal"));
+        assert!(loaded.hosts[0].uses_provider(CLOUDFLARE_PROVIDER));
+
+        // Hosts only need to lie within the zones of their own providers.
+        assert!(config(cloudflare, &host("www.example.com", "[\"cloudflare\"]")).is_ok());
+        assert!(config(cloudflare, &host("www.example.com", "[]")).is_err());
+        assert!(config("", &host("nas.lan.example.com", "[\"internal\"]")).is_ok());
+        assert!(config("", &host("nas.lan.example.com", "[\"cloudflare\"]")).is_err());
+        assert!(config(cloudflare, &host("nas.lan.example.com", "[\"other\"]")).is_err());
+
+        let renamed = |name: &str| {
+            config(
+                &format!(
+                    "[[providers]]\nname = \"{}\"\ntype = \"powerdns\"\napi_url = \"http://pdns\"\napi_key = \"key\"\nzones = [\"example.com\"]",
+                    name
+                ),
+                &host("nas.lan.example.com", "[]"),
+            )
+        };
+        assert!(renamed("external").is_ok());
+        assert!(renamed("internal").is_err());
+        assert!(renamed("cloudflare").is_err());
+        assert!(renamed("").is_err());
+    }
+
+    #[test]
+    fn test_http_provider() {
+        let config = |update: &str| {
+            Config::from_toml(&format!(
+                r#"
+                [server]
+                bind_address = "127.0.0.1:8080"
+
+                [[providers]]
+                name = "desec"
+                type = "http"
+                zones = ["example.com"]
+
+                [providers.update]
+                {}
+
+                [[hosts]]
+                hostname = "nas.example.com"
+                interface_id = "::1"
+                "#,
+                update
+            ))
+        };
+
+        let loaded = config(
+            "url = \"https://desec.io/api/v1/domains/{zone}/rrsets/\"\n\
+             headers = { Authorization = \"Token secret\" }\n\
+             checks = [{ path = \"$.records[0]\", equals = \"{address}\" }]",
+        )
+        .unwrap();
+        let ProviderKind::Http(http) = &loaded.providers[0].kind else {
+            panic!("expected an HTTP provider");
         };
+        assert_eq!(http.update.method, "POST");
+        assert_eq!(http.ttl, 300);
+        assert!(http.delete.is_none());
+        assert!(!format!("{:?}", http).contains("Token secret"));
 
-        assert!(config.validate().is_ok());
+        assert!(config("url = \"https://desec.io\"\nmethod = \"PUT\"").is_ok());
+        assert!(config("url = \"https://desec.io\"\nmethod = \"P T\"").is_err());
+        assert!(config("url = \"desec.io\"").is_err());
+        assert!(config("url = \"https://desec.io\"\nchecks = [{ path = \"records\" }]").is_err());
     }
 }
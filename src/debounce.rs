@@ -0,0 +1,234 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::ipv6::Ipv6Prefix;
+
+/// Period over which prefix changes are counted for flap detection.
+pub const FLAP_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Prefix changes held back per uplink until the settle window elapsed.
+#[derive(Debug, Clone, Default)]
+pub struct Debouncer {
+    pending: Arc<RwLock<PendingPrefixes>>,
+}
+
+#[derive(Debug, Default)]
+struct PendingPrefixes {
+    by_uplink: HashMap<Option<String>, PendingPrefix>,
+    next_generation: u64,
+}
+
+/// Prefix held back for one uplink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingPrefix {
+    pub prefix: Ipv6Prefix,
+    /// IPv4 address of the latest request that reported the prefix.
+    pub client_ipv4: Option<Ipv4Addr>,
+    generation: u64,
+}
+
+impl Debouncer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `prefix` the pending prefix of `uplink`. Returns the generation
+    /// to pass to [`Debouncer::take`] once the settle window elapsed, or
+    /// `None` if `prefix` was already pending and its window keeps running.
+    /// Either way, `client_ipv4` replaces the IPv4 address held with it.
+    pub async fn hold(
+        &self,
+        uplink: Option<&str>,
+        prefix: Ipv6Prefix,
+        client_ipv4: Option<Ipv4Addr>,
+    ) -> Option<u64> {
+        let mut pending = self.pending.write().await;
+        let key = uplink.map(str::to_string);
+        if let Some(held) = pending
+            .by_uplink
+            .get_mut(&key)
+            .filter(|held| held.prefix == prefix)
+        {
+            held.client_ipv4 = client_ipv4;
+            return None;
+        }
+
+        pending.next_generation += 1;
+        let generation = pending.next_generation;
+        pending.by_uplink.insert(
+            key,
+            PendingPrefix {
+                prefix,
+                client_ipv4,
+                generation,
+            },
+        );
+        Some(generation)
+    }
+
+    /// Removes the pending prefix of `uplink` if it is still the one held as
+    /// `generation`, i.e. no other prefix was reported in the meantime.
+    pub async fn take(&self, uplink: Option<&str>, generation: u64) -> Option<PendingPrefix> {
+        let mut pending = self.pending.write().await;
+        let key = uplink.map(str::to_string);
+        if pending.by_uplink.get(&key)?.generation != generation {
+            return None;
+        }
+        pending.by_uplink.remove(&key)
+    }
+
+    /// Drops the pending prefix of `uplink`, if any.
+    pub async fn cancel(&self, uplink: Option<&str>) {
+        let mut pending = self.pending.write().await;
+        pending.by_uplink.remove(&uplink.map(str::to_string));
+    }
+
+    /// Pending prefix of `uplink`, if any.
+    pub async fn pending(&self, uplink: Option<&str>) -> Option<Ipv6Prefix> {
+        let pending = self.pending.read().await;
+        pending
+            .by_uplink
+            .get(&uplink.map(str::to_string))
+            .map(|held| held.prefix)
+    }
+}
+
+/// Counts how often the reported prefix of each uplink changed within the
+/// last [`FLAP_WINDOW`].
+#[derive(Debug, Clone, Default)]
+pub struct FlapDetector {
+    uplinks: Arc<RwLock<HashMap<Option<String>, PrefixChanges>>>,
+}
+
+#[derive(Debug)]
+struct PrefixChanges {
+    last: Ipv6Prefix,
+    changes: VecDeque<Instant>,
+}
+
+impl FlapDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a report of `prefix` for `uplink`. Returns the number of
+    /// changes within the window if the prefix differs from the last report.
+    pub async fn observe(
+        &self,
+        uplink: Option<&str>,
+        prefix: Ipv6Prefix,
+        now: Instant,
+    ) -> Option<usize> {
+        let mut uplinks = self.uplinks.write().await;
+        let entry = uplinks
+            .entry(uplink.map(str::to_string))
+            .or_insert_with(|| PrefixChanges {
+                last: prefix,
+                changes: VecDeque::new(),
+            });
+
+        prune(&mut entry.changes, now);
+        if entry.last == prefix {
+            return None;
+        }
+
+        entry.last = prefix;
+        entry.changes.push_back(now);
+        Some(entry.changes.len())
+    }
+
+    /// Number of prefix changes of `uplink` within the window before `now`.
+    pub async fn changes(&self, uplink: Option<&str>, now: Instant) -> usize {
+        let uplinks = self.uplinks.read().await;
+        uplinks
+            .get(&uplink.map(str::to_string))
+            .map(|entry| {
+                entry
+                    .changes
+                    .iter()
+                    .filter(|at| now.duration_since(**at) < FLAP_WINDOW)
+                    .count()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn prune(changes: &mut VecDeque<Instant>, now: Instant) {
+    while changes
+        .front()
+        .is_some_and(|at| now.duration_since(*at) >= FLAP_WINDOW)
+    {
+        changes.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(cidr: &str) -> Ipv6Prefix {
+        Ipv6Prefix::from_cidr(cidr).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_debouncer() {
+        let debouncer = Debouncer::new();
+        let first = prefix("2001:db8:1::/64");
+        let second = prefix("2001:db8:2::/64");
+
+        let generation = debouncer.hold(None, first, None).await.unwrap();
+        assert_eq!(debouncer.hold(None, first, None).await, None);
+        assert_eq!(debouncer.pending(None).await, Some(first));
+
+        let newer = debouncer.hold(None, second, None).await.unwrap();
+        assert_eq!(debouncer.take(None, generation).await, None);
+        assert_eq!(debouncer.take(None, newer).await.unwrap().prefix, second);
+        assert_eq!(debouncer.pending(None).await, None);
+
+        let generation = debouncer.hold(Some("isp1"), first, None).await.unwrap();
+        debouncer.cancel(Some("isp1")).await;
+        assert_eq!(debouncer.take(Some("isp1"), generation).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_debouncer_keeps_latest_ipv4() {
+        let debouncer = Debouncer::new();
+        let first = Ipv4Addr::new(198, 51, 100, 1);
+        let second = Ipv4Addr::new(198, 51, 100, 2);
+
+        let generation = debouncer
+            .hold(None, prefix("2001:db8:1::/64"), Some(first))
+            .await
+            .unwrap();
+        assert_eq!(
+            debouncer
+                .hold(None, prefix("2001:db8:1::/64"), Some(second))
+                .await,
+            None
+        );
+        let settled = debouncer.take(None, generation).await.unwrap();
+        assert_eq!(settled.client_ipv4, Some(second));
+    }
+
+    #[tokio::test]
+    async fn test_flap_detector() {
+        let detector = FlapDetector::new();
+        let first = prefix("2001:db8:1::/64");
+        let second = prefix("2001:db8:2::/64");
+        let start = Instant::now();
+
+        assert_eq!(detector.observe(None, first, start).await, None);
+        assert_eq!(detector.observe(None, first, start).await, None);
+        assert_eq!(detector.observe(None, second, start).await, Some(1));
+        assert_eq!(detector.observe(None, first, start).await, Some(2));
+        assert_eq!(detector.changes(None, start).await, 2);
+        assert_eq!(detector.changes(Some("isp1"), start).await, 0);
+
+        let later = start + FLAP_WINDOW;
+        assert_eq!(detector.changes(None, later).await, 0);
+        assert_eq!(detector.observe(None, second, later).await, Some(1));
+    }
+}
@@ -0,0 +1,803 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tracing::{debug, warn};
+
+use crate::config::{in_zone, is_reverse_zone, longest_zone, DnsConfig};
+use crate::error::Result;
+use crate::history::unix_time;
+use crate::ipv6::reverse_name;
+use crate::record::RecordType;
+use crate::state::StateCache;
+
+const TYPE_A: u16 = 1;
+const TYPE_NS: u16 = 2;
+const TYPE_SOA: u16 = 6;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+const RCODE_FORMERR: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+const RCODE_NOTIMP: u16 = 4;
+const RCODE_REFUSED: u16 = 5;
+
+const FLAG_QR: u16 = 0x8000;
+const FLAG_AA: u16 = 0x0400;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+
+const HEADER_LEN: usize = 12;
+
+/// Largest response sent over UDP; clients retry over TCP when truncated.
+const UDP_PAYLOAD: usize = 512;
+
+/// Idle TCP connections are closed after this long.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Refresh, retry and expire of the SOA record.
+const SOA_TIMERS: [u32; 3] = [3600, 600, 1_209_600];
+
+/// Compression pointer to the name in the question section.
+const QUESTION_NAME: [u8; 2] = [0xc0, HEADER_LEN as u8];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(String),
+    Ns(String),
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+    },
+}
+
+impl RData {
+    fn record_type(&self) -> u16 {
+        match self {
+            RData::A(_) => TYPE_A,
+            RData::Aaaa(_) => TYPE_AAAA,
+            RData::Ptr(_) => TYPE_PTR,
+            RData::Ns(_) => TYPE_NS,
+            RData::Soa { .. } => TYPE_SOA,
+        }
+    }
+}
+
+/// A record of a zone; `owner` is lowercase without a trailing dot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    owner: String,
+    data: RData,
+}
+
+#[derive(Debug, Default)]
+struct Answer {
+    rcode: u16,
+    answers: Vec<Record>,
+    authority: Vec<Record>,
+}
+
+#[derive(Debug)]
+struct Question {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    /// Offset just past the question in the query.
+    end: usize,
+}
+
+/// Answers queries for the zones of `[dns]` from the published records.
+#[derive(Debug)]
+pub struct DnsServer {
+    config: DnsConfig,
+    state_cache: StateCache,
+    /// Serial at startup; every change to the records adds one.
+    serial_base: u32,
+}
+
+impl DnsServer {
+    pub fn new(config: &DnsConfig, state_cache: StateCache) -> Self {
+        Self {
+            config: config.clone(),
+            state_cache,
+            serial_base: unix_time() as u32,
+        }
+    }
+
+    /// Builds the response to `query`, at most `max_len` bytes long.
+    /// Returns `None` for messages that do not deserve an answer.
+    pub async fn respond(&self, query: &[u8], max_len: usize) -> Option<Vec<u8>> {
+        if query.len() < HEADER_LEN || read_u16(query, 2)? & FLAG_QR != 0 {
+            return None;
+        }
+        let flags = read_u16(query, 2)?;
+        let opcode = (flags >> 11) & 0x0f;
+
+        let ttl = self.config.ttl;
+        let question = match read_u16(query, 4)? {
+            1 => parse_question(query),
+            _ => None,
+        };
+        let Some(question) = question else {
+            return Some(header_only(query, RCODE_FORMERR));
+        };
+        if opcode != 0 {
+            return Some(response(
+                query,
+                &question,
+                &refused(RCODE_NOTIMP),
+                ttl,
+                max_len,
+            ));
+        }
+        if question.qclass != CLASS_IN && question.qclass != CLASS_ANY {
+            return Some(response(
+                query,
+                &question,
+                &refused(RCODE_REFUSED),
+                ttl,
+                max_len,
+            ));
+        }
+
+        let name = question.name.to_ascii_lowercase();
+        let Some(zone) = self.zone_for(&name) else {
+            debug!("Refusing DNS query for {} outside the served zones", name);
+            return Some(response(
+                query,
+                &question,
+                &refused(RCODE_REFUSED),
+                ttl,
+                max_len,
+            ));
+        };
+
+        let records = self.zone_records(zone).await;
+        let answer = resolve(zone, &records, &name, question.qtype);
+        Some(response(query, &question, &answer, ttl, max_len))
+    }
+
+    /// The longest served zone containing `name`.
+    fn zone_for(&self, name: &str) -> Option<&str> {
+        longest_zone(&self.config.zones, name, String::as_str).map(String::as_str)
+    }
+
+    /// The SOA and NS records of `zone` and the published records within it.
+    async fn zone_records(&self, zone: &str) -> Vec<Record> {
+        let serial = self
+            .serial_base
+            .wrapping_add(self.state_cache.changes() as u32);
+        let nameserver = self
+            .config
+            .nameserver
+            .clone()
+            .unwrap_or_else(|| format!("ns.{}", zone));
+        let hostmaster = self
+            .config
+            .hostmaster
+            .clone()
+            .unwrap_or_else(|| format!("hostmaster.{}", zone));
+
+        let mut records = vec![
+            Record {
+                owner: zone.to_string(),
+                data: RData::Soa {
+                    mname: nameserver.clone(),
+                    rname: hostmaster,
+                    serial,
+                },
+            },
+            Record {
+                owner: zone.to_string(),
+                data: RData::Ns(nameserver),
+            },
+        ];
+
+        let reverse = self.config.ptr_records && is_reverse_zone(zone);
+        for ((hostname, _, record_type), state) in self.state_cache.list_all().await {
+            match (record_type, state.address) {
+                (RecordType::Aaaa, IpAddr::V6(address)) => {
+                    if reverse {
+                        let name = reverse_name(address);
+                        if in_zone(&name, zone) && !hostname.starts_with("*.") {
+                            push_unique(
+                                &mut records,
+                                Record {
+                                    owner: name,
+                                    data: RData::Ptr(hostname.clone()),
+                                },
+                            );
+                        }
+                    }
+                    if in_zone(&hostname, zone) {
+                        push_unique(
+                            &mut records,
+                            Record {
+                                owner: hostname,
+                                data: RData::Aaaa(address),
+                            },
+                        );
+                    }
+                }
+                (RecordType::A, IpAddr::V4(address))
+                    if self.config.a_records && in_zone(&hostname, zone) =>
+                {
+                    push_unique(
+                        &mut records,
+                        Record {
+                            owner: hostname,
+                            data: RData::A(address),
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        records
+    }
+}
+
+/// Binds the UDP and TCP sockets of `config` and answers queries in the
+/// background. Returns the bound address.
+pub async fn spawn(config: &DnsConfig, state_cache: StateCache) -> Result<SocketAddr> {
+    let udp = UdpSocket::bind(&config.bind_address).await?;
+    let addr = udp.local_addr()?;
+    // Bind TCP to the same port, even when the configured port is 0.
+    let tcp = TcpListener::bind(addr).await?;
+
+    let server = Arc::new(DnsServer::new(config, state_cache));
+    tokio::spawn(serve_udp(server.clone(), udp));
+    tokio::spawn(serve_tcp(server, tcp));
+
+    Ok(addr)
+}
+
+async fn serve_udp(server: Arc<DnsServer>, socket: UdpSocket) {
+    let mut buf = vec![0; u16::MAX as usize];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Failed to receive DNS query: {}", e);
+                continue;
+            }
+        };
+
+        if let Some(response) = server.respond(&buf[..len], UDP_PAYLOAD).await {
+            if let Err(e) = socket.send_to(&response, peer).await {
+                warn!("Failed to send DNS response to {}: {}", peer, e);
+            }
+        }
+    }
+}
+
+async fn serve_tcp(server: Arc<DnsServer>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let server = server.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_tcp(&server, stream).await {
+                        debug!("DNS connection from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept DNS connection: {}", e),
+        }
+    }
+}
+
+/// Answers length-prefixed queries until the client closes the connection
+/// or stays idle.
+async fn handle_tcp(server: &DnsServer, mut stream: TcpStream) -> std::io::Result<()> {
+    loop {
+        let len = match tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_u16()).await {
+            Ok(Ok(len)) => len,
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(()),
+        };
+
+        let mut query = vec![0; len as usize];
+        tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut query))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+        if let Some(response) = server.respond(&query, u16::MAX as usize).await {
+            stream.write_u16(response.len() as u16).await?;
+            stream.write_all(&response).await?;
+        }
+    }
+}
+
+/// Looks up `name` in the records of `zone`, synthesizing answers from a
+/// wildcard as described in RFC 4592.
+fn resolve(zone: &str, records: &[Record], name: &str, qtype: u16) -> Answer {
+    let soa: Vec<Record> = records
+        .iter()
+        .filter(|record| record.owner == zone && record.data.record_type() == TYPE_SOA)
+        .cloned()
+        .collect();
+    let exists = |name: &str| records.iter().any(|record| in_zone(&record.owner, name));
+
+    let owner = if exists(name) {
+        name.to_string()
+    } else {
+        let closest_encloser = ancestors(name)
+            .take_while(|ancestor| in_zone(ancestor, zone))
+            .find(|ancestor| exists(ancestor));
+        match closest_encloser {
+            Some(encloser) if has_records(records, &format!("*.{}", encloser)) => {
+                format!("*.{}", encloser)
+            }
+            _ => {
+                return Answer {
+                    rcode: RCODE_NXDOMAIN,
+                    answers: Vec::new(),
+                    authority: soa,
+                };
+            }
+        }
+    };
+
+    let answers: Vec<Record> = records
+        .iter()
+        .filter(|record| record.owner == owner)
+        .filter(|record| qtype == TYPE_ANY || record.data.record_type() == qtype)
+        .cloned()
+        .collect();
+    let authority = if answers.is_empty() { soa } else { Vec::new() };
+
+    Answer {
+        rcode: 0,
+        answers,
+        authority,
+    }
+}
+
+/// Adds `record` unless it is already there, e.g. the same address published
+/// on two uplinks.
+fn push_unique(records: &mut Vec<Record>, record: Record) {
+    if !records.contains(&record) {
+        records.push(record);
+    }
+}
+
+fn has_records(records: &[Record], owner: &str) -> bool {
+    records.iter().any(|record| record.owner == owner)
+}
+
+/// The names above `name`, nearest first.
+fn ancestors(name: &str) -> impl Iterator<Item = &str> {
+    name.match_indices('.').map(move |(i, _)| &name[i + 1..])
+}
+
+fn refused(rcode: u16) -> Answer {
+    Answer {
+        rcode,
+        ..Answer::default()
+    }
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    let bytes = message.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Parses the only question of `query`. Labels are joined with dots as they
+/// are; names with dots inside a label simply match no record.
+fn parse_question(query: &[u8]) -> Option<Question> {
+    let mut labels = Vec::new();
+    let mut offset = HEADER_LEN;
+    loop {
+        let len = *query.get(offset)? as usize;
+        offset += 1;
+        match len {
+            0 => break,
+            // The question is the first name, so there is nothing to point to.
+            1..=63 => {
+                let label = query.get(offset..offset + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += len;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(Question {
+        name: labels.join("."),
+        qtype: read_u16(query, offset)?,
+        qclass: read_u16(query, offset + 2)?,
+        end: offset + 4,
+    })
+}
+
+/// A response without a question, for queries that could not be parsed.
+fn header_only(query: &[u8], rcode: u16) -> Vec<u8> {
+    let flags = FLAG_QR | (read_u16(query, 2).unwrap_or_default() & FLAG_RD) | rcode;
+    let mut message = Vec::with_capacity(HEADER_LEN);
+    message.extend_from_slice(&query[..2]);
+    message.extend_from_slice(&flags.to_be_bytes());
+    message.extend_from_slice(&[0; 8]);
+    message
+}
+
+/// Encodes `answer` to `question`, dropping the records and setting the TC
+/// flag if it would exceed `max_len`.
+fn response(
+    query: &[u8],
+    question: &Question,
+    answer: &Answer,
+    ttl: u32,
+    max_len: usize,
+) -> Vec<u8> {
+    let message = encode(query, question, answer, ttl, false);
+    if message.len() <= max_len {
+        return message;
+    }
+    encode(
+        query,
+        question,
+        &Answer {
+            rcode: answer.rcode,
+            ..Answer::default()
+        },
+        ttl,
+        true,
+    )
+}
+
+/// Encodes the response; every record, and the negative caching time in the
+/// SOA record, uses `ttl`.
+fn encode(
+    query: &[u8],
+    question: &Question,
+    answer: &Answer,
+    ttl: u32,
+    truncated: bool,
+) -> Vec<u8> {
+    let mut flags = FLAG_QR | (read_u16(query, 2).unwrap_or_default() & (0x7800 | FLAG_RD));
+    if answer.rcode != RCODE_NOTIMP && answer.rcode != RCODE_REFUSED {
+        flags |= FLAG_AA;
+    }
+    if truncated {
+        flags |= FLAG_TC;
+    }
+    flags |= answer.rcode;
+
+    let mut message = Vec::with_capacity(UDP_PAYLOAD);
+    message.extend_from_slice(&query[..2]);
+    message.extend_from_slice(&flags.to_be_bytes());
+    for count in [1, answer.answers.len(), answer.authority.len(), 0] {
+        message.extend_from_slice(&(count as u16).to_be_bytes());
+    }
+    // Echo the question as received, preserving the case of the name.
+    message.extend_from_slice(&query[HEADER_LEN..question.end]);
+
+    for record in &answer.answers {
+        // Answers are owned by the queried name, also when synthesized from
+        // a wildcard.
+        message.extend_from_slice(&QUESTION_NAME);
+        encode_record(&mut message, record, ttl);
+    }
+    for record in &answer.authority {
+        encode_name(&mut message, &record.owner);
+        encode_record(&mut message, record, ttl);
+    }
+
+    message
+}
+
+/// Appends type, class, TTL and data of `record` after its owner name.
+fn encode_record(message: &mut Vec<u8>, record: &Record, ttl: u32) {
+    message.extend_from_slice(&record.data.record_type().to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    message.extend_from_slice(&ttl.to_be_bytes());
+
+    let length_at = message.len();
+    message.extend_from_slice(&[0, 0]);
+    match &record.data {
+        RData::A(address) => message.extend_from_slice(&address.octets()),
+        RData::Aaaa(address) => message.extend_from_slice(&address.octets()),
+        RData::Ptr(name) | RData::Ns(name) => encode_name(message, name),
+        RData::Soa {
+            mname,
+            rname,
+            serial,
+        } => {
+            encode_name(message, mname);
+            encode_name(message, rname);
+            message.extend_from_slice(&serial.to_be_bytes());
+            for timer in SOA_TIMERS {
+                message.extend_from_slice(&timer.to_be_bytes());
+            }
+            message.extend_from_slice(&ttl.to_be_bytes());
+        }
+    }
+
+    let length = (message.len() - length_at - 2) as u16;
+    message[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+}
+
+fn encode_name(message: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPE_TXT: u16 = 16;
+
+    fn server(cache: &StateCache) -> DnsServer {
+        DnsServer::new(
+            &DnsConfig {
+                bind_address: "127.0.0.1:0".to_string(),
+                zones: vec![
+                    "example.com".to_string(),
+                    "8.b.d.0.1.0.0.2.ip6.arpa".to_string(),
+                ],
+                ttl: 60,
+                a_records: true,
+                ptr_records: true,
+                nameserver: None,
+                hostmaster: None,
+            },
+            cache.clone(),
+        )
+    }
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut message = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        encode_name(&mut message, name);
+        message.extend_from_slice(&qtype.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+        message
+    }
+
+    struct Parsed {
+        flags: u16,
+        answers: Vec<(u16, Vec<u8>)>,
+        authority: Vec<(u16, Vec<u8>)>,
+    }
+
+    fn skip_name(message: &[u8], mut offset: usize) -> usize {
+        loop {
+            match message[offset] {
+                0 => return offset + 1,
+                len if len & 0xc0 == 0xc0 => return offset + 2,
+                len => offset += 1 + len as usize,
+            }
+        }
+    }
+
+    fn parse(message: &[u8]) -> Parsed {
+        assert_eq!(&message[..2], &[0x12, 0x34]);
+        let count = |i: usize| read_u16(message, 4 + 2 * i).unwrap() as usize;
+        let mut offset = skip_name(message, HEADER_LEN) + 4;
+        let mut records = Vec::new();
+        for _ in 0..count(1) + count(2) {
+            offset = skip_name(message, offset);
+            let record_type = read_u16(message, offset).unwrap();
+            let len = read_u16(message, offset + 8).unwrap() as usize;
+            offset += 10;
+            records.push((record_type, message[offset..offset + len].to_vec()));
+            offset += len;
+        }
+        assert_eq!(offset, message.len());
+
+        let authority = records.split_off(count(1));
+        Parsed {
+            flags: read_u16(message, 2).unwrap(),
+            answers: records,
+            authority,
+        }
+    }
+
+    fn decode_name(data: &[u8]) -> String {
+        let mut labels = Vec::new();
+        let mut offset = 0;
+        while data[offset] != 0 {
+            let len = data[offset] as usize;
+            labels.push(String::from_utf8_lossy(&data[offset + 1..offset + 1 + len]).into_owned());
+            offset += 1 + len;
+        }
+        labels.join(".")
+    }
+
+    async fn ask(server: &DnsServer, name: &str, qtype: u16) -> Parsed {
+        parse(
+            &server
+                .respond(&query(name, qtype), UDP_PAYLOAD)
+                .await
+                .unwrap(),
+        )
+    }
+
+    fn rcode(parsed: &Parsed) -> u16 {
+        parsed.flags & 0x0f
+    }
+
+    #[tokio::test]
+    async fn test_answers_published_records() {
+        let cache = StateCache::new();
+        let isp1: Ipv6Addr = "2001:db8:1::10".parse().unwrap();
+        let isp2: Ipv6Addr = "2001:db8:2::10".parse().unwrap();
+        cache
+            .update("nas.example.com".to_string(), Some("isp1"), isp1)
+            .await;
+        cache
+            .update("nas.example.com".to_string(), Some("isp2"), isp2)
+            .await;
+        cache
+            .update(
+                "nas.example.com".to_string(),
+                None,
+                "192.0.2.1".parse::<Ipv4Addr>().unwrap(),
+            )
+            .await;
+        let server = server(&cache);
+
+        let aaaa = ask(&server, "NAS.Example.com", TYPE_AAAA).await;
+        assert_eq!(rcode(&aaaa), 0);
+        assert_ne!(aaaa.flags & FLAG_AA, 0);
+        assert_ne!(aaaa.flags & FLAG_RD, 0);
+        let mut addresses: Vec<_> = aaaa.answers.iter().map(|(_, data)| data.clone()).collect();
+        addresses.sort();
+        assert_eq!(addresses, [isp1.octets().to_vec(), isp2.octets().to_vec()]);
+
+        let a = ask(&server, "nas.example.com", TYPE_A).await;
+        assert_eq!(a.answers, [(TYPE_A, vec![192, 0, 2, 1])]);
+
+        let any = ask(&server, "nas.example.com", TYPE_ANY).await;
+        assert_eq!(any.answers.len(), 3);
+
+        let ptr = ask(&server, &reverse_name(isp1), TYPE_PTR).await;
+        assert_eq!(ptr.answers.len(), 1);
+        assert_eq!(decode_name(&ptr.answers[0].1), "nas.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_negative_answers() {
+        let cache = StateCache::new();
+        cache
+            .update(
+                "nas.example.com".to_string(),
+                None,
+                "2001:db8::10".parse::<Ipv6Addr>().unwrap(),
+            )
+            .await;
+        let server = server(&cache);
+
+        let nodata = ask(&server, "nas.example.com", TYPE_TXT).await;
+        assert_eq!(rcode(&nodata), 0);
+        assert!(nodata.answers.is_empty());
+        assert_eq!(nodata.authority[0].0, TYPE_SOA);
+
+        let nxdomain = ask(&server, "tv.example.com", TYPE_AAAA).await;
+        assert_eq!(rcode(&nxdomain), RCODE_NXDOMAIN);
+        assert_eq!(nxdomain.authority[0].0, TYPE_SOA);
+
+        let refused = ask(&server, "nas.example.net", TYPE_AAAA).await;
+        assert_eq!(rcode(&refused), RCODE_REFUSED);
+        assert_eq!(refused.flags & FLAG_AA, 0);
+
+        let apex = ask(&server, "example.com", TYPE_NS).await;
+        assert_eq!(decode_name(&apex.answers[0].1), "ns.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_wildcard() {
+        let cache = StateCache::new();
+        let address: Ipv6Addr = "2001:db8::20".parse().unwrap();
+        cache
+            .update("*.lab.example.com".to_string(), None, address)
+            .await;
+        cache
+            .update(
+                "www.lab.example.com".to_string(),
+                None,
+                "2001:db8::30".parse::<Ipv6Addr>().unwrap(),
+            )
+            .await;
+        let server = server(&cache);
+
+        for name in ["foo.lab.example.com", "a.b.lab.example.com"] {
+            let answer = ask(&server, name, TYPE_AAAA).await;
+            assert_eq!(answer.answers, [(TYPE_AAAA, address.octets().to_vec())]);
+        }
+
+        let www = ask(&server, "www.lab.example.com", TYPE_AAAA).await;
+        assert_ne!(www.answers, [(TYPE_AAAA, address.octets().to_vec())]);
+
+        let empty_non_terminal = ask(&server, "lab.example.com", TYPE_AAAA).await;
+        assert_eq!(rcode(&empty_non_terminal), 0);
+        assert!(empty_non_terminal.answers.is_empty());
+
+        let ptr = ask(&server, &reverse_name(address), TYPE_PTR).await;
+        assert_eq!(rcode(&ptr), RCODE_NXDOMAIN);
+    }
+
+    #[tokio::test]
+    async fn test_serial_follows_changes() {
+        let cache = StateCache::new();
+        let server = server(&cache);
+        let serial = |parsed: Parsed| {
+            let data = &parsed.answers[0].1;
+            let names_end = skip_name(data, skip_name(data, 0));
+            u32::from_be_bytes(data[names_end..names_end + 4].try_into().unwrap())
+        };
+
+        let first = serial(ask(&server, "example.com", TYPE_SOA).await);
+        let address: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        cache
+            .update("nas.example.com".to_string(), None, address)
+            .await;
+        cache
+            .update("nas.example.com".to_string(), None, address)
+            .await;
+        let second = serial(ask(&server, "example.com", TYPE_SOA).await);
+        cache
+            .remove_record("nas.example.com", RecordType::Aaaa)
+            .await;
+        let third = serial(ask(&server, "example.com", TYPE_SOA).await);
+
+        assert_eq!(second, first.wrapping_add(1));
+        assert_eq!(third, first.wrapping_add(2));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_and_truncated() {
+        let cache = StateCache::new();
+        for i in 0..40u16 {
+            cache
+                .update(
+                    format!("nas{}.example.com", i),
+                    None,
+                    Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i),
+                )
+                .await;
+            cache
+                .update(
+                    "all.example.com".to_string(),
+                    Some(&i.to_string()),
+                    Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i),
+                )
+                .await;
+        }
+        let server = server(&cache);
+
+        assert!(server
+            .respond(&[0x12, 0x34, 0x01], UDP_PAYLOAD)
+            .await
+            .is_none());
+
+        let mut no_question = query("nas.example.com", TYPE_AAAA);
+        no_question[5] = 0;
+        let formerr = server.respond(&no_question, UDP_PAYLOAD).await.unwrap();
+        assert_eq!(read_u16(&formerr, 2).unwrap() & 0x0f, RCODE_FORMERR);
+
+        let truncated = ask(&server, "all.example.com", TYPE_AAAA).await;
+        assert_ne!(truncated.flags & FLAG_TC, 0);
+        assert!(truncated.answers.is_empty());
+
+        let full = server
+            .respond(&query("all.example.com", TYPE_AAAA), u16::MAX as usize)
+            .await
+            .unwrap();
+        assert_eq!(parse(&full).answers.len(), 40);
+    }
+}
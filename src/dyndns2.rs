@@ -1,29 +1,115 @@
 use axum::{
-    extract::{Query, State as AxumState},
+    extract::{ConnectInfo, Query, State as AxumState},
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 use serde::Deserialize;
-use std::net::Ipv6Addr;
+use std::borrow::Cow;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
-use crate::cloudflare::CloudflareClient;
-use crate::config::Config;
+use crate::config::{Config, HostMapping};
+use crate::debounce::{Debouncer, FlapDetector, FLAP_WINDOW};
 use crate::error::Ddns6Error;
-use crate::ipv6::Ipv6Prefix;
-use crate::state::StateCache;
-
-#[derive(Debug, Deserialize)]
+use crate::event::{ChangeEvent, HostChange};
+use crate::history::{unix_time, HostOutcome, Outcome, PrefixHistory};
+use crate::hooks::HookRunner;
+use crate::ipv6::{check_publishable, Ipv6Prefix};
+use crate::mqtt::MqttPublisher;
+use crate::provider::Providers;
+use crate::record::RecordType;
+use crate::state::{ProviderResult, StateCache};
+use crate::uplink::{self, UplinkTracker};
+use crate::webhook::WebhookNotifier;
+
+#[derive(Debug, Default, Deserialize)]
 pub struct UpdateQuery {
+    #[serde(default)]
     prefix: String,
+    /// Standard DynDNS2 address parameter; may hold a comma-separated list of
+    /// IPv4 and IPv6 addresses. Only the IPv4 address is used.
+    #[serde(default)]
+    myip: Option<String>,
+    #[serde(default)]
+    ipv4: Option<String>,
+    /// Uplink that reported the prefix; required when uplinks are configured.
+    #[serde(default)]
+    uplink: Option<String>,
+    /// DynDNS2 `offline=yes`: the uplink went down, withdraw its addresses.
+    #[serde(default)]
+    offline: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
     pub state_cache: StateCache,
-    pub cloudflare_client: Arc<CloudflareClient>,
+    /// Empty when records are only served locally, by the embedded DNS
+    /// server or output files.
+    pub providers: Providers,
+    pub uplinks: UplinkTracker,
+    pub webhooks: WebhookNotifier,
+    pub hooks: HookRunner,
+    pub mqtt: Option<MqttPublisher>,
+    pub debouncer: Debouncer,
+    pub flaps: FlapDetector,
+    pub history: PrefixHistory,
+}
+
+/// Where an update request came from.
+#[derive(Debug, Clone, Copy)]
+pub enum UpdateSource {
+    /// A request to `/update`, with the peer address if known.
+    Http(Option<IpAddr>),
+    /// A message on the MQTT command topic.
+    Mqtt,
+}
+
+impl UpdateSource {
+    pub fn peer(&self) -> Option<IpAddr> {
+        match self {
+            UpdateSource::Http(peer) => *peer,
+            UpdateSource::Mqtt => None,
+        }
+    }
+}
+
+impl fmt::Display for UpdateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateSource::Http(Some(peer)) => write!(f, "http {}", peer),
+            UpdateSource::Http(None) => write!(f, "http"),
+            UpdateSource::Mqtt => write!(f, "mqtt"),
+        }
+    }
+}
+
+impl UpdateQuery {
+    /// An update reporting `prefix` and nothing else.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Parses an update sent as a message body: either a JSON object with
+    /// the query parameters or just the prefix.
+    pub fn from_payload(payload: &[u8]) -> Result<Self, Ddns6Error> {
+        let payload = std::str::from_utf8(payload)
+            .map_err(|_| Ddns6Error::InvalidDynDns2Request("payload is not UTF-8".to_string()))?
+            .trim();
+
+        if payload.starts_with('{') {
+            serde_json::from_str(payload)
+                .map_err(|e| Ddns6Error::InvalidDynDns2Request(e.to_string()))
+        } else {
+            Ok(Self::with_prefix(payload))
+        }
+    }
 }
 
 pub enum DynDns2Response {
@@ -34,40 +120,85 @@ pub enum DynDns2Response {
     BadAgent,
     #[allow(dead_code)]
     Abuse,
+    /// The reported prefix is not globally routable.
+    BadPrefix(String),
+    /// The reported prefix is held back until it settled.
+    Pending(String),
     Error(String),
 }
 
-impl IntoResponse for DynDns2Response {
-    fn into_response(self) -> Response {
-        let (status, body) = match self {
-            DynDns2Response::Good(hosts) => (StatusCode::OK, format!("good {}", hosts.join(", "))),
-            DynDns2Response::NoChg(hosts) => {
-                (StatusCode::OK, format!("nochg {}", hosts.join(", ")))
-            }
-            DynDns2Response::PartialSuccess(success, failed) => (
-                StatusCode::OK,
-                format!(
-                    "partial success: {} | failed: {}",
-                    success.join(", "),
-                    failed.join(", ")
-                ),
+impl fmt::Display for DynDns2Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynDns2Response::Good(hosts) => write!(f, "good {}", hosts.join(", ")),
+            DynDns2Response::NoChg(hosts) => write!(f, "nochg {}", hosts.join(", ")),
+            DynDns2Response::PartialSuccess(success, failed) => write!(
+                f,
+                "partial success: {} | failed: {}",
+                success.join(", "),
+                failed.join(", ")
             ),
-            DynDns2Response::BadAgent => (StatusCode::OK, "badagent".to_string()),
-            DynDns2Response::Abuse => (StatusCode::OK, "abuse".to_string()),
-            DynDns2Response::Error(msg) => (StatusCode::OK, format!("911 {}", msg)),
-        };
+            DynDns2Response::BadAgent => write!(f, "badagent"),
+            DynDns2Response::Abuse => write!(f, "abuse"),
+            DynDns2Response::BadPrefix(msg) => write!(f, "badprefix {}", msg),
+            DynDns2Response::Pending(prefix) => write!(f, "good pending {}", prefix),
+            DynDns2Response::Error(msg) => write!(f, "911 {}", msg),
+        }
+    }
+}
 
-        (status, body).into_response()
+impl IntoResponse for DynDns2Response {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, self.to_string()).into_response()
     }
 }
 
+enum PublishOutcome {
+    /// Published to at least one provider, or locally without providers.
+    Updated {
+        /// Previously published address, if known.
+        previous: Option<IpAddr>,
+        /// Providers that failed.
+        failed: Vec<String>,
+    },
+    Unchanged,
+    Failed,
+}
+
 pub async fn handle_update(
     AxumState(state): AxumState<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     Query(params): Query<UpdateQuery>,
+) -> DynDns2Response {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    apply_update(&state, params, UpdateSource::Http(peer)).await
+}
+
+/// Publishes the addresses for an update request.
+pub async fn apply_update(
+    state: &AppState,
+    params: UpdateQuery,
+    source: UpdateSource,
 ) -> DynDns2Response {
     info!("Received update request for all hosts");
     debug!("Update parameters: {:?}", params);
 
+    let uplink = match resolve_uplink(&state.config, params.uplink.as_deref()) {
+        Ok(uplink) => uplink,
+        Err(e) => {
+            error!("Rejecting update: {}", e);
+            return DynDns2Response::Error(e.to_string());
+        }
+    };
+
+    if is_offline(&params) {
+        state.debouncer.cancel(uplink).await;
+        return match uplink {
+            Some(uplink) => withdraw_uplink(state, uplink).await,
+            None => DynDns2Response::Error("offline requires an uplink".to_string()),
+        };
+    }
+
     let client_ipv6 = match extract_ipv6_address(&params) {
         Ok(addr) => addr,
         Err(e) => {
@@ -78,6 +209,27 @@ pub async fn handle_update(
 
     debug!("Client IPv6 address: {}", client_ipv6);
 
+    if let Err(e) = check_publishable(client_ipv6, state.config.server.allow_ula_prefix) {
+        warn!("Rejecting update: {}", e);
+        return DynDns2Response::BadPrefix(e.to_string());
+    }
+
+    let client_ipv4 = if state.config.hosts.iter().any(|h| h.ipv4) {
+        match extract_ipv4_address(&params, source.peer()) {
+            Ok(addr) => addr,
+            Err(e) => {
+                error!("Failed to extract IPv4 address: {}", e);
+                return DynDns2Response::Error("Invalid IPv4 address".to_string());
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Some(ipv4) = client_ipv4 {
+        debug!("Client IPv4 address: {}", ipv4);
+    }
+
     let prefix = match Ipv6Prefix::extract_from_address(client_ipv6, 64) {
         Ok(p) => p,
         Err(e) => {
@@ -86,6 +238,85 @@ pub async fn handle_update(
         }
     };
 
+    state
+        .history
+        .observe(uplink, prefix, &source.to_string(), unix_time())
+        .await;
+
+    if let Some(changes) = state.flaps.observe(uplink, prefix, Instant::now()).await {
+        if state
+            .config
+            .debounce
+            .flap_threshold
+            .is_some_and(|threshold| changes > threshold)
+        {
+            warn!(
+                "Prefix{} is flapping: changed {} times within {:?}",
+                uplink
+                    .map(|u| format!(" of uplink {}", u))
+                    .unwrap_or_default(),
+                changes,
+                FLAP_WINDOW
+            );
+        }
+    }
+
+    if state.config.debounce.settle_secs > 0 {
+        if let Some(response) = hold_prefix(state, uplink, prefix, client_ipv4).await {
+            return response;
+        }
+    }
+
+    publish_update(state, uplink, prefix, client_ipv4).await
+}
+
+/// Holds back a prefix that differs from the published one until it was
+/// reported unchanged for `debounce.settle_secs`. Returns `None` if the
+/// update should be published right away.
+async fn hold_prefix(
+    state: &AppState,
+    uplink: Option<&str>,
+    prefix: Ipv6Prefix,
+    client_ipv4: Option<Ipv4Addr>,
+) -> Option<DynDns2Response> {
+    let current = state.state_cache.prefix(uplink).await;
+    if current.is_none() || current == Some(prefix) {
+        state.debouncer.cancel(uplink).await;
+        return None;
+    }
+
+    if let Some(generation) = state.debouncer.hold(uplink, prefix, client_ipv4).await {
+        let settle = Duration::from_secs(state.config.debounce.settle_secs);
+        info!(
+            "Holding back prefix {} until it is unchanged for {:?}",
+            prefix, settle
+        );
+
+        let state = state.clone();
+        let uplink = uplink.map(str::to_string);
+        tokio::spawn(async move {
+            tokio::time::sleep(settle).await;
+            // Publish with the IPv4 address of the latest report, which may
+            // have changed during the window.
+            if let Some(settled) = state.debouncer.take(uplink.as_deref(), generation).await {
+                info!("Prefix {} settled, publishing it", prefix);
+                let response =
+                    publish_update(&state, uplink.as_deref(), prefix, settled.client_ipv4).await;
+                info!("Settled update: {}", response);
+            }
+        });
+    }
+
+    Some(DynDns2Response::Pending(prefix.to_string()))
+}
+
+/// Publishes the addresses of all hosts in `prefix`.
+async fn publish_update(
+    state: &AppState,
+    uplink: Option<&str>,
+    prefix: Ipv6Prefix,
+    client_ipv4: Option<Ipv4Addr>,
+) -> DynDns2Response {
     info!(
         "Extracted prefix: {}/{}, updating all {} host(s)",
         prefix.network(),
@@ -93,12 +324,23 @@ pub async fn handle_update(
         state.config.hosts.len()
     );
 
+    let old_prefix = state.state_cache.swap_prefix(uplink, prefix).await;
+
+    if let Some(uplink) = uplink {
+        if !state.uplinks.is_active(uplink).await {
+            info!("Uplink {} is up", uplink);
+        }
+        state.uplinks.seen(uplink).await;
+    }
+
     let mut updated_hosts = Vec::new();
     let mut unchanged_hosts = Vec::new();
     let mut failed_hosts = Vec::new();
+    let mut changes = Vec::new();
+    let mut outcomes = Vec::new();
 
     for host in &state.config.hosts {
-        let final_address = match prefix.combine_with_interface_id(&host.interface_id) {
+        let final_address = match host.address_in(&prefix) {
             Ok(addr) => addr,
             Err(e) => {
                 error!(
@@ -106,6 +348,12 @@ pub async fn handle_update(
                     host.hostname, e
                 );
                 failed_hosts.push(host.hostname.clone());
+                outcomes.push(host_outcome(
+                    &host.hostname,
+                    RecordType::Aaaa,
+                    None,
+                    Outcome::Failed,
+                ));
                 continue;
             }
         };
@@ -115,48 +363,171 @@ pub async fn handle_update(
             host.hostname,
             final_address,
             prefix.network(),
-            host.interface_id
+            if host.stable_privacy.is_some() {
+                "stable-privacy"
+            } else {
+                &host.interface_id
+            }
         );
 
-        let has_changed = state
-            .state_cache
-            .has_changed(&host.hostname, final_address)
-            .await;
-
-        if !has_changed {
-            info!("Address for {} has not changed, skipping", host.hostname);
-            unchanged_hosts.push(format!("{}={}", host.hostname, final_address));
-            continue;
+        let mut records = vec![(Cow::Borrowed(host), IpAddr::V6(final_address), uplink)];
+        if host.ipv4 {
+            match client_ipv4 {
+                Some(ipv4) => records.push((Cow::Borrowed(host), IpAddr::V4(ipv4), None)),
+                None => warn!(
+                    "No IPv4 address available, leaving A record for {} untouched",
+                    host.hostname
+                ),
+            }
         }
 
-        info!(
-            "Address for {} has changed to {}, updating Cloudflare",
-            host.hostname, final_address
-        );
+        for view in &host.views {
+            let view_host = host.in_view(view);
+            match view_address(&state.config, &view_host, &view.view) {
+                Ok(address) => records.push((Cow::Owned(view_host), IpAddr::V6(address), None)),
+                Err(e) => {
+                    error!(
+                        "Failed to compute address of {} in view {}: {}",
+                        view_host.hostname, view.view, e
+                    );
+                    outcomes.push(host_outcome(
+                        &view_host.hostname,
+                        RecordType::Aaaa,
+                        None,
+                        Outcome::Failed,
+                    ));
+                    failed_hosts.push(view_host.hostname);
+                }
+            }
+        }
 
-        match state
-            .cloudflare_client
-            .update_aaaa_record(&host.hostname, final_address)
-            .await
-        {
-            Ok(_) => {
-                state
-                    .state_cache
-                    .update(host.hostname.clone(), final_address)
-                    .await;
-                info!(
-                    "Successfully updated {} to {}",
-                    host.hostname, final_address
-                );
-                updated_hosts.push(format!("{}={}", host.hostname, final_address));
+        for (record_host, address, record_uplink) in records {
+            let entry = format!("{}={}", record_host.hostname, address);
+            let outcome = match publish_address(state, &record_host, address, record_uplink).await {
+                PublishOutcome::Updated { previous, failed } => {
+                    updated_hosts.push(entry);
+                    // A retry that only reached lagging providers changes nothing.
+                    if previous != Some(address) {
+                        changes.push(HostChange::new(&record_host.hostname, previous, address));
+                    }
+                    for provider in &failed {
+                        let failure = format!("{}@{}", record_host.hostname, provider);
+                        if !failed_hosts.contains(&failure) {
+                            failed_hosts.push(failure);
+                        }
+                    }
+                    if failed.is_empty() {
+                        Outcome::Updated
+                    } else {
+                        Outcome::Partial
+                    }
+                }
+                PublishOutcome::Unchanged => {
+                    unchanged_hosts.push(entry);
+                    Outcome::Unchanged
+                }
+                PublishOutcome::Failed => {
+                    if !failed_hosts.contains(&record_host.hostname) {
+                        failed_hosts.push(record_host.hostname.clone());
+                    }
+                    Outcome::Failed
+                }
+            };
+            outcomes.push(host_outcome(
+                &record_host.hostname,
+                RecordType::for_address(address),
+                Some(address),
+                outcome,
+            ));
+
+            // A wildcard has no single name for the PTR record to point to.
+            let is_dynamic =
+                address == IpAddr::V6(final_address) && record_host.hostname == host.hostname;
+            if !is_dynamic || !state.config.reverse.enabled || host.is_wildcard() {
+                continue;
             }
-            Err(e) => {
-                error!("Failed to update Cloudflare for {}: {}", host.hostname, e);
-                failed_hosts.push(host.hostname.clone());
+            if let Some(ptr_outcome) = publish_ptr(state, host, final_address, record_uplink).await
+            {
+                if ptr_outcome == Outcome::Failed && !failed_hosts.contains(&host.hostname) {
+                    failed_hosts.push(host.hostname.clone());
+                }
+                outcomes.push(host_outcome(
+                    &host.hostname,
+                    RecordType::Ptr,
+                    Some(address),
+                    ptr_outcome,
+                ));
             }
         }
     }
 
+    state
+        .history
+        .record_outcomes(uplink, prefix, outcomes)
+        .await;
+
+    let event = ChangeEvent::new(uplink, old_prefix, prefix, changes);
+    if event.has_changes() {
+        notify(state, &event);
+    }
+
+    summarize(updated_hosts, unchanged_hosts, failed_hosts)
+}
+
+/// Sends `event` to the webhooks, hook commands and MQTT.
+fn notify(state: &AppState, event: &ChangeEvent) {
+    state.webhooks.notify(event);
+    state.hooks.notify(event);
+    if let Some(mqtt) = &state.mqtt {
+        mqtt.notify(event);
+    }
+}
+
+fn host_outcome(
+    hostname: &str,
+    record_type: RecordType,
+    address: Option<IpAddr>,
+    outcome: Outcome,
+) -> HostOutcome {
+    HostOutcome {
+        hostname: hostname.to_string(),
+        record_type: record_type.to_string(),
+        address,
+        outcome,
+    }
+}
+
+/// Withdraws the addresses of an uplink that reported itself down or whose
+/// prefix expired. The uplink stays pending until every record is gone, so
+/// the expiry check retries a failed withdrawal.
+pub async fn withdraw_uplink(state: &AppState, uplink: &str) -> DynDns2Response {
+    info!("Uplink {} is down, withdrawing its addresses", uplink);
+    state.uplinks.withdrawing(uplink).await;
+
+    let (withdrawn, failed) =
+        uplink::withdraw(&state.config, &state.providers, &state.state_cache, uplink).await;
+    if failed.is_empty() {
+        state.uplinks.withdrawn(uplink).await;
+    }
+
+    let hostnames = withdrawn.iter().map(|host| host.hostname.clone()).collect();
+    if !withdrawn.is_empty() {
+        let old_prefix = state.state_cache.prefix(Some(uplink)).await;
+        notify(
+            state,
+            &ChangeEvent::withdrawal(uplink, old_prefix, withdrawn),
+        );
+    }
+
+    summarize(hostnames, Vec::new(), failed)
+}
+
+/// Turns the per-record outcomes into the DynDNS2 response.
+fn summarize(
+    updated_hosts: Vec<String>,
+    unchanged_hosts: Vec<String>,
+    failed_hosts: Vec<String>,
+) -> DynDns2Response {
     if !failed_hosts.is_empty() && !updated_hosts.is_empty() {
         warn!(
             "Partial success: {} updated, {} failed",
@@ -180,6 +551,251 @@ pub async fn handle_update(
     DynDns2Response::NoChg(unchanged_hosts)
 }
 
+/// Computes the address of `host` within the static prefix of `view`.
+fn view_address(config: &Config, host: &HostMapping, view: &str) -> Result<Ipv6Addr, Ddns6Error> {
+    let view = config
+        .views
+        .iter()
+        .find(|v| v.name == view)
+        .ok_or_else(|| Ddns6Error::Config(format!("Unknown view: {}", view)))?;
+
+    host.address_in(&view.parsed_prefix()?)
+}
+
+/// Publishes one A or AAAA record for `host` to each of its providers,
+/// skipping those that already hold the address.
+async fn publish_address(
+    state: &AppState,
+    host: &HostMapping,
+    address: IpAddr,
+    uplink: Option<&str>,
+) -> PublishOutcome {
+    let record_type = RecordType::for_address(address);
+    let cached = state
+        .state_cache
+        .get(&host.hostname, uplink, record_type)
+        .await;
+    let previous = cached.as_ref().map(|cached| cached.address);
+
+    let providers: Vec<_> = state.providers.for_host(host).collect();
+    if providers.is_empty() {
+        if previous == Some(address) {
+            info!("Address for {} has not changed, skipping", host.hostname);
+            return PublishOutcome::Unchanged;
+        }
+        info!("Address for {} has changed to {}", host.hostname, address);
+        state
+            .state_cache
+            .update(host.hostname.clone(), uplink, address)
+            .await;
+        return PublishOutcome::Updated {
+            previous,
+            failed: Vec::new(),
+        };
+    }
+
+    // Providers that failed before are retried even if the address is the same.
+    let pending: Vec<_> = providers
+        .into_iter()
+        .filter(|(name, _)| {
+            !cached
+                .as_ref()
+                .and_then(|cached| cached.providers.get(*name))
+                .is_some_and(|result| result.holds(address))
+        })
+        .collect();
+    if pending.is_empty() {
+        info!("Address for {} has not changed, skipping", host.hostname);
+        return PublishOutcome::Unchanged;
+    }
+
+    // Providers replacing whole record sets need the addresses of the other
+    // uplinks as well.
+    let mut addresses: Vec<IpAddr> = state
+        .state_cache
+        .list_host(&host.hostname)
+        .await
+        .into_iter()
+        .filter(|((_, record_uplink, kind), _)| {
+            *kind == record_type && record_uplink.as_deref() != uplink
+        })
+        .map(|(_, published)| published.address)
+        .collect();
+    addresses.push(address);
+    addresses.sort();
+
+    let mut results = Vec::new();
+    let mut updated = false;
+    let mut failed = Vec::new();
+    for (name, provider) in pending {
+        info!(
+            "Address for {} has changed to {}, updating {}",
+            host.hostname, address, name
+        );
+        match provider
+            .update_address(host, address, uplink, &addresses)
+            .await
+        {
+            Ok(()) => {
+                info!(
+                    "Successfully updated {} to {} at {}",
+                    host.hostname, address, name
+                );
+                results.push((name.to_string(), ProviderResult::success(address)));
+                updated = true;
+            }
+            Err(e) => {
+                error!("Failed to update {} for {}: {}", name, host.hostname, e);
+                results.push((name.to_string(), ProviderResult::failure(address, &e)));
+                failed.push(name.to_string());
+            }
+        }
+    }
+
+    state
+        .state_cache
+        .update_with_results(host.hostname.clone(), uplink, address, results)
+        .await;
+
+    if !updated {
+        PublishOutcome::Failed
+    } else {
+        PublishOutcome::Updated { previous, failed }
+    }
+}
+
+/// Points the PTR record of the dynamic address of `host` back to it at
+/// each provider that holds the AAAA record but not yet the PTR record, so
+/// failed PTR records are retried with the next update. Returns `None` if
+/// no provider needed or could take the PTR record.
+async fn publish_ptr(
+    state: &AppState,
+    host: &HostMapping,
+    address: Ipv6Addr,
+    uplink: Option<&str>,
+) -> Option<Outcome> {
+    let cached = state
+        .state_cache
+        .get(&host.hostname, uplink, RecordType::Aaaa)
+        .await?;
+
+    let mut results = Vec::new();
+    for (name, provider) in state.providers.for_host(host) {
+        let published = cached.providers.get(name);
+        let ptr = cached.ptr.get(name);
+        if !published.is_some_and(|result| result.holds(IpAddr::V6(address)))
+            || ptr.is_some_and(|result| result.holds(IpAddr::V6(address)))
+        {
+            continue;
+        }
+
+        let previous =
+            ptr.filter(|result| result.success)
+                .and_then(|result| match result.address {
+                    IpAddr::V6(previous) => Some(previous),
+                    IpAddr::V4(_) => None,
+                });
+        match provider.update_ptr(host, address, previous, uplink).await {
+            Ok(true) => {
+                info!(
+                    "Successfully updated PTR record for {} at {}",
+                    host.hostname, name
+                );
+                results.push((name.to_string(), ProviderResult::success(address.into())));
+            }
+            Ok(false) => {
+                debug!("{} has no ip6.arpa zone for {}", name, address);
+            }
+            Err(e) => {
+                error!(
+                    "Failed to update PTR record for {} at {}: {}",
+                    host.hostname, name, e
+                );
+                results.push((
+                    name.to_string(),
+                    ProviderResult::failure(address.into(), &e),
+                ));
+            }
+        }
+    }
+    if results.is_empty() {
+        return None;
+    }
+
+    let outcome = if results.iter().all(|(_, result)| result.success) {
+        Outcome::Updated
+    } else if results.iter().any(|(_, result)| result.success) {
+        Outcome::Partial
+    } else {
+        Outcome::Failed
+    };
+    state
+        .state_cache
+        .update_ptr_results(&host.hostname, uplink, results)
+        .await;
+    Some(outcome)
+}
+
+/// Checks the `uplink` parameter against the configured uplinks.
+fn resolve_uplink<'a>(
+    config: &'a Config,
+    uplink: Option<&str>,
+) -> Result<Option<&'a str>, Ddns6Error> {
+    match uplink {
+        None if config.uplinks.is_empty() => Ok(None),
+        None => Err(Ddns6Error::InvalidDynDns2Request(
+            "Missing uplink parameter".to_string(),
+        )),
+        Some(name) => config
+            .uplink(name)
+            .map(|uplink| Some(uplink.name.as_str()))
+            .ok_or_else(|| Ddns6Error::InvalidDynDns2Request(format!("Unknown uplink {}", name))),
+    }
+}
+
+fn is_offline(params: &UpdateQuery) -> bool {
+    params
+        .offline
+        .as_deref()
+        .is_some_and(|offline| offline.eq_ignore_ascii_case("yes"))
+}
+
+/// Determines the IPv4 address for A records from the `ipv4` parameter, the
+/// IPv4 entry of `myip`, or the peer address, in that order.
+fn extract_ipv4_address(
+    params: &UpdateQuery,
+    peer: Option<IpAddr>,
+) -> Result<Option<Ipv4Addr>, Ddns6Error> {
+    if let Some(ipv4) = &params.ipv4 {
+        return ipv4
+            .trim()
+            .parse::<Ipv4Addr>()
+            .map(Some)
+            .map_err(|e| Ddns6Error::Ipv4Parse(format!("Failed to parse ipv4 parameter: {}", e)));
+    }
+
+    if let Some(myip) = &params.myip {
+        for entry in myip.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.parse::<IpAddr>() {
+                Ok(IpAddr::V4(addr)) => return Ok(Some(addr)),
+                Ok(IpAddr::V6(_)) => continue,
+                Err(e) => {
+                    return Err(Ddns6Error::Ipv4Parse(format!(
+                        "Failed to parse myip parameter: {}",
+                        e
+                    )))
+                }
+            }
+        }
+    }
+
+    Ok(match peer {
+        Some(IpAddr::V4(addr)) => Some(addr),
+        Some(IpAddr::V6(addr)) => addr.to_ipv4_mapped(),
+        None => None,
+    })
+}
+
 fn extract_ipv6_address(params: &UpdateQuery) -> Result<Ipv6Addr, Ddns6Error> {
     params
         .prefix
@@ -195,6 +811,10 @@ mod tests {
     fn test_extract_ipv6_from_prefix() {
         let params = UpdateQuery {
             prefix: "2001:db8::1".to_string(),
+            myip: None,
+            ipv4: None,
+            uplink: None,
+            offline: None,
         };
 
         let result = extract_ipv6_address(&params).unwrap();
@@ -205,6 +825,10 @@ mod tests {
     fn test_extract_ipv6_invalid_prefix() {
         let params = UpdateQuery {
             prefix: "not-an-ip".to_string(),
+            myip: None,
+            ipv4: None,
+            uplink: None,
+            offline: None,
         };
 
         assert!(extract_ipv6_address(&params).is_err());
@@ -223,20 +847,99 @@ mod tests {
         for addr_str in test_cases {
             let params = UpdateQuery {
                 prefix: addr_str.to_string(),
+                myip: None,
+                ipv4: None,
+                uplink: None,
+                offline: None,
             };
 
+            let address = extract_ipv6_address(&params)
+                .unwrap_or_else(|_| panic!("Failed to parse: {}", addr_str));
             assert!(
-                extract_ipv6_address(&params).is_ok(),
-                "Failed to parse: {}",
+                check_publishable(address, false).is_err(),
+                "Accepted non-global prefix: {}",
                 addr_str
             );
         }
     }
 
+    #[test]
+    fn test_extract_ipv4_sources() {
+        let query = |myip: Option<&str>, ipv4: Option<&str>| UpdateQuery {
+            prefix: "2001:db8::1".to_string(),
+            myip: myip.map(str::to_string),
+            ipv4: ipv4.map(str::to_string),
+            uplink: None,
+            offline: None,
+        };
+        let peer_v4: IpAddr = "198.51.100.7".parse().unwrap();
+        let peer_v6: IpAddr = "2001:db8::7".parse().unwrap();
+        let expected = |s: &str| Some(s.parse::<Ipv4Addr>().unwrap());
+
+        assert_eq!(
+            extract_ipv4_address(&query(Some("192.0.2.2"), Some("192.0.2.1")), Some(peer_v4))
+                .unwrap(),
+            expected("192.0.2.1")
+        );
+        assert_eq!(
+            extract_ipv4_address(&query(Some("2001:db8::1, 192.0.2.2"), None), Some(peer_v4))
+                .unwrap(),
+            expected("192.0.2.2")
+        );
+        assert_eq!(
+            extract_ipv4_address(&query(Some("2001:db8::1"), None), Some(peer_v4)).unwrap(),
+            expected("198.51.100.7")
+        );
+        assert_eq!(
+            extract_ipv4_address(
+                &query(None, None),
+                Some("::ffff:198.51.100.8".parse().unwrap())
+            )
+            .unwrap(),
+            expected("198.51.100.8")
+        );
+        assert_eq!(
+            extract_ipv4_address(&query(None, None), Some(peer_v6)).unwrap(),
+            None
+        );
+        assert_eq!(
+            extract_ipv4_address(&query(None, None), None).unwrap(),
+            None
+        );
+
+        assert!(matches!(
+            extract_ipv4_address(&query(None, Some("2001:db8::1")), None),
+            Err(Ddns6Error::Ipv4Parse(_))
+        ));
+        assert!(matches!(
+            extract_ipv4_address(&query(Some("not-an-ip"), None), None),
+            Err(Ddns6Error::Ipv4Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_update_query_from_payload() {
+        let query = UpdateQuery::from_payload(b"2001:db8::1\n").unwrap();
+        assert_eq!(query.prefix, "2001:db8::1");
+        assert!(query.uplink.is_none());
+
+        let query =
+            UpdateQuery::from_payload(br#"{"prefix": "2001:db8::1", "uplink": "isp1"}"#).unwrap();
+        assert_eq!(query.prefix, "2001:db8::1");
+        assert_eq!(query.uplink.as_deref(), Some("isp1"));
+
+        assert!(UpdateQuery::from_payload(b"{\"prefix\": 1}").is_err());
+        assert!(UpdateQuery::from_payload(&[0xff, 0xfe]).is_err());
+    }
+
     #[test]
     fn test_update_query_deserialization() {
         let query = UpdateQuery {
             prefix: "2001:db8::1".to_string(),
+            myip: None,
+            ipv4: None,
+            uplink: None,
+            offline: None,
         };
 
         assert_eq!(query.prefix, "2001:db8::1");
@@ -272,6 +975,13 @@ mod tests {
         assert_eq!(status, axum::http::StatusCode::OK);
     }
 
+    #[test]
+    fn test_dyndns2_response_bad_prefix() {
+        let response = DynDns2Response::BadPrefix("fe80::1 is a link-local address".to_string());
+        let (parts, _body) = response.into_response().into_parts();
+        assert_eq!(parts.status, axum::http::StatusCode::OK);
+    }
+
     #[test]
     fn test_dyndns2_response_partial_success() {
         let success = vec!["device1.example.com=2001:db8::1".to_string()];
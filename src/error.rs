@@ -8,6 +8,12 @@ pub enum Ddns6Error {
     #[error("IPv6 parsing error: {0}")]
     Ipv6Parse(String),
 
+    #[error("IPv4 parsing error: {0}")]
+    Ipv4Parse(String),
+
+    #[error("Prefix is not globally routable: {0}")]
+    NonGlobalPrefix(String),
+
     #[allow(dead_code)]
     #[error("Hostname not found in configuration: {0}")]
     HostnameNotFound(String),
@@ -18,10 +24,24 @@ pub enum Ddns6Error {
     #[error("Cloudflare API error: {0}")]
     CloudflareApi(String),
 
+    #[error("PowerDNS API error: {0}")]
+    PowerDnsApi(String),
+
+    #[error("HTTP provider error: {0}")]
+    HttpProvider(String),
+
+    #[error("Zone not found: {0}")]
+    ZoneNotFound(String),
+
+    #[error("Refusing to overwrite record not managed by ddns6: {0}")]
+    RecordNotOwned(String),
+
+    #[error("Webhook error: {0}")]
+    Webhook(String),
+
     #[error("HTTP request error: {0}")]
     HttpRequest(#[from] reqwest::Error),
 
-    #[allow(dead_code)]
     #[error("Invalid DynDNS2 request: {0}")]
     InvalidDynDns2Request(String),
 
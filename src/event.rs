@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::net::IpAddr;
+
+use crate::history::unix_time;
+use crate::ipv6::Ipv6Prefix;
+use crate::record::RecordType;
+
+/// What changed in one update request or withdrawal of an uplink; sent to
+/// webhooks.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub uplink: Option<String>,
+    /// Prefix reported by the previous update, if any since startup.
+    pub old_prefix: Option<String>,
+    /// `None` when the addresses of `uplink` were withdrawn.
+    pub new_prefix: Option<String>,
+    /// Records that were published with a new address or withdrawn.
+    pub hosts: Vec<HostChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostChange {
+    pub hostname: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    /// Address published before, if known since startup.
+    pub old_address: Option<IpAddr>,
+    /// `None` when the record was withdrawn.
+    pub new_address: Option<IpAddr>,
+}
+
+impl ChangeEvent {
+    pub fn new(
+        uplink: Option<&str>,
+        old_prefix: Option<Ipv6Prefix>,
+        new_prefix: Ipv6Prefix,
+        hosts: Vec<HostChange>,
+    ) -> Self {
+        Self {
+            timestamp: unix_time(),
+            uplink: uplink.map(str::to_string),
+            old_prefix: old_prefix.map(|prefix| prefix.to_string()),
+            new_prefix: Some(new_prefix.to_string()),
+            hosts,
+        }
+    }
+
+    /// The records of `uplink` that were withdrawn when it went down or its
+    /// prefix expired.
+    pub fn withdrawal(
+        uplink: &str,
+        old_prefix: Option<Ipv6Prefix>,
+        hosts: Vec<HostChange>,
+    ) -> Self {
+        Self {
+            timestamp: unix_time(),
+            uplink: Some(uplink.to_string()),
+            old_prefix: old_prefix.map(|prefix| prefix.to_string()),
+            new_prefix: None,
+            hosts,
+        }
+    }
+
+    /// Whether the prefix or any published address changed.
+    pub fn has_changes(&self) -> bool {
+        self.old_prefix != self.new_prefix || !self.hosts.is_empty()
+    }
+}
+
+impl HostChange {
+    pub fn new(hostname: &str, old_address: Option<IpAddr>, new_address: IpAddr) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            record_type: RecordType::for_address(new_address).to_string(),
+            old_address,
+            new_address: Some(new_address),
+        }
+    }
+
+    /// A withdrawn AAAA record.
+    pub fn withdrawn(hostname: &str, old_address: Option<IpAddr>) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            record_type: RecordType::Aaaa.to_string(),
+            old_address,
+            new_address: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_event_json() {
+        let old = Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap();
+        let new = Ipv6Prefix::from_cidr("2001:db8:2::/64").unwrap();
+        let event = ChangeEvent::new(
+            Some("isp1"),
+            Some(old),
+            new,
+            vec![HostChange::new(
+                "nas.example.com",
+                Some("2001:db8:1::10".parse().unwrap()),
+                "2001:db8:2::10".parse().unwrap(),
+            )],
+        );
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["uplink"], "isp1");
+        assert_eq!(json["old_prefix"], "2001:db8:1::/64");
+        assert_eq!(json["new_prefix"], "2001:db8:2::/64");
+        assert_eq!(json["hosts"][0]["type"], "AAAA");
+        assert_eq!(json["hosts"][0]["old_address"], "2001:db8:1::10");
+        assert_eq!(json["hosts"][0]["new_address"], "2001:db8:2::10");
+        assert!(event.has_changes());
+    }
+
+    #[test]
+    fn test_unchanged_event() {
+        let prefix = Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap();
+        assert!(!ChangeEvent::new(None, Some(prefix), prefix, Vec::new()).has_changes());
+        assert!(ChangeEvent::new(None, None, prefix, Vec::new()).has_changes());
+    }
+
+    #[test]
+    fn test_withdrawal_event() {
+        let prefix = Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap();
+        let event = ChangeEvent::withdrawal(
+            "isp1",
+            Some(prefix),
+            vec![HostChange::withdrawn(
+                "nas.example.com",
+                Some("2001:db8:1::10".parse().unwrap()),
+            )],
+        );
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["uplink"], "isp1");
+        assert_eq!(json["old_prefix"], "2001:db8:1::/64");
+        assert!(json["new_prefix"].is_null());
+        assert_eq!(json["hosts"][0]["old_address"], "2001:db8:1::10");
+        assert!(json["hosts"][0]["new_address"].is_null());
+        assert!(event.has_changes());
+    }
+}
@@ -0,0 +1,321 @@
+use axum::{
+    extract::{Query, State as AxumState},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+
+use crate::config::HistoryConfig;
+use crate::dyndns2::AppState;
+use crate::ipv6::Ipv6Prefix;
+
+/// A period during which an uplink kept reporting the same prefix.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefixObservation {
+    pub uplink: Option<String>,
+    pub prefix: String,
+    /// Seconds since the Unix epoch.
+    pub first_seen: u64,
+    /// Seconds since the Unix epoch.
+    pub last_seen: u64,
+    /// Where the last report came from, e.g. `http 198.51.100.7` or `mqtt`.
+    pub source: String,
+    pub reports: u64,
+    /// Outcome of publishing each record in this prefix.
+    pub hosts: Vec<HostOutcome>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostOutcome {
+    pub hostname: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    /// Address the record was published with; `None` if it could not be computed.
+    pub address: Option<IpAddr>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Updated,
+    /// Published to some of the host's providers, but not all.
+    Partial,
+    Unchanged,
+    Failed,
+}
+
+/// Query parameters of `GET /api/v1/history`; times are seconds since the
+/// Unix epoch.
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Only observations seen at or after this time.
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// Only observations first seen at or before this time.
+    #[serde(default)]
+    pub until: Option<u64>,
+    #[serde(default)]
+    pub uplink: Option<String>,
+}
+
+/// Observed prefixes, oldest first, bounded by `[history]` retention.
+#[derive(Debug, Clone)]
+pub struct PrefixHistory {
+    config: HistoryConfig,
+    entries: Arc<RwLock<VecDeque<PrefixObservation>>>,
+}
+
+impl PrefixHistory {
+    pub fn new(config: &HistoryConfig) -> Self {
+        Self {
+            config: config.clone(),
+            entries: Arc::new(RwLock::new(VecDeque::new())),
+        }
+    }
+
+    /// Records a report of `prefix` for `uplink`. Extends the latest
+    /// observation of the uplink if it has the same prefix and starts a new
+    /// one otherwise.
+    pub async fn observe(&self, uplink: Option<&str>, prefix: Ipv6Prefix, source: &str, now: u64) {
+        let prefix = prefix.to_string();
+        let mut entries = self.entries.write().await;
+
+        match latest(&mut entries, uplink, &prefix) {
+            Some(entry) => {
+                entry.last_seen = now;
+                entry.source = source.to_string();
+                entry.reports += 1;
+            }
+            None => entries.push_back(PrefixObservation {
+                uplink: uplink.map(str::to_string),
+                prefix,
+                first_seen: now,
+                last_seen: now,
+                source: source.to_string(),
+                reports: 1,
+                hosts: Vec::new(),
+            }),
+        }
+
+        self.prune(&mut entries, now);
+    }
+
+    /// Adds the outcome of publishing `prefix` to the latest observation of
+    /// `uplink`. Outcomes replace earlier ones of the same record unless they
+    /// are [`Outcome::Unchanged`].
+    pub async fn record_outcomes(
+        &self,
+        uplink: Option<&str>,
+        prefix: Ipv6Prefix,
+        hosts: Vec<HostOutcome>,
+    ) {
+        let mut entries = self.entries.write().await;
+        let Some(entry) = latest(&mut entries, uplink, &prefix.to_string()) else {
+            return;
+        };
+
+        for host in hosts {
+            let known = entry.hosts.iter_mut().find(|known| {
+                known.hostname == host.hostname && known.record_type == host.record_type
+            });
+            match known {
+                Some(known) if host.outcome != Outcome::Unchanged => *known = host,
+                Some(_) => {}
+                None => entry.hosts.push(host),
+            }
+        }
+    }
+
+    /// Observations that overlap the range of `query`, oldest first.
+    pub async fn query(&self, query: &HistoryQuery, now: u64) -> Vec<PrefixObservation> {
+        let mut entries = self.entries.write().await;
+        self.prune(&mut entries, now);
+
+        entries
+            .iter()
+            .filter(|entry| query.since.is_none_or(|since| entry.last_seen >= since))
+            .filter(|entry| query.until.is_none_or(|until| entry.first_seen <= until))
+            .filter(|entry| {
+                query
+                    .uplink
+                    .as_deref()
+                    .is_none_or(|uplink| entry.uplink.as_deref() == Some(uplink))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Drops observations beyond `max_entries` or last seen before `max_age_secs`.
+    fn prune(&self, entries: &mut VecDeque<PrefixObservation>, now: u64) {
+        while entries.len() > self.config.max_entries {
+            entries.pop_front();
+        }
+        if let Some(max_age) = self.config.max_age_secs {
+            entries.retain(|entry| now.saturating_sub(entry.last_seen) <= max_age);
+        }
+    }
+}
+
+/// Latest observation of `uplink`, if it is for `prefix`.
+fn latest<'a>(
+    entries: &'a mut VecDeque<PrefixObservation>,
+    uplink: Option<&str>,
+    prefix: &str,
+) -> Option<&'a mut PrefixObservation> {
+    entries
+        .iter_mut()
+        .rev()
+        .find(|entry| entry.uplink.as_deref() == uplink)
+        .filter(|entry| entry.prefix == prefix)
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+pub async fn handle_history(
+    AxumState(state): AxumState<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Vec<PrefixObservation>> {
+    Json(state.history.query(&query, unix_time()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(cidr: &str) -> Ipv6Prefix {
+        Ipv6Prefix::from_cidr(cidr).unwrap()
+    }
+
+    fn outcome(hostname: &str, outcome: Outcome) -> HostOutcome {
+        HostOutcome {
+            hostname: hostname.to_string(),
+            record_type: "AAAA".to_string(),
+            address: None,
+            outcome,
+        }
+    }
+
+    fn history(max_entries: usize, max_age_secs: Option<u64>) -> PrefixHistory {
+        PrefixHistory::new(&HistoryConfig {
+            max_entries,
+            max_age_secs,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_observe_extends_and_starts_entries() {
+        let history = history(100, None);
+        let first = prefix("2001:db8:1::/64");
+        let second = prefix("2001:db8:2::/64");
+
+        history.observe(None, first, "http", 100).await;
+        history
+            .record_outcomes(
+                None,
+                first,
+                vec![outcome("nas.example.com", Outcome::Updated)],
+            )
+            .await;
+        history.observe(None, first, "mqtt", 200).await;
+        history
+            .record_outcomes(
+                None,
+                first,
+                vec![outcome("nas.example.com", Outcome::Unchanged)],
+            )
+            .await;
+        history.observe(Some("isp1"), second, "http", 250).await;
+        history.observe(None, second, "http", 300).await;
+        history
+            .record_outcomes(
+                None,
+                first,
+                vec![outcome("tv.example.com", Outcome::Failed)],
+            )
+            .await;
+
+        let entries = history.query(&HistoryQuery::default(), 300).await;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prefix, "2001:db8:1::/64");
+        assert_eq!((entries[0].first_seen, entries[0].last_seen), (100, 200));
+        assert_eq!(entries[0].reports, 2);
+        assert_eq!(entries[0].source, "mqtt");
+        assert_eq!(entries[0].hosts.len(), 1);
+        assert_eq!(entries[0].hosts[0].outcome, Outcome::Updated);
+        assert_eq!(entries[1].uplink.as_deref(), Some("isp1"));
+        assert_eq!(entries[2].prefix, "2001:db8:2::/64");
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_range_and_uplink() {
+        let history = history(100, None);
+        history
+            .observe(None, prefix("2001:db8:1::/64"), "http", 100)
+            .await;
+        history
+            .observe(None, prefix("2001:db8:1::/64"), "http", 200)
+            .await;
+        history
+            .observe(None, prefix("2001:db8:2::/64"), "http", 300)
+            .await;
+        history
+            .observe(Some("isp1"), prefix("2001:db8:3::/64"), "http", 400)
+            .await;
+
+        let query = |since, until, uplink: Option<&str>| HistoryQuery {
+            since,
+            until,
+            uplink: uplink.map(str::to_string),
+        };
+        let prefixes = |entries: Vec<PrefixObservation>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.prefix)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            prefixes(history.query(&query(Some(150), Some(300), None), 400).await),
+            ["2001:db8:1::/64", "2001:db8:2::/64"]
+        );
+        assert_eq!(
+            prefixes(history.query(&query(Some(201), None, None), 400).await),
+            ["2001:db8:2::/64", "2001:db8:3::/64"]
+        );
+        assert_eq!(
+            prefixes(history.query(&query(None, None, Some("isp1")), 400).await),
+            ["2001:db8:3::/64"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let history = history(2, Some(1000));
+        for (i, cidr) in ["2001:db8:1::/64", "2001:db8:2::/64", "2001:db8:3::/64"]
+            .into_iter()
+            .enumerate()
+        {
+            history
+                .observe(None, prefix(cidr), "http", i as u64 * 100)
+                .await;
+        }
+
+        let entries = history.query(&HistoryQuery::default(), 200).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].prefix, "2001:db8:2::/64");
+
+        let entries = history.query(&HistoryQuery::default(), 1150).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].prefix, "2001:db8:3::/64");
+    }
+}
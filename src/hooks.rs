@@ -0,0 +1,401 @@
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, warn};
+
+use crate::config::HookConfig;
+use crate::event::{ChangeEvent, HostChange};
+use crate::history::unix_time;
+
+/// Outcome of the most recent run of a hook. For hooks with `per_host`, the
+/// run of the first host that failed, or else of the last host.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookRun {
+    pub command: String,
+    /// Host the run was for, for hooks with `per_host`.
+    pub host: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub finished_at: u64,
+    /// Exit code; `None` if the command could not be started, was killed by
+    /// a signal or timed out.
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub error: Option<String>,
+    pub success: bool,
+}
+
+/// Runs the `on_change` commands and remembers their last results.
+#[derive(Debug, Clone)]
+pub struct HookRunner {
+    hooks: Arc<Vec<HookConfig>>,
+    last_runs: Arc<RwLock<Vec<Option<HookRun>>>>,
+    /// Events waiting for the worker, which runs them in order; `None`
+    /// without hooks.
+    events: Option<mpsc::UnboundedSender<ChangeEvent>>,
+}
+
+impl HookRunner {
+    /// Creates the runner and, if there are hooks, spawns its worker.
+    pub fn new(hooks: &[HookConfig]) -> Self {
+        let mut runner = Self {
+            hooks: Arc::new(hooks.to_vec()),
+            last_runs: Arc::new(RwLock::new(vec![None; hooks.len()])),
+            events: None,
+        };
+        if !hooks.is_empty() {
+            let (sender, mut receiver) = mpsc::unbounded_channel::<ChangeEvent>();
+            let worker = runner.clone();
+            tokio::spawn(async move {
+                while let Some(event) = receiver.recv().await {
+                    worker.run_all(&event).await;
+                }
+            });
+            runner.events = Some(sender);
+        }
+        runner
+    }
+
+    /// Runs the hooks for `event` in the background, after those of earlier
+    /// events have finished, so that commands never see prefixes out of
+    /// order.
+    pub fn notify(&self, event: &ChangeEvent) {
+        if let Some(events) = &self.events {
+            if events.send(event.clone()).is_err() {
+                error!("Hook worker stopped, dropping change event");
+            }
+        }
+    }
+
+    /// Last result of every hook, in configuration order.
+    pub async fn last_runs(&self) -> Vec<Option<HookRun>> {
+        self.last_runs.read().await.clone()
+    }
+
+    async fn run_all(&self, event: &ChangeEvent) {
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if hook.per_host {
+                // The first failing host is kept, so that a later success
+                // does not hide it.
+                let mut failed = false;
+                for host in &event.hosts {
+                    let run = run_hook(hook, event, Some(host)).await;
+                    if !failed {
+                        failed = !run.success;
+                        self.last_runs.write().await[index] = Some(run);
+                    }
+                }
+            } else {
+                let run = run_hook(hook, event, None).await;
+                self.last_runs.write().await[index] = Some(run);
+            }
+        }
+    }
+}
+
+/// Environment passed to a hook for `event` and, for per-host hooks, `host`.
+pub fn environment(event: &ChangeEvent, host: Option<&HostChange>) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        (
+            "DDNS6_OLD_PREFIX",
+            event.old_prefix.clone().unwrap_or_default(),
+        ),
+        (
+            "DDNS6_NEW_PREFIX",
+            event.new_prefix.clone().unwrap_or_default(),
+        ),
+        ("DDNS6_UPLINK", event.uplink.clone().unwrap_or_default()),
+        (
+            "DDNS6_HOSTS",
+            event
+                .hosts
+                .iter()
+                .map(|host| host.hostname.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    ];
+
+    if let Some(host) = host {
+        env.push(("DDNS6_HOST", host.hostname.clone()));
+        env.push(("DDNS6_RECORD_TYPE", host.record_type.clone()));
+        env.push((
+            "DDNS6_ADDRESS",
+            host.new_address
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+        ));
+        env.push((
+            "DDNS6_OLD_ADDRESS",
+            host.old_address
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+        ));
+    }
+
+    env
+}
+
+async fn run_hook(hook: &HookConfig, event: &ChangeEvent, host: Option<&HostChange>) -> HookRun {
+    let host_name = host.map(|host| host.hostname.clone());
+    debug!("Running hook {:?} for {:?}", hook.command, host_name);
+
+    let mut run = run_command(&hook.command, environment(event, host), hook.timeout_secs).await;
+    run.host = host_name;
+    run
+}
+
+/// Runs `command` with `/bin/sh -c`, forwarding its output to the log, and
+/// kills it after `timeout_secs`.
+pub async fn run_command(
+    command: &str,
+    env: Vec<(&'static str, String)>,
+    timeout_secs: u64,
+) -> HookRun {
+    let mut run = HookRun {
+        command: command.to_string(),
+        host: None,
+        finished_at: 0,
+        exit_code: None,
+        timed_out: false,
+        error: None,
+        success: false,
+    };
+
+    let child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to start hook {:?}: {}", command, e);
+            run.error = Some(e.to_string());
+            run.finished_at = unix_time();
+            return run;
+        }
+    };
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| log_lines(command.to_string(), out, false));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| log_lines(command.to_string(), err, true));
+
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), child.wait()).await {
+        Ok(Ok(status)) => {
+            run.exit_code = status.code();
+            run.success = status.success();
+            if run.success {
+                info!("Hook {:?} finished", command);
+            } else {
+                warn!("Hook {:?} failed: {}", command, status);
+                run.error = Some(status.to_string());
+            }
+        }
+        Ok(Err(e)) => {
+            error!("Failed to wait for hook {:?}: {}", command, e);
+            run.error = Some(e.to_string());
+        }
+        Err(_) => {
+            warn!(
+                "Hook {:?} timed out after {}s, killing it",
+                command, timeout_secs
+            );
+            let _ = child.kill().await;
+            run.timed_out = true;
+            run.error = Some(format!("timed out after {}s", timeout_secs));
+        }
+    }
+
+    // After a timeout, children of the shell may still hold the pipes open.
+    for task in [stdout, stderr].into_iter().flatten() {
+        if run.timed_out {
+            task.abort();
+        } else {
+            let _ = task.await;
+        }
+    }
+
+    run.finished_at = unix_time();
+    run
+}
+
+/// Forwards the output of a hook to the log, line by line.
+fn log_lines(
+    command: String,
+    output: impl AsyncRead + Unpin + Send + 'static,
+    is_stderr: bool,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if is_stderr {
+                warn!("Hook {:?}: {}", command, line);
+            } else {
+                info!("Hook {:?}: {}", command, line);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipv6::Ipv6Prefix;
+
+    fn hook(command: &str, per_host: bool, timeout_secs: u64) -> HookConfig {
+        HookConfig {
+            command: command.to_string(),
+            per_host,
+            timeout_secs,
+        }
+    }
+
+    fn event() -> ChangeEvent {
+        ChangeEvent::new(
+            None,
+            Some(Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap()),
+            Ipv6Prefix::from_cidr("2001:db8:2::/64").unwrap(),
+            vec![
+                HostChange::new(
+                    "nas.example.com",
+                    Some("2001:db8:1::10".parse().unwrap()),
+                    "2001:db8:2::10".parse().unwrap(),
+                ),
+                HostChange::new("tv.example.com", None, "2001:db8:2::20".parse().unwrap()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_environment() {
+        let event = event();
+
+        let env = environment(&event, None);
+        let value = |env: &[(&str, String)], name| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(value(&env, "DDNS6_OLD_PREFIX").unwrap(), "2001:db8:1::/64");
+        assert_eq!(value(&env, "DDNS6_NEW_PREFIX").unwrap(), "2001:db8:2::/64");
+        assert_eq!(
+            value(&env, "DDNS6_HOSTS").unwrap(),
+            "nas.example.com tv.example.com"
+        );
+        assert!(value(&env, "DDNS6_HOST").is_none());
+
+        let env = environment(&event, Some(&event.hosts[1]));
+        assert_eq!(value(&env, "DDNS6_HOST").unwrap(), "tv.example.com");
+        assert_eq!(value(&env, "DDNS6_ADDRESS").unwrap(), "2001:db8:2::20");
+        assert_eq!(value(&env, "DDNS6_OLD_ADDRESS").unwrap(), "");
+        assert_eq!(value(&env, "DDNS6_RECORD_TYPE").unwrap(), "AAAA");
+    }
+
+    #[tokio::test]
+    async fn test_run_hook() {
+        let event = event();
+
+        let run = run_hook(
+            &hook("test \"$DDNS6_NEW_PREFIX\" = 2001:db8:2::/64", false, 5),
+            &event,
+            None,
+        )
+        .await;
+        assert!(run.success, "{:?}", run);
+        assert_eq!(run.exit_code, Some(0));
+
+        let run = run_hook(&hook("echo oops >&2; exit 3", false, 5), &event, None).await;
+        assert!(!run.success);
+        assert_eq!(run.exit_code, Some(3));
+
+        let run = run_hook(
+            &hook("test \"$DDNS6_HOST\" = nas.example.com", true, 5),
+            &event,
+            Some(&event.hosts[0]),
+        )
+        .await;
+        assert!(run.success, "{:?}", run);
+        assert_eq!(run.host.as_deref(), Some("nas.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_run_hook_timeout() {
+        let run = run_hook(&hook("sleep 10", false, 1), &event(), None).await;
+        assert!(run.timed_out);
+        assert!(!run.success);
+        assert_eq!(run.exit_code, None);
+    }
+
+    #[tokio::test]
+    async fn test_events_run_in_order() {
+        let dir = std::env::temp_dir().join(format!("ddns6-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("prefixes");
+        // The hooks of the first event take longer than those of the second.
+        let runner = HookRunner::new(&[hook(
+            &format!(
+                "[ \"$DDNS6_NEW_PREFIX\" = 2001:db8:2::/64 ] && sleep 1; echo \"$DDNS6_NEW_PREFIX\" >> {}",
+                log.display()
+            ),
+            false,
+            5,
+        )]);
+
+        let second = ChangeEvent::new(
+            None,
+            Some(Ipv6Prefix::from_cidr("2001:db8:2::/64").unwrap()),
+            Ipv6Prefix::from_cidr("2001:db8:3::/64").unwrap(),
+            Vec::new(),
+        );
+        runner.notify(&event());
+        runner.notify(&second);
+
+        let mut written = String::new();
+        for _ in 0..100 {
+            written = std::fs::read_to_string(&log).unwrap_or_default();
+            if written.lines().count() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(written, "2001:db8:2::/64\n2001:db8:3::/64\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_last_runs() {
+        let runner = HookRunner::new(&[hook("true", false, 5), hook("exit 1", true, 5)]);
+        assert!(runner.last_runs().await.iter().all(Option::is_none));
+
+        runner.run_all(&event()).await;
+
+        let runs = runner.last_runs().await;
+        assert!(runs[0].as_ref().unwrap().success);
+        let per_host = runs[1].as_ref().unwrap();
+        assert!(!per_host.success);
+        assert_eq!(per_host.host.as_deref(), Some("nas.example.com"));
+
+        let runner = HookRunner::new(&[hook("test \"$DDNS6_HOST\" = tv.example.com", true, 5)]);
+        runner.run_all(&event()).await;
+
+        let run = runner.last_runs().await[0].clone().unwrap();
+        assert!(!run.success);
+        assert_eq!(run.exit_code, Some(1));
+        assert_eq!(run.host.as_deref(), Some("nas.example.com"));
+    }
+}
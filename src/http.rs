@@ -3,27 +3,76 @@ use std::sync::Arc;
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
+use crate::cleanup;
 use crate::cloudflare::CloudflareClient;
 use crate::config::Config;
+use crate::debounce::{Debouncer, FlapDetector};
+use crate::dns;
 use crate::dyndns2::{handle_update, AppState};
 use crate::error::Result;
+use crate::history::{handle_history, PrefixHistory};
+use crate::hooks::HookRunner;
+use crate::mqtt::{self, MqttPublisher};
+use crate::output;
+use crate::provider::Providers;
 use crate::state::StateCache;
+use crate::status::handle_status;
+use crate::uplink::{self, UplinkTracker};
+use crate::webhook::WebhookNotifier;
 
 pub async fn create_server(config: Arc<Config>) -> Result<Router> {
-    let cloudflare_client = Arc::new(CloudflareClient::new(
-        config.cloudflare.api_token.clone(),
-        config.cloudflare.zone_id.clone(),
-        config.cloudflare.ttl,
-    ));
+    let cloudflare_client = config
+        .cloudflare
+        .as_ref()
+        .map(|cloudflare| Arc::new(CloudflareClient::new(cloudflare)));
+
+    let state_cache = StateCache::new();
+    let providers = Providers::new(&config, cloudflare_client);
+
+    if config.cleanup.enabled {
+        info!("Cleanup of stale records enabled");
+        cleanup::spawn(config.clone(), providers.clone(), state_cache.clone());
+    }
+
+    if let Some(dns_config) = &config.dns {
+        let addr = dns::spawn(dns_config, state_cache.clone()).await?;
+        info!("DNS server listening on {} (UDP and TCP)", addr);
+    }
+
+    output::spawn(config.outputs.clone(), state_cache.clone());
+
+    let mqtt = match &config.mqtt {
+        Some(mqtt_config) => Some(MqttPublisher::new(mqtt_config, state_cache.clone())?),
+        None => None,
+    };
+    let (mqtt, mqtt_eventloop) = mqtt.unzip();
 
     let state = AppState {
         config: config.clone(),
-        state_cache: StateCache::new(),
-        cloudflare_client,
+        state_cache,
+        providers,
+        uplinks: UplinkTracker::new(),
+        webhooks: WebhookNotifier::new(&config.webhooks),
+        hooks: HookRunner::new(&config.on_change),
+        mqtt,
+        debouncer: Debouncer::new(),
+        flaps: FlapDetector::new(),
+        history: PrefixHistory::new(&config.history),
     };
 
+    if !config.uplinks.is_empty() {
+        uplink::spawn_expiry(state.clone());
+    }
+
+    if let Some(eventloop) = mqtt_eventloop {
+        info!("Publishing state to MQTT broker");
+        mqtt::spawn(eventloop, state.clone());
+    }
+
     let app = Router::new()
         .route("/update", get(handle_update))
+        .route("/api/v1/status", get(handle_status))
+        .route("/api/v1/history", get(handle_history))
         .route("/", get(health_check))
         .layer(TraceLayer::new_for_http())
         .with_state(state);
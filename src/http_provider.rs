@@ -0,0 +1,319 @@
+use reqwest::{Client, Method};
+use serde_json::Value;
+use std::net::IpAddr;
+use tracing::{debug, error, info};
+
+use crate::config::{
+    longest_zone, HostMapping, HttpProviderConfig, HttpRequestTemplate, ResponseCheck,
+};
+use crate::error::{Ddns6Error, Result};
+use crate::provider::http_client;
+use crate::record::RecordType;
+
+/// Values substituted for the `{name}` placeholders of a template.
+type Variables = Vec<(&'static str, String)>;
+
+/// A provider calling an arbitrary HTTP API with requests rendered from the
+/// templates in its configuration.
+#[derive(Debug, Clone)]
+pub struct HttpProvider {
+    client: Client,
+    config: HttpProviderConfig,
+}
+
+/// One step of a JSONPath-style location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+impl HttpProvider {
+    pub fn new(config: &HttpProviderConfig) -> Self {
+        Self {
+            client: http_client(),
+            config: config.clone(),
+        }
+    }
+
+    /// Sends the `update` request for the A or AAAA record of `host`.
+    /// `addresses` holds the addresses of the record on all uplinks.
+    pub async fn update_record(
+        &self,
+        host: &HostMapping,
+        address: IpAddr,
+        addresses: &[IpAddr],
+    ) -> Result<()> {
+        let record_type = RecordType::for_address(address);
+        info!(
+            "Updating {} record for {} to {} through the HTTP API",
+            record_type, host.hostname, address
+        );
+        let variables = self.variables(host, record_type, Some(address), addresses)?;
+        self.send(&self.config.update, &variables).await
+    }
+
+    /// Sends the `delete` request for the records of `host` of
+    /// `record_type`. Returns whether there is a `delete` request.
+    pub async fn delete_record(&self, host: &HostMapping, record_type: RecordType) -> Result<bool> {
+        let Some(delete) = &self.config.delete else {
+            return Ok(false);
+        };
+        info!(
+            "Deleting {} record for {} through the HTTP API",
+            record_type, host.hostname
+        );
+        let variables = self.variables(host, record_type, None, &[])?;
+        self.send(delete, &variables).await?;
+        Ok(true)
+    }
+
+    fn variables(
+        &self,
+        host: &HostMapping,
+        record_type: RecordType,
+        address: Option<IpAddr>,
+        addresses: &[IpAddr],
+    ) -> Result<Variables> {
+        let zone = longest_zone(&self.config.zones, &host.hostname, String::as_str)
+            .ok_or_else(|| Ddns6Error::ZoneNotFound(host.hostname.clone()))?;
+        let subname = host
+            .hostname
+            .strip_suffix(zone.as_str())
+            .and_then(|rest| rest.strip_suffix('.'))
+            .unwrap_or("@");
+        let addresses: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+
+        Ok(vec![
+            ("hostname", host.hostname.clone()),
+            ("subname", subname.to_string()),
+            ("zone", zone.clone()),
+            ("type", record_type.to_string()),
+            (
+                "address",
+                address
+                    .map(|address| address.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("addresses", Value::from(addresses).to_string()),
+            ("ttl", host.ttl.unwrap_or(self.config.ttl).to_string()),
+        ])
+    }
+
+    async fn send(&self, template: &HttpRequestTemplate, variables: &Variables) -> Result<()> {
+        let method = Method::from_bytes(template.method.as_bytes())
+            .map_err(|_| Ddns6Error::HttpProvider(format!("Invalid method {}", template.method)))?;
+        let url = render(&template.url, variables);
+        debug!("Sending {} {}", method, url);
+
+        let mut request = self.client.request(method.clone(), &url);
+        for (name, value) in &template.headers {
+            request = request.header(name, render(value.expose(), variables));
+        }
+        if let Some(body) = &template.body {
+            let has_content_type = template
+                .headers
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case("content-type"));
+            if !has_content_type {
+                request = request.header("Content-Type", "application/json");
+            }
+            request = request.body(render(body, variables));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            error!("HTTP API error (status {}): {}", status, body);
+            return Err(Ddns6Error::HttpProvider(format!(
+                "{} {} failed: {} - {}",
+                method, url, status, body
+            )));
+        }
+
+        check_response(&template.checks, &body, variables)
+    }
+}
+
+/// Replaces the `{name}` placeholders in `template`.
+fn render(template: &str, variables: &Variables) -> String {
+    variables
+        .iter()
+        .fold(template.to_string(), |rendered, (name, value)| {
+            rendered.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// Checks the JSON response `body`. A check without `equals` passes if the
+/// value is present and not null; expected strings also match numbers and
+/// booleans with the same text, e.g. `"{ttl}"`.
+fn check_response(checks: &[ResponseCheck], body: &str, variables: &Variables) -> Result<()> {
+    if checks.is_empty() {
+        return Ok(());
+    }
+    let response: Value = serde_json::from_str(body)
+        .map_err(|e| Ddns6Error::HttpProvider(format!("Response is not JSON: {}", e)))?;
+
+    for check in checks {
+        let path = parse_json_path(&check.path).map_err(Ddns6Error::HttpProvider)?;
+        let actual = lookup(&response, &path);
+        let passed = match (&check.equals, actual) {
+            (None, actual) => actual.is_some_and(|actual| !actual.is_null()),
+            (Some(Value::String(expected)), Some(actual)) => {
+                let expected = render(expected, variables);
+                match actual {
+                    Value::String(actual) => *actual == expected,
+                    Value::Number(_) | Value::Bool(_) => serde_json::from_str::<Value>(&expected)
+                        .is_ok_and(|expected| expected == *actual),
+                    _ => false,
+                }
+            }
+            (Some(expected), actual) => actual.unwrap_or(&Value::Null) == expected,
+        };
+
+        if !passed {
+            return Err(Ddns6Error::HttpProvider(format!(
+                "Check of {} failed, response has {}",
+                check.path,
+                actual.map_or_else(|| "nothing there".to_string(), Value::to_string)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parses the subset of JSONPath used by response checks: `$` followed by
+/// `.field`, `['field']` and `[index]` steps.
+pub fn parse_json_path(path: &str) -> std::result::Result<Vec<PathSegment>, String> {
+    let mut rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| "must start with $".to_string())?;
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err("empty field name".to_string());
+            }
+            segments.push(PathSegment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| "unclosed [".to_string())?;
+            let inner = &after[..end];
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|field| field.strip_suffix('\''))
+                .or_else(|| {
+                    inner
+                        .strip_prefix('"')
+                        .and_then(|field| field.strip_suffix('"'))
+                });
+            let segment = match quoted {
+                Some(field) => PathSegment::Field(field.to_string()),
+                None => PathSegment::Index(
+                    inner
+                        .parse()
+                        .map_err(|_| format!("invalid index {}", inner))?,
+                ),
+            };
+            segments.push(segment);
+            rest = &after[end + 1..];
+        } else {
+            return Err(format!("unexpected {:?}", rest));
+        }
+    }
+    Ok(segments)
+}
+
+fn lookup<'a>(value: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, segment| match segment {
+        PathSegment::Field(field) => value.get(field.as_str()),
+        PathSegment::Index(index) => value.get(*index),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variables() -> Variables {
+        vec![
+            ("hostname", "nas.example.com".to_string()),
+            ("address", "2001:db8::10".to_string()),
+            ("addresses", "[\"2001:db8::10\"]".to_string()),
+            ("ttl", "3600".to_string()),
+        ]
+    }
+
+    fn check(path: &str, equals: Option<Value>) -> ResponseCheck {
+        ResponseCheck {
+            path: path.to_string(),
+            equals,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(
+                r#"{"name": "{hostname}", "ttl": {ttl}, "records": {addresses}, "x": "{unknown}"}"#,
+                &variables()
+            ),
+            r#"{"name": "nas.example.com", "ttl": 3600, "records": ["2001:db8::10"], "x": "{unknown}"}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        use PathSegment::{Field, Index};
+
+        assert_eq!(parse_json_path("$").unwrap(), vec![]);
+        assert_eq!(
+            parse_json_path("$.records[0].content").unwrap(),
+            vec![
+                Field("records".to_string()),
+                Index(0),
+                Field("content".to_string())
+            ]
+        );
+        assert_eq!(
+            parse_json_path("$['dns-record'].id").unwrap(),
+            vec![Field("dns-record".to_string()), Field("id".to_string())]
+        );
+        assert!(parse_json_path("records").is_err());
+        assert!(parse_json_path("$..records").is_err());
+        assert!(parse_json_path("$.records[x]").is_err());
+        assert!(parse_json_path("$.records[0").is_err());
+    }
+
+    #[test]
+    fn test_check_response() {
+        let body = json!({
+            "success": true,
+            "ttl": 3600,
+            "records": ["2001:db8::10"],
+            "error": null,
+        })
+        .to_string();
+        let passes = |check: ResponseCheck| check_response(&[check], &body, &variables()).is_ok();
+
+        assert!(passes(check("$.success", Some(json!(true)))));
+        assert!(passes(check("$.records[0]", Some(json!("{address}")))));
+        assert!(passes(check("$.ttl", Some(json!("{ttl}")))));
+        assert!(passes(check("$.ttl", Some(json!(3600)))));
+        assert!(passes(check("$.records", None)));
+        assert!(passes(check("$.error", Some(Value::Null))));
+        assert!(passes(check("$.missing", Some(Value::Null))));
+
+        assert!(!passes(check("$.success", Some(json!(false)))));
+        assert!(!passes(check("$.records[1]", Some(json!("{address}")))));
+        assert!(!passes(check("$.error", None)));
+        assert!(!passes(check("$.missing", None)));
+
+        assert!(check_response(&[], "not json", &variables()).is_ok());
+        assert!(check_response(&[check("$.success", None)], "not json", &variables()).is_err());
+    }
+}
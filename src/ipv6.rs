@@ -32,6 +32,15 @@ impl Ipv6Prefix {
         })
     }
 
+    /// Parses a prefix in CIDR notation, e.g. `fd12:3456:789a:1::/64`.
+    pub fn from_cidr(cidr: &str) -> Result<Self> {
+        let network = cidr
+            .parse::<Ipv6Net>()
+            .map_err(|e| Ddns6Error::Ipv6Parse(format!("Invalid prefix {}: {}", cidr, e)))?;
+
+        Self::from_address(network.addr(), network.prefix_len())
+    }
+
     pub fn extract_from_address(addr: Ipv6Addr, default_prefix_len: u8) -> Result<Self> {
         Self::from_address(addr, default_prefix_len)
     }
@@ -67,9 +76,246 @@ impl Ipv6Prefix {
 
         Ok(Ipv6Addr::from(result_bytes))
     }
+
+    /// Computes the RFC 7217 stable-privacy address within this /64 the same
+    /// way Linux does with `addr_gen_mode = stable_privacy`: one SHA-1 block
+    /// over the secret, the upper 64 bits of the prefix, the hardware address
+    /// and the DAD counter. Reserved Interface IDs are skipped by increasing
+    /// the DAD counter until it exceeds the kernel's default `idgen_retries`
+    /// of 3; like the kernel, this caps the absolute counter, so a
+    /// `dad_counter` of 3 or more gets a single attempt.
+    pub fn stable_privacy_address(
+        &self,
+        secret: Ipv6Addr,
+        mac: &str,
+        dad_counter: u8,
+    ) -> Result<Ipv6Addr> {
+        if self.prefix_len != 64 {
+            return Err(Ddns6Error::Ipv6Parse(format!(
+                "Stable-privacy addresses require a /64 prefix, got /{}",
+                self.prefix_len
+            )));
+        }
+
+        let hwaddr = parse_mac(mac)?;
+        let prefix = self.addr.octets();
+
+        for dad_count in dad_counter..=dad_counter.max(STABLE_PRIVACY_RETRIES) {
+            // Layout of the kernel's packed `data` union, zero padded to one block.
+            let mut block = [0u8; 64];
+            block[..16].copy_from_slice(&secret.octets());
+            block[16..24].copy_from_slice(&prefix[..8]);
+            block[24..30].copy_from_slice(&hwaddr);
+            block[56] = dad_count;
+
+            let mut digest = SHA1_IV;
+            sha1_transform(&mut digest, &block);
+
+            // The kernel stores the native-endian digest words directly into
+            // the address, so this matches little-endian machines.
+            let mut octets = prefix;
+            octets[8..12].copy_from_slice(&digest[0].to_le_bytes());
+            octets[12..16].copy_from_slice(&digest[1].to_le_bytes());
+
+            let address = Ipv6Addr::from(octets);
+            if !is_reserved_interface_id(address) {
+                return Ok(address);
+            }
+        }
+
+        Err(Ddns6Error::InvalidInterfaceId(
+            "Stable-privacy generation only produced reserved Interface IDs".to_string(),
+        ))
+    }
 }
 
-fn parse_interface_id(iid: &str) -> Result<Ipv6Addr> {
+/// Matches the kernel's default `net.ipv6.conf.*.idgen_retries`.
+const STABLE_PRIVACY_RETRIES: u8 = 3;
+
+const SHA1_IV: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+/// The SHA-1 compression function applied to a single block, without the
+/// message padding of a full SHA-1 hash (as `sha1_transform` in the kernel).
+fn sha1_transform(digest: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w = [0u32; 80];
+    for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *digest;
+
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (value, add) in digest.iter_mut().zip([a, b, c, d, e]) {
+        *value = value.wrapping_add(add);
+    }
+}
+
+/// Interface IDs that must not be used for stable-privacy addresses
+/// (RFC 5453): the subnet-router anycast ID, the proxy mobile IPv6 range and
+/// the subnet anycast range.
+fn is_reserved_interface_id(address: Ipv6Addr) -> bool {
+    let iid = u128::from(address) as u64;
+    let high = (iid >> 32) as u32;
+    let low = iid as u32;
+
+    iid == 0
+        || (high == 0x0200_5eff && low & 0xfe00_0000 == 0xfe00_0000)
+        || (high == 0xfdff_ffff && low & 0xffff_ff80 == 0xffff_ff80)
+}
+
+impl std::fmt::Display for Ipv6Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Kind of address a client reported, as far as publishing it is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressScope {
+    Global,
+    UniqueLocal,
+    Unspecified,
+    Loopback,
+    LinkLocal,
+    SiteLocal,
+    Multicast,
+    Documentation,
+    Ipv4Mapped,
+}
+
+impl AddressScope {
+    pub fn of(address: Ipv6Addr) -> Self {
+        let segments = address.segments();
+
+        if address.is_unspecified() {
+            AddressScope::Unspecified
+        } else if address.is_loopback() {
+            AddressScope::Loopback
+        } else if address.to_ipv4_mapped().is_some() {
+            AddressScope::Ipv4Mapped
+        } else if address.is_multicast() {
+            AddressScope::Multicast
+        } else if segments[0] & 0xffc0 == 0xfe80 {
+            AddressScope::LinkLocal
+        } else if segments[0] & 0xffc0 == 0xfec0 {
+            AddressScope::SiteLocal
+        } else if segments[0] & 0xfe00 == 0xfc00 {
+            AddressScope::UniqueLocal
+        } else if (segments[0] == 0x2001 && segments[1] == 0x0db8)
+            || (segments[0] == 0x3fff && segments[1] & 0xf000 == 0)
+        {
+            AddressScope::Documentation
+        } else {
+            AddressScope::Global
+        }
+    }
+}
+
+impl std::fmt::Display for AddressScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AddressScope::Global => "global",
+            AddressScope::UniqueLocal => "unique local",
+            AddressScope::Unspecified => "unspecified",
+            AddressScope::Loopback => "loopback",
+            AddressScope::LinkLocal => "link-local",
+            AddressScope::SiteLocal => "site-local",
+            AddressScope::Multicast => "multicast",
+            AddressScope::Documentation => "documentation",
+            AddressScope::Ipv4Mapped => "IPv4-mapped",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Rejects addresses whose prefix must never end up in public DNS. Unique
+/// local prefixes are only accepted when `allow_ula` is set.
+pub fn check_publishable(address: Ipv6Addr, allow_ula: bool) -> Result<()> {
+    match AddressScope::of(address) {
+        AddressScope::Global => Ok(()),
+        AddressScope::UniqueLocal if allow_ula => Ok(()),
+        scope => Err(Ddns6Error::NonGlobalPrefix(format!(
+            "{} is a {} address",
+            address, scope
+        ))),
+    }
+}
+
+/// Derives the modified EUI-64 Interface ID (RFC 4291, Appendix A) from a
+/// 48-bit MAC address given as `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`.
+/// The Interface ID is returned in the lower 64 bits of the address.
+pub fn eui64_interface_id(mac: &str) -> Result<Ipv6Addr> {
+    let bytes = parse_mac(mac)?;
+
+    let mut octets = [0u8; 16];
+    octets[8] = bytes[0] ^ 0x02;
+    octets[9] = bytes[1];
+    octets[10] = bytes[2];
+    octets[11] = 0xff;
+    octets[12] = 0xfe;
+    octets[13] = bytes[3];
+    octets[14] = bytes[4];
+    octets[15] = bytes[5];
+
+    Ok(Ipv6Addr::from(octets))
+}
+
+fn parse_mac(mac: &str) -> Result<[u8; 6]> {
+    let invalid = || Ddns6Error::InvalidInterfaceId(format!("Invalid MAC address: {}", mac));
+
+    let parts: Vec<&str> = mac.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(invalid());
+    }
+
+    let mut bytes = [0u8; 6];
+    for (byte, part) in bytes.iter_mut().zip(&parts) {
+        if part.len() != 2 {
+            return Err(invalid());
+        }
+        *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+    }
+
+    Ok(bytes)
+}
+
+/// Returns the `ip6.arpa` name of `address`: its 32 nibbles in reverse order.
+pub fn reverse_name(address: Ipv6Addr) -> String {
+    let mut labels: Vec<String> = address
+        .octets()
+        .iter()
+        .rev()
+        .flat_map(|octet| [octet & 0x0f, octet >> 4])
+        .map(|nibble| format!("{:x}", nibble))
+        .collect();
+    labels.push("ip6.arpa".to_string());
+    labels.join(".")
+}
+
+pub(crate) fn parse_interface_id(iid: &str) -> Result<Ipv6Addr> {
     if let Ok(addr) = iid.parse::<Ipv6Addr>() {
         return Ok(addr);
     }
@@ -94,6 +340,82 @@ fn parse_interface_id(iid: &str) -> Result<Ipv6Addr> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_reverse_name() {
+        let prefix = Ipv6Prefix::from_cidr("2001:db8:1234:5678::/64").unwrap();
+        let address = prefix
+            .combine_with_interface_id("::a1b2:c3d4:e5f6:7890")
+            .unwrap();
+        assert_eq!(
+            reverse_name(address),
+            "0.9.8.7.6.f.5.e.4.d.3.c.2.b.1.a.8.7.6.5.4.3.2.1.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+        assert_eq!(
+            reverse_name(Ipv6Addr::LOCALHOST),
+            format!("1.{}ip6.arpa", "0.".repeat(31))
+        );
+    }
+
+    #[test]
+    fn test_from_cidr() {
+        let prefix = Ipv6Prefix::from_cidr("fd12:3456:789a:1::5/64").unwrap();
+        assert_eq!(
+            prefix.network(),
+            "fd12:3456:789a:1::".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(prefix.prefix_len(), 64);
+
+        assert_eq!(prefix.to_string(), "fd12:3456:789a:1::/64");
+
+        assert!(Ipv6Prefix::from_cidr("fd12:3456:789a:1::").is_err());
+        assert!(Ipv6Prefix::from_cidr("192.168.1.0/24").is_err());
+    }
+
+    #[test]
+    fn test_address_scope() {
+        let cases = [
+            ("2a00:1450:4001:812::200e", AddressScope::Global),
+            ("2600::1", AddressScope::Global),
+            ("fd12:3456:789a::1", AddressScope::UniqueLocal),
+            ("fc00::1", AddressScope::UniqueLocal),
+            ("::", AddressScope::Unspecified),
+            ("::1", AddressScope::Loopback),
+            ("fe80::1", AddressScope::LinkLocal),
+            ("febf::1", AddressScope::LinkLocal),
+            ("fec0::1", AddressScope::SiteLocal),
+            ("ff02::1", AddressScope::Multicast),
+            ("2001:db8::1", AddressScope::Documentation),
+            ("3fff:0fff::1", AddressScope::Documentation),
+            ("3fff:1000::1", AddressScope::Global),
+            ("3ff0::1", AddressScope::Global),
+            ("::ffff:192.168.1.1", AddressScope::Ipv4Mapped),
+        ];
+
+        for (address, scope) in cases {
+            assert_eq!(
+                AddressScope::of(address.parse().unwrap()),
+                scope,
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_publishable() {
+        let global: Ipv6Addr = "2a00:1450:4001:812::200e".parse().unwrap();
+        let ula: Ipv6Addr = "fd12:3456:789a::1".parse().unwrap();
+        let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+
+        assert!(check_publishable(global, false).is_ok());
+        assert!(check_publishable(ula, false).is_err());
+        assert!(check_publishable(ula, true).is_ok());
+        assert!(check_publishable(link_local, true).is_err());
+
+        let err = check_publishable(link_local, false).unwrap_err();
+        assert!(err.to_string().contains("link-local"));
+    }
+
     #[test]
     fn test_extract_prefix() {
         let addr = "2001:db8:1234:5678::1".parse::<Ipv6Addr>().unwrap();
@@ -194,6 +516,156 @@ mod tests {
         assert!(parse_interface_id("gggg::1").is_err());
     }
 
+    #[test]
+    fn test_eui64_interface_id() {
+        assert_eq!(
+            eui64_interface_id("aa:bb:cc:dd:ee:ff").unwrap().to_string(),
+            "::a8bb:ccff:fedd:eeff"
+        );
+        assert_eq!(
+            eui64_interface_id("00-1A-2B-3C-4D-5E").unwrap().to_string(),
+            "::21a:2bff:fe3c:4d5e"
+        );
+        // A locally administered address has its U/L bit cleared.
+        assert_eq!(
+            eui64_interface_id("02:00:00:00:00:01").unwrap().to_string(),
+            "::ff:fe00:1"
+        );
+    }
+
+    #[test]
+    fn test_eui64_combined_with_prefix() {
+        let prefix = Ipv6Prefix::from_address("2001:db8:1234:5678::".parse().unwrap(), 64).unwrap();
+        let iid = eui64_interface_id("aa:bb:cc:dd:ee:ff").unwrap().to_string();
+        assert_eq!(
+            prefix.combine_with_interface_id(&iid).unwrap().to_string(),
+            "2001:db8:1234:5678:a8bb:ccff:fedd:eeff"
+        );
+    }
+
+    #[test]
+    fn test_eui64_interface_id_invalid() {
+        assert!(eui64_interface_id("aa:bb:cc:dd:ee").is_err());
+        assert!(eui64_interface_id("aa:bb:cc:dd:ee:ff:00:11").is_err());
+        assert!(eui64_interface_id("aa:bb:cc:dd:ee:gg").is_err());
+        assert!(eui64_interface_id("aabb.ccdd.eeff").is_err());
+        assert!(eui64_interface_id("a:bb:cc:dd:ee:fff").is_err());
+    }
+
+    #[test]
+    fn test_sha1_transform() {
+        // A single padded block for "abc" yields the standard SHA-1 digest.
+        let mut block = [0u8; 64];
+        block[..3].copy_from_slice(b"abc");
+        block[3] = 0x80;
+        block[63] = 24;
+
+        let mut digest = SHA1_IV;
+        sha1_transform(&mut digest, &block);
+        assert_eq!(
+            digest,
+            [0xa9993e36, 0x4706816a, 0xba3e2571, 0x7850c26c, 0x9cd0d89d]
+        );
+    }
+
+    /// Vectors taken from a Linux 6.18 kernel (x86_64) with
+    /// `addr_gen_mode = 2` and the given `stable_secret`, on tap devices,
+    /// which have no permanent hardware address.
+    #[test]
+    fn test_stable_privacy_kernel_vectors() {
+        let vectors = [
+            (
+                "2001:db8:1:2:3:4:5:6",
+                "00:00:00:00:00:00",
+                "fe80::",
+                "fe80::ff05:eb87:4e94:b3ad",
+            ),
+            (
+                "2001:db8:1:2:3:4:5:6",
+                "00:00:00:00:00:00",
+                "2001:db8:1234:5678::",
+                "2001:db8:1234:5678:5525:fb67:6f69:b9ed",
+            ),
+            (
+                "2001:db8:1:2:3:4:5:6",
+                "00:00:00:00:00:00",
+                "2a00:1450:4001:812::",
+                "2a00:1450:4001:812:8d94:b524:febc:d28c",
+            ),
+            (
+                "fd00:1234:5678:9abc:def0:1234:5678:9abc",
+                "00:00:00:00:00:00",
+                "fe80::",
+                "fe80::953b:9e4a:25b8:ac70",
+            ),
+            (
+                "fd00:1234:5678:9abc:def0:1234:5678:9abc",
+                "00:00:00:00:00:00",
+                "2001:db8:abcd:1::",
+                "2001:db8:abcd:1:897b:2827:a5c7:4dd",
+            ),
+        ];
+
+        for (secret, mac, prefix, expected) in vectors {
+            let prefix = Ipv6Prefix::from_address(prefix.parse().unwrap(), 64).unwrap();
+            let address = prefix
+                .stable_privacy_address(secret.parse().unwrap(), mac, 0)
+                .unwrap();
+            assert_eq!(address.to_string(), expected, "prefix {:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn test_stable_privacy_depends_on_hardware_address() {
+        let prefix = Ipv6Prefix::from_address("fe80::".parse().unwrap(), 64).unwrap();
+        let secret = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+
+        let address = prefix
+            .stable_privacy_address(secret, "52:54:00:12:34:56", 0)
+            .unwrap();
+        assert_eq!(address.to_string(), "fe80::6707:65c0:3d0d:c558");
+    }
+
+    #[test]
+    fn test_stable_privacy_depends_on_dad_counter() {
+        let prefix = Ipv6Prefix::from_address("2001:db8:1234:5678::".parse().unwrap(), 64).unwrap();
+        let secret = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+
+        let first = prefix
+            .stable_privacy_address(secret, "52:54:00:12:34:56", 0)
+            .unwrap();
+        let second = prefix
+            .stable_privacy_address(secret, "52:54:00:12:34:56", 1)
+            .unwrap();
+        assert_ne!(first, second);
+
+        // Beyond the kernel's retry limit, the counter is still tried once.
+        assert!(prefix
+            .stable_privacy_address(secret, "52:54:00:12:34:56", 5)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_stable_privacy_requires_64() {
+        let prefix = Ipv6Prefix::from_address("2001:db8:1234:5600::".parse().unwrap(), 56).unwrap();
+        assert!(prefix
+            .stable_privacy_address("::1".parse().unwrap(), "52:54:00:12:34:56", 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_reserved_interface_ids() {
+        let reserved = |s: &str| is_reserved_interface_id(s.parse().unwrap());
+
+        assert!(reserved("2001:db8::"));
+        assert!(reserved("2001:db8::200:5eff:fe00:5212"));
+        assert!(reserved("2001:db8::fdff:ffff:ffff:ff80"));
+        assert!(reserved("2001:db8::fdff:ffff:ffff:ffff"));
+        assert!(!reserved("2001:db8::1"));
+        assert!(!reserved("2001:db8::200:5eff:fd00:0"));
+        assert!(!reserved("2001:db8::fdff:ffff:ffff:ff7f"));
+    }
+
     #[test]
     fn test_combine_with_various_prefix_lengths() {
         let addr32 = "2001:db8::".parse::<Ipv6Addr>().unwrap();
@@ -0,0 +1,22 @@
+pub mod cleanup;
+pub mod cloudflare;
+pub mod config;
+pub mod debounce;
+pub mod dns;
+pub mod dyndns2;
+pub mod error;
+pub mod event;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod http_provider;
+pub mod ipv6;
+pub mod mqtt;
+pub mod output;
+pub mod powerdns;
+pub mod provider;
+pub mod record;
+pub mod state;
+pub mod status;
+pub mod uplink;
+pub mod webhook;
@@ -1,19 +1,14 @@
 use clap::Parser;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::signal;
 use tracing::{error, info};
 
-mod cloudflare;
-mod config;
-mod dyndns2;
-mod error;
-mod http;
-mod ipv6;
-mod state;
-
-use config::Config;
-use error::Result;
+use ddns6::config::Config;
+use ddns6::error::{self, Result};
+use ddns6::http;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -24,6 +19,10 @@ use error::Result;
 struct Args {
     #[arg(short, long, default_value = "config.toml")]
     config: String,
+
+    /// Directory whose *.toml files add [[hosts]] and [[host_groups]]
+    #[arg(long)]
+    config_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -47,14 +46,44 @@ async fn run() -> Result<()> {
     info!("Starting ddns6 daemon");
     info!("Loading configuration from: {}", args.config);
 
-    let config = Arc::new(Config::from_file(&args.config)?);
+    if let Some(config_dir) = &args.config_dir {
+        info!("Loading hosts from: {}", config_dir);
+    }
+
+    let config = Arc::new(Config::from_files(
+        Path::new(&args.config),
+        args.config_dir.as_deref().map(Path::new),
+    )?);
 
     info!(
         "Configuration loaded successfully with {} host(s)",
         config.hosts.len()
     );
     info!("Bind address: {}", config.server.bind_address);
-    info!("Cloudflare Zone ID: {}", config.cloudflare.zone_id);
+    if let Some(cloudflare) = &config.cloudflare {
+        if !cloudflare.zone_id.is_empty() {
+            info!("Cloudflare Zone ID: {}", cloudflare.zone_id);
+        }
+        if !cloudflare.zones.is_empty() {
+            info!("Cloudflare zones: {}", cloudflare.zones.join(", "));
+        }
+        if cloudflare.auto_zones {
+            info!("Using all Cloudflare zones accessible to the API token");
+        }
+    }
+    for provider in &config.providers {
+        info!("Provider: {}", provider.name);
+    }
+    if let Some(dns) = &config.dns {
+        info!(
+            "Serving zones {} over DNS on {}",
+            dns.zones.join(", "),
+            dns.bind_address
+        );
+    }
+    if let Some(mqtt) = &config.mqtt {
+        info!("MQTT broker: {}:{}", mqtt.host, mqtt.port);
+    }
 
     let app = http::create_server(config.clone()).await?;
 
@@ -71,10 +100,13 @@ async fn run() -> Result<()> {
         listener.local_addr().unwrap()
     );
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .map_err(|e| error::Ddns6Error::Io(std::io::Error::other(e)))?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .map_err(|e| error::Ddns6Error::Io(std::io::Error::other(e)))?;
 
     info!("ddns6 daemon shut down gracefully");
 
@@ -0,0 +1,392 @@
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, Publish, QoS, Transport};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::config::MqttConfig;
+use crate::dyndns2::{apply_update, AppState, UpdateQuery, UpdateSource};
+use crate::error::{Ddns6Error, Result};
+use crate::event::ChangeEvent;
+use crate::state::StateCache;
+use crate::status::{sort_records, RecordStatus};
+
+/// Delay before reconnecting after the broker connection failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Requests queued for the broker before publishing waits.
+const REQUEST_CAPACITY: usize = 64;
+
+/// Publishes the prefix and host state as retained MQTT messages.
+#[derive(Debug, Clone)]
+pub struct MqttPublisher {
+    client: AsyncClient,
+    topic_prefix: String,
+    command_topic: Option<String>,
+    state_cache: StateCache,
+}
+
+impl MqttPublisher {
+    /// Creates the publisher and the event loop that drives its connection;
+    /// nothing is sent until the event loop is passed to [`spawn`].
+    pub fn new(config: &MqttConfig, state_cache: StateCache) -> Result<(Self, EventLoop)> {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(config.keep_alive_secs));
+        if let Some(username) = &config.username {
+            options.set_credentials(
+                username,
+                config
+                    .password
+                    .as_ref()
+                    .map(|password| password.expose().to_string())
+                    .unwrap_or_default(),
+            );
+        }
+        if config.tls {
+            options.set_transport(match &config.ca_file {
+                Some(ca_file) => {
+                    let ca = std::fs::read(ca_file).map_err(|e| {
+                        Ddns6Error::Config(format!("Failed to read mqtt.ca_file: {}", e))
+                    })?;
+                    Transport::tls(ca, None, None)
+                }
+                None => Transport::tls_with_default_config(),
+            });
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+        let publisher = Self {
+            client,
+            topic_prefix: config.topic_prefix.clone(),
+            command_topic: config.command_topic.clone(),
+            state_cache,
+        };
+
+        Ok((publisher, eventloop))
+    }
+
+    /// Publishes the prefix and the changed hosts of `event` in the
+    /// background. A withdrawal leaves the last prefix of the uplink in place.
+    pub fn notify(&self, event: &ChangeEvent) {
+        let publisher = self.clone();
+        let uplink = event.uplink.clone();
+        let prefix = event.new_prefix.clone();
+        let hostnames: BTreeSet<String> = event
+            .hosts
+            .iter()
+            .map(|host| host.hostname.clone())
+            .collect();
+
+        tokio::spawn(async move {
+            if let Some(prefix) = &prefix {
+                publisher.publish_prefix(uplink.as_deref(), prefix).await;
+            }
+            for hostname in &hostnames {
+                publisher.publish_host(hostname).await;
+            }
+        });
+    }
+
+    /// Subscribes to the command topic and republishes the whole state, as
+    /// the broker forgets subscriptions and may have missed updates while
+    /// disconnected.
+    async fn on_connect(&self) {
+        if let Some(topic) = &self.command_topic {
+            if let Err(e) = self.client.subscribe(topic, QoS::AtLeastOnce).await {
+                error!("Failed to subscribe to MQTT topic {}: {}", topic, e);
+            }
+        }
+
+        for (uplink, prefix) in self.state_cache.list_prefixes().await {
+            self.publish_prefix(uplink.as_deref(), &prefix.to_string())
+                .await;
+        }
+
+        let hostnames: BTreeSet<String> = self
+            .state_cache
+            .list_all()
+            .await
+            .into_iter()
+            .map(|((hostname, _, _), _)| hostname)
+            .collect();
+        for hostname in &hostnames {
+            self.publish_host(hostname).await;
+        }
+    }
+
+    async fn publish_prefix(&self, uplink: Option<&str>, prefix: &str) {
+        self.publish(
+            prefix_topic(&self.topic_prefix, uplink),
+            prefix.as_bytes().to_vec(),
+        )
+        .await;
+    }
+
+    /// Publishes every record of `hostname` as a JSON array.
+    async fn publish_host(&self, hostname: &str) {
+        let mut records: Vec<RecordStatus> = self
+            .state_cache
+            .list_host(hostname)
+            .await
+            .into_iter()
+            .map(|(key, host_state)| RecordStatus::new(key, host_state))
+            .collect();
+        sort_records(&mut records);
+
+        match serde_json::to_vec(&records) {
+            Ok(payload) => {
+                self.publish(host_topic(&self.topic_prefix, hostname), payload)
+                    .await
+            }
+            Err(e) => error!("Failed to serialize MQTT state of {}: {}", hostname, e),
+        }
+    }
+
+    async fn publish(&self, topic: String, payload: Vec<u8>) {
+        debug!("Publishing to MQTT topic {}", topic);
+        if let Err(e) = self
+            .client
+            .publish(&topic, QoS::AtLeastOnce, true, payload)
+            .await
+        {
+            error!("Failed to publish to MQTT topic {}: {}", topic, e);
+        }
+    }
+}
+
+/// Topic holding the last prefix of `uplink`.
+pub fn prefix_topic(topic_prefix: &str, uplink: Option<&str>) -> String {
+    match uplink {
+        Some(uplink) => format!("{}/prefix/{}", topic_prefix, uplink),
+        None => format!("{}/prefix", topic_prefix),
+    }
+}
+
+/// Topic holding the published records of `hostname`.
+pub fn host_topic(topic_prefix: &str, hostname: &str) -> String {
+    format!("{}/hosts/{}", topic_prefix, hostname)
+}
+
+/// Drives the broker connection of `state.mqtt`, reconnecting after errors,
+/// and runs updates requested on the command topic.
+pub fn spawn(mut eventloop: EventLoop, state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker");
+                    if let Some(mqtt) = state.mqtt.clone() {
+                        tokio::spawn(async move { mqtt.on_connect().await });
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => handle_command(&state, publish),
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(
+                        "MQTT connection failed, reconnecting in {:?}: {}",
+                        RECONNECT_DELAY, e
+                    );
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+}
+
+fn handle_command(state: &AppState, publish: Publish) {
+    let is_command = state
+        .mqtt
+        .as_ref()
+        .and_then(|mqtt| mqtt.command_topic.as_deref())
+        .is_some_and(|topic| topic == publish.topic);
+    if !is_command {
+        return;
+    }
+
+    let params = match UpdateQuery::from_payload(&publish.payload) {
+        Ok(params) => params,
+        Err(e) => {
+            warn!("Ignoring MQTT command on {}: {}", publish.topic, e);
+            return;
+        }
+    };
+
+    info!("Received update command on MQTT topic {}", publish.topic);
+    let state = state.clone();
+    tokio::spawn(async move {
+        let response = apply_update(&state, params, UpdateSource::Mqtt).await;
+        info!("MQTT update command: {}", response);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::debounce::{Debouncer, FlapDetector};
+    use crate::event::HostChange;
+    use crate::history::PrefixHistory;
+    use crate::hooks::HookRunner;
+    use crate::ipv6::Ipv6Prefix;
+    use crate::provider::Providers;
+    use crate::record::RecordType;
+    use crate::uplink::UplinkTracker;
+    use crate::webhook::WebhookNotifier;
+    use rumqttc::Request;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [dns]
+            bind_address = "127.0.0.1:5353"
+            zones = ["example.com"]
+
+            [mqtt]
+            host = "localhost"
+            command_topic = "ddns6/update"
+
+            [[uplinks]]
+            name = "isp1"
+
+            [[hosts]]
+            hostname = "nas.example.com"
+            interface_id = "::10"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn app_state(config: Config) -> (AppState, EventLoop) {
+        let state_cache = StateCache::new();
+        let (mqtt, eventloop) =
+            MqttPublisher::new(config.mqtt.as_ref().unwrap(), state_cache.clone()).unwrap();
+        let state = AppState {
+            state_cache,
+            providers: Providers::default(),
+            uplinks: UplinkTracker::new(),
+            webhooks: WebhookNotifier::new(&config.webhooks),
+            hooks: HookRunner::new(&config.on_change),
+            mqtt: Some(mqtt),
+            debouncer: Debouncer::new(),
+            flaps: FlapDetector::new(),
+            history: PrefixHistory::new(&config.history),
+            config: Arc::new(config),
+        };
+        (state, eventloop)
+    }
+
+    /// Waits until `count` messages were queued for the broker and returns
+    /// their topics and payloads.
+    async fn published(eventloop: &mut EventLoop, count: usize) -> Vec<(String, String)> {
+        for _ in 0..100 {
+            eventloop.clean();
+            let messages: Vec<_> = eventloop
+                .pending
+                .iter()
+                .filter_map(|request| match request {
+                    Request::Publish(publish) => Some((
+                        publish.topic.clone(),
+                        String::from_utf8_lossy(&publish.payload).into_owned(),
+                    )),
+                    _ => None,
+                })
+                .collect();
+            if messages.len() >= count {
+                return messages;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} MQTT messages", count);
+    }
+
+    #[test]
+    fn test_topics() {
+        assert_eq!(prefix_topic("ddns6", None), "ddns6/prefix");
+        assert_eq!(
+            prefix_topic("home/ddns6", Some("isp1")),
+            "home/ddns6/prefix/isp1"
+        );
+        assert_eq!(
+            host_topic("ddns6", "nas.example.com"),
+            "ddns6/hosts/nas.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notify_payloads() {
+        let (state, mut eventloop) = app_state(config());
+        let address = "2001:db8:1::10".parse().unwrap();
+        state
+            .state_cache
+            .update("nas.example.com".to_string(), Some("isp1"), address)
+            .await;
+        let prefix = Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap();
+        let event = ChangeEvent::new(
+            Some("isp1"),
+            None,
+            prefix,
+            vec![HostChange::new("nas.example.com", None, address)],
+        );
+
+        state.mqtt.as_ref().unwrap().notify(&event);
+
+        let messages = published(&mut eventloop, 2).await;
+        assert_eq!(messages[0].0, "ddns6/prefix/isp1");
+        assert_eq!(messages[0].1, "2001:db8:1::/64");
+        assert_eq!(messages[1].0, "ddns6/hosts/nas.example.com");
+        let records: Value = serde_json::from_str(&messages[1].1).unwrap();
+        assert_eq!(records[0]["type"], "AAAA");
+        assert_eq!(records[0]["uplink"], "isp1");
+        assert_eq!(records[0]["address"], "2001:db8:1::10");
+
+        // A withdrawal leaves the prefix and publishes the remaining records.
+        state
+            .state_cache
+            .remove_uplink_record("nas.example.com", "isp1")
+            .await;
+        let event = ChangeEvent::withdrawal(
+            "isp1",
+            Some(prefix),
+            vec![HostChange::withdrawn("nas.example.com", Some(address))],
+        );
+        state.mqtt.as_ref().unwrap().notify(&event);
+
+        let messages = published(&mut eventloop, 3).await;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].0, "ddns6/hosts/nas.example.com");
+        assert_eq!(messages[2].1, "[]");
+    }
+
+    #[tokio::test]
+    async fn test_handle_command() {
+        let (state, mut eventloop) = app_state(config());
+
+        handle_command(
+            &state,
+            Publish::new("ddns6/other", QoS::AtLeastOnce, "2a02:8070:2::1"),
+        );
+        handle_command(
+            &state,
+            Publish::new(
+                "ddns6/update",
+                QoS::AtLeastOnce,
+                r#"{"prefix": "2a02:8070:1::1", "uplink": "isp1"}"#,
+            ),
+        );
+
+        let messages = published(&mut eventloop, 2).await;
+        assert_eq!(messages[0].0, "ddns6/prefix/isp1");
+        assert_eq!(messages[0].1, "2a02:8070:1::/64");
+        let published = state
+            .state_cache
+            .get("nas.example.com", Some("isp1"), RecordType::Aaaa)
+            .await
+            .unwrap();
+        assert_eq!(published.address.to_string(), "2a02:8070:1::10");
+        assert_eq!(state.state_cache.list_prefixes().await.len(), 1);
+    }
+}
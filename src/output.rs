@@ -0,0 +1,205 @@
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+use crate::config::{OutputConfig, OutputFormat};
+use crate::hooks::run_command;
+use crate::record::RecordType;
+use crate::state::StateCache;
+
+/// Time to wait after a change before writing, so that the records of one
+/// update end up in a single write and reload.
+const WRITE_DELAY: Duration = Duration::from_millis(200);
+
+/// Rewrites the output files whenever a published record changes.
+pub fn spawn(outputs: Vec<OutputConfig>, state_cache: StateCache) {
+    if outputs.is_empty() {
+        return;
+    }
+
+    let mut changes = state_cache.subscribe();
+    tokio::spawn(async move {
+        while changes.changed().await.is_ok() {
+            tokio::time::sleep(WRITE_DELAY).await;
+            changes.borrow_and_update();
+
+            let records = published_records(&state_cache).await;
+            for output in &outputs {
+                write_output(output, &records).await;
+            }
+        }
+    });
+}
+
+/// Published A and AAAA records as sorted, distinct hostname and address
+/// pairs.
+async fn published_records(state_cache: &StateCache) -> Vec<(String, IpAddr)> {
+    let mut records: Vec<(String, IpAddr)> = state_cache
+        .list_all()
+        .await
+        .into_iter()
+        .filter(|((_, _, record_type), _)| *record_type != RecordType::Ptr)
+        .map(|((hostname, _, _), state)| (hostname, state.address))
+        .collect();
+    records.sort();
+    records.dedup();
+    records
+}
+
+async fn write_output(output: &OutputConfig, records: &[(String, IpAddr)]) {
+    let content = render(output.format, output.ttl, records);
+    if tokio::fs::read_to_string(&output.path)
+        .await
+        .is_ok_and(|existing| existing == content)
+    {
+        debug!("Output {} is up to date", output.path);
+        return;
+    }
+
+    if let Err(e) = write_atomically(Path::new(&output.path), &content).await {
+        error!("Failed to write output {}: {}", output.path, e);
+        return;
+    }
+    info!("Wrote output {}", output.path);
+
+    if let Some(command) = &output.reload_command {
+        run_command(
+            command,
+            vec![("DDNS6_OUTPUT", output.path.clone())],
+            output.timeout_secs,
+        )
+        .await;
+    }
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it over
+/// `path`, so readers never see a partially written file.
+async fn write_atomically(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    tokio::fs::write(&tmp, content).await?;
+    if let Err(e) = tokio::fs::rename(&tmp, path).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Renders `records` in `format`. Wildcard hostnames only exist in zone
+/// files; the other formats cannot express them and skip them.
+fn render(format: OutputFormat, ttl: u32, records: &[(String, IpAddr)]) -> String {
+    let mut out = String::new();
+    let comment = match format {
+        OutputFormat::Zone => ';',
+        OutputFormat::Hosts | OutputFormat::Unbound => '#',
+    };
+    out.push_str(&format!("{} Generated by ddns6, do not edit.\n", comment));
+    if format == OutputFormat::Unbound {
+        out.push_str("server:\n");
+    }
+
+    for (hostname, address) in records {
+        let record_type = RecordType::for_address(*address).as_str();
+        match format {
+            OutputFormat::Zone => {
+                out.push_str(&format!(
+                    "{}.\t{}\tIN\t{}\t{}\n",
+                    hostname, ttl, record_type, address
+                ));
+            }
+            _ if hostname.starts_with("*.") => {}
+            OutputFormat::Hosts => {
+                out.push_str(&format!("{}\t{}\n", address, hostname));
+            }
+            OutputFormat::Unbound => {
+                out.push_str(&format!(
+                    "\tlocal-data: \"{}. {} IN {} {}\"\n",
+                    hostname, ttl, record_type, address
+                ));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<(String, IpAddr)> {
+        vec![
+            (
+                "*.lab.example.com".to_string(),
+                "2001:db8::3".parse().unwrap(),
+            ),
+            (
+                "nas.example.com".to_string(),
+                "2001:db8::2".parse().unwrap(),
+            ),
+            (
+                "printer.example.com".to_string(),
+                "192.0.2.7".parse().unwrap(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_render_zone() {
+        assert_eq!(
+            render(OutputFormat::Zone, 300, &records()),
+            "; Generated by ddns6, do not edit.\n\
+             *.lab.example.com.\t300\tIN\tAAAA\t2001:db8::3\n\
+             nas.example.com.\t300\tIN\tAAAA\t2001:db8::2\n\
+             printer.example.com.\t300\tIN\tA\t192.0.2.7\n"
+        );
+    }
+
+    #[test]
+    fn test_render_hosts() {
+        assert_eq!(
+            render(OutputFormat::Hosts, 300, &records()),
+            "# Generated by ddns6, do not edit.\n\
+             2001:db8::2\tnas.example.com\n\
+             192.0.2.7\tprinter.example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_render_unbound() {
+        assert_eq!(
+            render(OutputFormat::Unbound, 60, &records()),
+            "# Generated by ddns6, do not edit.\n\
+             server:\n\
+             \tlocal-data: \"nas.example.com. 60 IN AAAA 2001:db8::2\"\n\
+             \tlocal-data: \"printer.example.com. 60 IN A 192.0.2.7\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_output_reloads_only_on_change() {
+        let dir = std::env::temp_dir().join(format!("ddns6-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hosts");
+        let marker = dir.join("reloads");
+        let output = OutputConfig {
+            format: OutputFormat::Hosts,
+            path: path.to_string_lossy().into_owned(),
+            reload_command: Some(format!("echo \"$DDNS6_OUTPUT\" >> {}", marker.display())),
+            timeout_secs: 10,
+            ttl: 300,
+        };
+
+        write_output(&output, &records()).await;
+        write_output(&output, &records()).await;
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("2001:db8::2\tnas.example.com\n"));
+        assert!(!dir.join("hosts.tmp").exists());
+        let reloads = std::fs::read_to_string(&marker).unwrap();
+        assert_eq!(reloads, format!("{}\n", path.display()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
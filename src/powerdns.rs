@@ -0,0 +1,531 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv6Addr};
+use tracing::{error, info, warn};
+
+use crate::config::{longest_zone, HostMapping, PowerDnsConfig, Secret};
+use crate::error::{Ddns6Error, Result};
+use crate::ipv6::reverse_name;
+use crate::provider::http_client;
+use crate::record::RecordType;
+
+/// Comment on the record sets written by ddns6. Record sets without it are
+/// neither replaced nor deleted unless `force` is set.
+const OWNERSHIP_MARKER: &str = "managed-by=ddns6";
+
+/// Client for the HTTP API of the PowerDNS Authoritative Server. Records are
+/// written as whole record sets, so a host with several uplinks gets all of
+/// its addresses in one request.
+#[derive(Debug, Clone)]
+pub struct PowerDnsClient {
+    client: Client,
+    api_url: String,
+    api_key: Secret,
+    server_id: String,
+    zones: Vec<String>,
+    ttl: u32,
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct PatchZoneRequest {
+    rrsets: Vec<RecordSet>,
+}
+
+#[derive(Debug, Serialize)]
+struct RecordSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    changetype: &'static str,
+    records: Vec<Record>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<Comment>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    content: String,
+    disabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Comment {
+    content: String,
+    #[serde(default)]
+    account: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Zone {
+    #[serde(default)]
+    rrsets: Vec<ExistingRecordSet>,
+}
+
+/// A record set written by ddns6, as listed for cleanup.
+#[derive(Debug, Clone)]
+pub struct ManagedRecordSet {
+    pub zone: String,
+    /// Name without the trailing dot.
+    pub name: String,
+    pub contents: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExistingRecordSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(default)]
+    records: Vec<Record>,
+    #[serde(default)]
+    comments: Vec<Comment>,
+}
+
+impl ExistingRecordSet {
+    /// Whether ddns6 wrote the record set.
+    fn is_owned(&self) -> bool {
+        self.comments.iter().any(|comment| {
+            comment
+                .content
+                .split_whitespace()
+                .any(|word| word == OWNERSHIP_MARKER)
+        })
+    }
+}
+
+impl PowerDnsClient {
+    pub fn new(config: &PowerDnsConfig) -> Self {
+        Self {
+            client: http_client(),
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+            server_id: config.server_id.clone(),
+            zones: config.zones.clone(),
+            ttl: config.ttl,
+            force: config.force,
+        }
+    }
+
+    /// Replaces the A or AAAA records of `host` with `addresses`, deleting
+    /// the record set when `addresses` is empty. The addresses of other
+    /// uplinks are only known once they were published since startup, so
+    /// after a restart the first update drops them until they report again.
+    pub async fn replace_record_set(
+        &self,
+        host: &HostMapping,
+        record_type: RecordType,
+        addresses: &[IpAddr],
+    ) -> Result<()> {
+        let zone = self.zone_for(&host.hostname)?;
+        info!(
+            "Setting {} records of {} in PowerDNS zone {} to [{}]",
+            record_type,
+            host.hostname,
+            zone,
+            addresses
+                .iter()
+                .map(IpAddr::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let contents: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+        self.patch(
+            zone,
+            self.record_set(&host.hostname, record_type, &contents, host.ttl),
+        )
+        .await
+    }
+
+    /// Points the PTR record of `address` at `host` and deletes the one of
+    /// its `previous` address. Returns `false` without touching PowerDNS if
+    /// no configured `ip6.arpa` zone contains the PTR record.
+    pub async fn replace_ptr_record(
+        &self,
+        host: &HostMapping,
+        address: Ipv6Addr,
+        previous: Option<Ipv6Addr>,
+    ) -> Result<bool> {
+        let name = reverse_name(address);
+        let Ok(zone) = self.zone_for(&name) else {
+            return Ok(false);
+        };
+        info!(
+            "Pointing PTR record of {} at {} in PowerDNS zone {}",
+            address, host.hostname, zone
+        );
+        let contents = [format!("{}.", host.hostname)];
+        self.patch(
+            zone,
+            self.record_set(&name, RecordType::Ptr, &contents, host.ttl),
+        )
+        .await?;
+
+        if let Some(previous) = previous.filter(|previous| *previous != address) {
+            self.delete_ptr_record(previous).await?;
+        }
+        Ok(true)
+    }
+
+    /// Deletes the PTR record of `address`, if a configured `ip6.arpa` zone
+    /// contains it.
+    pub async fn delete_ptr_record(&self, address: Ipv6Addr) -> Result<()> {
+        let name = reverse_name(address);
+        let Ok(zone) = self.zone_for(&name) else {
+            return Ok(());
+        };
+        info!(
+            "Deleting PTR record of {} in PowerDNS zone {}",
+            address, zone
+        );
+        self.patch(zone, self.record_set(&name, RecordType::Ptr, &[], None))
+            .await
+    }
+
+    /// Lists the record sets of `record_type` that carry the ownership
+    /// marker, across all zones.
+    pub async fn list_managed_record_sets(
+        &self,
+        record_type: RecordType,
+    ) -> Result<Vec<ManagedRecordSet>> {
+        let mut record_sets = Vec::new();
+        for zone in &self.zones {
+            record_sets.extend(
+                self.fetch_zone(zone, &[])
+                    .await?
+                    .into_iter()
+                    .filter(|existing| {
+                        existing.record_type == record_type.as_str()
+                            && !existing.records.is_empty()
+                            && existing.is_owned()
+                    })
+                    .map(|existing| ManagedRecordSet {
+                        zone: zone.clone(),
+                        name: existing.name.trim_end_matches('.').to_string(),
+                        contents: existing
+                            .records
+                            .into_iter()
+                            .map(|record| record.content)
+                            .collect(),
+                    }),
+            );
+        }
+        Ok(record_sets)
+    }
+
+    /// Deletes the record set `name` of `record_type` from `zone`.
+    pub async fn delete_record_set(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<()> {
+        self.patch(zone, self.record_set(name, record_type, &[], None))
+            .await
+    }
+
+    /// Writes `record_set` to `zone` after checking that ddns6 owns the
+    /// record set it replaces.
+    async fn patch(&self, zone: &str, record_set: RecordSet) -> Result<()> {
+        let existing = self.fetch_record_sets(zone, &record_set).await?;
+        self.check_ownership(&existing, &record_set, zone)?;
+
+        let url = format!(
+            "{}/api/v1/servers/{}/zones/{}.",
+            self.api_url, self.server_id, zone
+        );
+        let response = self
+            .client
+            .patch(&url)
+            .header("X-API-Key", self.api_key.expose())
+            .json(&PatchZoneRequest {
+                rrsets: vec![record_set],
+            })
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            error!("PowerDNS API error (status {}): {}", status, body);
+            return Err(Ddns6Error::PowerDnsApi(format!(
+                "Failed to update zone {}: {} - {}",
+                zone, status, body
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads the record sets named like `record_set` from `zone`. Servers
+    /// before PowerDNS 4.8 ignore the filter and return the whole zone.
+    async fn fetch_record_sets(
+        &self,
+        zone: &str,
+        record_set: &RecordSet,
+    ) -> Result<Vec<ExistingRecordSet>> {
+        self.fetch_zone(
+            zone,
+            &[
+                ("rrset_name", record_set.name.as_str()),
+                ("rrset_type", record_set.record_type.as_str()),
+            ],
+        )
+        .await
+    }
+
+    /// Reads the record sets of `zone`, filtered by `query`.
+    async fn fetch_zone(
+        &self,
+        zone: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<ExistingRecordSet>> {
+        let url = format!(
+            "{}/api/v1/servers/{}/zones/{}.",
+            self.api_url, self.server_id, zone
+        );
+        let response = self
+            .client
+            .get(&url)
+            .query(query)
+            .header("X-API-Key", self.api_key.expose())
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            error!("PowerDNS API error (status {}): {}", status, body);
+            return Err(Ddns6Error::PowerDnsApi(format!(
+                "Failed to read zone {}: {} - {}",
+                zone, status, body
+            )));
+        }
+
+        let zone: Zone = serde_json::from_str(&body)
+            .map_err(|e| Ddns6Error::PowerDnsApi(format!("Failed to parse zone: {}", e)))?;
+        Ok(zone.rrsets)
+    }
+
+    /// Refuses to replace or delete a record set with records that ddns6 did
+    /// not write, unless `force` is set.
+    fn check_ownership(
+        &self,
+        existing: &[ExistingRecordSet],
+        record_set: &RecordSet,
+        zone: &str,
+    ) -> Result<()> {
+        let foreign = existing.iter().any(|existing| {
+            existing.name.eq_ignore_ascii_case(&record_set.name)
+                && existing.record_type == record_set.record_type
+                && !existing.records.is_empty()
+                && !existing.is_owned()
+        });
+        if !foreign {
+            return Ok(());
+        }
+        if self.force {
+            warn!(
+                "Taking over {} records of {} in PowerDNS zone {}",
+                record_set.record_type, record_set.name, zone
+            );
+            return Ok(());
+        }
+        Err(Ddns6Error::RecordNotOwned(format!(
+            "{} {} in PowerDNS zone {}",
+            record_set.name, record_set.record_type, zone
+        )))
+    }
+
+    /// The record set `name` of `record_type` with `contents`; a deletion
+    /// when `contents` is empty.
+    fn record_set(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        contents: &[String],
+        ttl: Option<u32>,
+    ) -> RecordSet {
+        let name = format!("{}.", name);
+        if contents.is_empty() {
+            return RecordSet {
+                name,
+                record_type: record_type.to_string(),
+                ttl: None,
+                changetype: "DELETE",
+                records: Vec::new(),
+                comments: Vec::new(),
+            };
+        }
+
+        RecordSet {
+            name,
+            record_type: record_type.to_string(),
+            ttl: Some(ttl.unwrap_or(self.ttl)),
+            changetype: "REPLACE",
+            records: contents
+                .iter()
+                .map(|content| Record {
+                    content: content.clone(),
+                    disabled: false,
+                })
+                .collect(),
+            comments: vec![Comment {
+                content: OWNERSHIP_MARKER.to_string(),
+                account: "ddns6".to_string(),
+            }],
+        }
+    }
+
+    /// The longest of the configured zones containing `hostname`, which may
+    /// also be the `ip6.arpa` name of a PTR record.
+    fn zone_for(&self, hostname: &str) -> Result<&str> {
+        longest_zone(&self.zones, hostname, String::as_str)
+            .map(String::as_str)
+            .ok_or_else(|| Ddns6Error::ZoneNotFound(hostname.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_client() -> PowerDnsClient {
+        PowerDnsClient::new(&PowerDnsConfig {
+            api_url: "http://127.0.0.1:8081/".to_string(),
+            api_key: "key".into(),
+            server_id: "localhost".to_string(),
+            zones: vec![
+                "example.com".to_string(),
+                "lan.example.com".to_string(),
+                "8.b.d.0.1.0.0.2.ip6.arpa".to_string(),
+            ],
+            ttl: 300,
+            force: false,
+        })
+    }
+
+    fn contents(addresses: &[&str]) -> Vec<String> {
+        addresses
+            .iter()
+            .map(|address| address.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_zone_for() {
+        let client = test_client();
+        assert_eq!(client.zone_for("nas.example.com").unwrap(), "example.com");
+        assert_eq!(
+            client.zone_for("nas.lan.example.com").unwrap(),
+            "lan.example.com"
+        );
+        assert!(client.zone_for("nas.example.net").is_err());
+
+        let ptr = reverse_name("2001:db8:1::10".parse().unwrap());
+        assert_eq!(client.zone_for(&ptr).unwrap(), "8.b.d.0.1.0.0.2.ip6.arpa");
+        assert!(client
+            .zone_for(&reverse_name("2a02:8070::10".parse().unwrap()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_record_set() {
+        let client = test_client();
+        let addresses = contents(&["2001:db8:1::10", "2001:db8:2::10"]);
+
+        let replace = client.record_set("nas.example.com", RecordType::Aaaa, &addresses, None);
+        assert_eq!(
+            serde_json::to_value(&replace).unwrap(),
+            serde_json::json!({
+                "name": "nas.example.com.",
+                "type": "AAAA",
+                "ttl": 300,
+                "changetype": "REPLACE",
+                "records": [
+                    {"content": "2001:db8:1::10", "disabled": false},
+                    {"content": "2001:db8:2::10", "disabled": false},
+                ],
+                "comments": [{"content": "managed-by=ddns6", "account": "ddns6"}],
+            })
+        );
+
+        let replace = client.record_set("nas.example.com", RecordType::Aaaa, &addresses, Some(60));
+        assert_eq!(replace.ttl, Some(60));
+
+        let delete = client.record_set("nas.example.com", RecordType::A, &[], None);
+        assert_eq!(
+            serde_json::to_value(&delete).unwrap(),
+            serde_json::json!({
+                "name": "nas.example.com.",
+                "type": "A",
+                "changetype": "DELETE",
+                "records": [],
+            })
+        );
+
+        let ptr = client.record_set(
+            "0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.1.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa",
+            RecordType::Ptr,
+            &contents(&["nas.example.com."]),
+            None,
+        );
+        assert_eq!(ptr.record_type, "PTR");
+        assert_eq!(ptr.records[0].content, "nas.example.com.");
+    }
+
+    #[test]
+    fn test_check_ownership() {
+        let client = test_client();
+        let record_set = client.record_set(
+            "nas.example.com",
+            RecordType::Aaaa,
+            &contents(&["2001:db8:1::10"]),
+            None,
+        );
+        let zone = |rrsets: serde_json::Value| -> Vec<ExistingRecordSet> {
+            serde_json::from_value::<Zone>(serde_json::json!({ "rrsets": rrsets }))
+                .unwrap()
+                .rrsets
+        };
+        let records = serde_json::json!([{"content": "2001:db8::99", "disabled": false}]);
+
+        let owned = zone(serde_json::json!([{
+            "name": "nas.example.com.",
+            "type": "AAAA",
+            "records": records,
+            "comments": [{"content": "managed-by=ddns6", "account": "ddns6", "modified_at": 0}],
+        }]));
+        assert!(client
+            .check_ownership(&owned, &record_set, "example.com")
+            .is_ok());
+
+        let other_names = zone(serde_json::json!([
+            {"name": "tv.example.com.", "type": "AAAA", "records": records},
+            {"name": "nas.example.com.", "type": "A", "records": records},
+        ]));
+        assert!(client
+            .check_ownership(&other_names, &record_set, "example.com")
+            .is_ok());
+
+        let foreign = zone(serde_json::json!([{
+            "name": "NAS.example.com.",
+            "type": "AAAA",
+            "records": records,
+            "comments": [{"content": "added by hand"}],
+        }]));
+        assert!(matches!(
+            client.check_ownership(&foreign, &record_set, "example.com"),
+            Err(Ddns6Error::RecordNotOwned(_))
+        ));
+
+        let forced = PowerDnsClient {
+            force: true,
+            ..client
+        };
+        assert!(forced
+            .check_ownership(&foreign, &record_set, "example.com")
+            .is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use reqwest::Client;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Arc;
+
+use crate::cloudflare::{CloudflareClient, DnsRecord};
+use crate::config::{Config, HostMapping, ProviderKind, CLOUDFLARE_PROVIDER};
+use crate::error::{Ddns6Error, Result};
+use crate::http_provider::HttpProvider;
+use crate::powerdns::PowerDnsClient;
+use crate::record::RecordType;
+
+/// A DNS provider the records of hosts are published to.
+#[derive(Debug, Clone)]
+pub enum Provider {
+    Cloudflare(Arc<CloudflareClient>),
+    PowerDns(PowerDnsClient),
+    Http(HttpProvider),
+}
+
+/// A record created by ddns6 at a provider, as listed for cleanup.
+#[derive(Debug)]
+pub struct ManagedRecord {
+    /// Name without the trailing dot.
+    pub name: String,
+    /// Contents of the record set, e.g. the hostname a PTR record points to.
+    pub contents: Vec<String>,
+    /// Uplink the record was published for, if the provider marks it.
+    pub uplink: Option<String>,
+    origin: RecordOrigin,
+}
+
+/// What a provider needs to delete a listed record.
+#[derive(Debug)]
+enum RecordOrigin {
+    Cloudflare(DnsRecord),
+    PowerDns { zone: String },
+}
+
+/// HTTP client used for every provider API.
+pub fn http_client() -> Client {
+    Client::builder()
+        .user_agent(concat!("ddns6/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+}
+
+impl Provider {
+    /// Publishes `address` for `host`. `addresses` holds every address of
+    /// the same type the host has across uplinks, `address` included, for
+    /// providers that replace whole record sets.
+    pub async fn update_address(
+        &self,
+        host: &HostMapping,
+        address: IpAddr,
+        uplink: Option<&str>,
+        addresses: &[IpAddr],
+    ) -> Result<()> {
+        match self {
+            Provider::Cloudflare(client) => {
+                client.update_address_record(host, address, uplink).await
+            }
+            Provider::PowerDns(client) => {
+                client
+                    .replace_record_set(host, RecordType::for_address(address), addresses)
+                    .await
+            }
+            Provider::Http(client) => client.update_record(host, address, addresses).await,
+        }
+    }
+
+    /// Withdraws the AAAA record of `host` on `uplink`, keeping the
+    /// `remaining` addresses of its other uplinks. Returns whether the
+    /// provider knows that a record was deleted.
+    pub async fn withdraw_address(
+        &self,
+        host: &HostMapping,
+        uplink: &str,
+        remaining: &[IpAddr],
+    ) -> Result<bool> {
+        match self {
+            Provider::Cloudflare(client) => client.withdraw_address_record(host, uplink).await,
+            Provider::PowerDns(client) => {
+                client
+                    .replace_record_set(host, RecordType::Aaaa, remaining)
+                    .await?;
+                Ok(false)
+            }
+            // Other uplinks keep the record alive, so it is updated instead.
+            Provider::Http(client) => match remaining.first() {
+                Some(address) => {
+                    client.update_record(host, *address, remaining).await?;
+                    Ok(false)
+                }
+                None => client.delete_record(host, RecordType::Aaaa).await,
+            },
+        }
+    }
+
+    /// Whether the provider can list the records ddns6 created, so that
+    /// cleanup can delete stale ones. HTTP providers only write.
+    pub fn supports_cleanup(&self) -> bool {
+        match self {
+            Provider::Cloudflare(client) => client.marks_records(),
+            Provider::PowerDns(_) => true,
+            Provider::Http(_) => false,
+        }
+    }
+
+    /// Lists the records of `record_type` created by ddns6.
+    pub async fn list_managed_records(
+        &self,
+        record_type: RecordType,
+    ) -> Result<Vec<ManagedRecord>> {
+        match self {
+            Provider::Cloudflare(client) if client.marks_records() => Ok(client
+                .list_managed_records(record_type.as_str())
+                .await?
+                .into_iter()
+                .map(|record| ManagedRecord {
+                    name: record.name.clone(),
+                    contents: vec![record.content.clone()],
+                    uplink: client.uplink_of(&record).map(str::to_string),
+                    origin: RecordOrigin::Cloudflare(record),
+                })
+                .collect()),
+            Provider::PowerDns(client) => Ok(client
+                .list_managed_record_sets(record_type)
+                .await?
+                .into_iter()
+                .map(|record_set| ManagedRecord {
+                    name: record_set.name,
+                    contents: record_set.contents,
+                    uplink: None,
+                    origin: RecordOrigin::PowerDns {
+                        zone: record_set.zone,
+                    },
+                })
+                .collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Deletes a record listed by `list_managed_records`.
+    pub async fn delete_managed_record(
+        &self,
+        record_type: RecordType,
+        record: &ManagedRecord,
+    ) -> Result<()> {
+        match (self, &record.origin) {
+            (Provider::Cloudflare(client), RecordOrigin::Cloudflare(record)) => {
+                client.delete_record(record).await
+            }
+            (Provider::PowerDns(client), RecordOrigin::PowerDns { zone }) => {
+                client
+                    .delete_record_set(zone, &record.name, record_type)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Points the PTR record of `address` at `host`, replacing the PTR
+    /// record of the `previous` address the provider pointed at it. Returns
+    /// `false` if the provider has no `ip6.arpa` zone for `address`.
+    pub async fn update_ptr(
+        &self,
+        host: &HostMapping,
+        address: Ipv6Addr,
+        previous: Option<Ipv6Addr>,
+        uplink: Option<&str>,
+    ) -> Result<bool> {
+        match self {
+            // Cloudflare finds the previous PTR records by their comment.
+            Provider::Cloudflare(client) => {
+                match client.update_ptr_record(host, address, uplink).await {
+                    Ok(()) => Ok(true),
+                    Err(Ddns6Error::ZoneNotFound(_)) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            }
+            Provider::PowerDns(client) => client.replace_ptr_record(host, address, previous).await,
+            Provider::Http(_) => Ok(false),
+        }
+    }
+
+    /// Deletes the PTR record of `address`, the address of `host` on
+    /// `uplink`.
+    pub async fn withdraw_ptr(
+        &self,
+        host: &HostMapping,
+        address: Ipv6Addr,
+        uplink: &str,
+    ) -> Result<()> {
+        match self {
+            Provider::Cloudflare(client) => client
+                .delete_ptr_records(host, None, Some(uplink))
+                .await
+                .map(|_| ()),
+            Provider::PowerDns(client) => client.delete_ptr_record(address).await,
+            Provider::Http(_) => Ok(()),
+        }
+    }
+}
+
+/// The configured providers, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Providers {
+    providers: Arc<Vec<(String, Provider)>>,
+}
+
+impl Providers {
+    pub fn new(config: &Config, cloudflare: Option<Arc<CloudflareClient>>) -> Self {
+        let mut providers = Vec::new();
+        if let Some(client) = cloudflare {
+            providers.push((
+                CLOUDFLARE_PROVIDER.to_string(),
+                Provider::Cloudflare(client),
+            ));
+        }
+        for provider in &config.providers {
+            let client = match &provider.kind {
+                ProviderKind::PowerDns(powerdns) => {
+                    Provider::PowerDns(PowerDnsClient::new(powerdns))
+                }
+                ProviderKind::Http(http) => Provider::Http(HttpProvider::new(http)),
+            };
+            providers.push((provider.name.clone(), client));
+        }
+
+        Self {
+            providers: Arc::new(providers),
+        }
+    }
+
+    /// Every provider with its name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Provider)> {
+        self.providers
+            .iter()
+            .map(|(name, provider)| (name.as_str(), provider))
+    }
+
+    /// Providers the records of `host` are published to.
+    pub fn for_host<'a>(
+        &'a self,
+        host: &'a HostMapping,
+    ) -> impl Iterator<Item = (&'a str, &'a Provider)> {
+        self.providers
+            .iter()
+            .filter(|(name, _)| host.uses_provider(name))
+            .map(|(name, provider)| (name.as_str(), provider))
+    }
+}
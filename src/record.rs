@@ -0,0 +1,52 @@
+use std::fmt;
+use std::net::IpAddr;
+
+/// DNS record types managed by ddns6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    /// Reverse record in an `ip6.arpa` zone, pointing back to a hostname.
+    Ptr,
+}
+
+impl RecordType {
+    pub fn for_address(address: IpAddr) -> Self {
+        match address {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::Aaaa,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Ptr => "PTR",
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_address() {
+        assert_eq!(
+            RecordType::for_address("192.0.2.1".parse().unwrap()),
+            RecordType::A
+        );
+        assert_eq!(
+            RecordType::for_address("2001:db8::1".parse().unwrap()),
+            RecordType::Aaaa
+        );
+        assert_eq!(RecordType::Aaaa.to_string(), "AAAA");
+    }
+}
@@ -1,63 +1,265 @@
-use std::collections::HashMap;
-use std::net::Ipv6Addr;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
+
+use crate::ipv6::Ipv6Prefix;
+use crate::record::RecordType;
 
 #[derive(Debug, Clone)]
 pub struct HostState {
-    pub ipv6_address: Ipv6Addr,
-    #[allow(dead_code)]
+    pub address: IpAddr,
     pub last_updated: std::time::SystemTime,
+    /// Outcome of the last attempt to publish the record, per provider.
+    pub providers: BTreeMap<String, ProviderResult>,
+    /// Outcome of the last attempt to point the PTR record of the address
+    /// back to the host, per provider. Empty for A records.
+    pub ptr: BTreeMap<String, ProviderResult>,
+}
+
+/// Outcome of publishing a record to one provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProviderResult {
+    /// Address sent to the provider.
+    pub address: IpAddr,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl ProviderResult {
+    pub fn success(address: IpAddr) -> Self {
+        Self {
+            address,
+            success: true,
+            error: None,
+        }
+    }
+
+    pub fn failure(address: IpAddr, error: impl ToString) -> Self {
+        Self {
+            address,
+            success: false,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// Whether the provider holds `address`.
+    pub fn holds(&self, address: IpAddr) -> bool {
+        self.success && self.address == address
+    }
 }
 
+/// Identifies a published record: hostname, uplink (for hosts published
+/// once per uplink) and record type.
+pub type RecordKey = (String, Option<String>, RecordType);
+
+/// Last published address per hostname, uplink and record type, and the
+/// last reported prefix per uplink.
 #[derive(Debug, Clone)]
 pub struct StateCache {
-    cache: Arc<RwLock<HashMap<String, HostState>>>,
+    cache: Arc<RwLock<HashMap<RecordKey, HostState>>>,
+    prefixes: Arc<RwLock<HashMap<Option<String>, Ipv6Prefix>>>,
+    /// Number of times a record was added, changed or removed.
+    changes: Arc<watch::Sender<u64>>,
 }
 
 impl StateCache {
     pub fn new() -> Self {
         Self {
             cache: Arc::new(RwLock::new(HashMap::new())),
+            prefixes: Arc::new(RwLock::new(HashMap::new())),
+            changes: Arc::new(watch::Sender::new(0)),
         }
     }
 
-    #[allow(dead_code)]
-    pub async fn get(&self, hostname: &str) -> Option<HostState> {
+    /// Records the prefix reported for `uplink` and returns the previous one.
+    pub async fn swap_prefix(
+        &self,
+        uplink: Option<&str>,
+        prefix: Ipv6Prefix,
+    ) -> Option<Ipv6Prefix> {
+        let mut prefixes = self.prefixes.write().await;
+        prefixes.insert(uplink.map(str::to_string), prefix)
+    }
+
+    /// Last prefix reported for `uplink`.
+    pub async fn prefix(&self, uplink: Option<&str>) -> Option<Ipv6Prefix> {
+        let prefixes = self.prefixes.read().await;
+        prefixes.get(&uplink.map(str::to_string)).copied()
+    }
+
+    pub async fn get(
+        &self,
+        hostname: &str,
+        uplink: Option<&str>,
+        record_type: RecordType,
+    ) -> Option<HostState> {
         let cache = self.cache.read().await;
-        cache.get(hostname).cloned()
+        cache
+            .get(&(
+                hostname.to_string(),
+                uplink.map(str::to_string),
+                record_type,
+            ))
+            .cloned()
     }
 
-    pub async fn has_changed(&self, hostname: &str, new_address: Ipv6Addr) -> bool {
+    pub async fn has_changed(
+        &self,
+        hostname: &str,
+        uplink: Option<&str>,
+        new_address: impl Into<IpAddr>,
+    ) -> bool {
+        let new_address = new_address.into();
+        let key = (
+            hostname.to_string(),
+            uplink.map(str::to_string),
+            RecordType::for_address(new_address),
+        );
         let cache = self.cache.read().await;
-        match cache.get(hostname) {
-            Some(state) => state.ipv6_address != new_address,
+        match cache.get(&key) {
+            Some(state) => state.address != new_address,
             None => true,
         }
     }
 
-    pub async fn update(&self, hostname: String, ipv6_address: Ipv6Addr) {
-        let mut cache = self.cache.write().await;
-        cache.insert(
+    pub async fn update(&self, hostname: String, uplink: Option<&str>, address: impl Into<IpAddr>) {
+        self.update_with_results(hostname, uplink, address, Vec::new())
+            .await;
+    }
+
+    /// Records the outcome of publishing `address` to each provider. The
+    /// address becomes the published one unless every provider failed.
+    pub async fn update_with_results(
+        &self,
+        hostname: String,
+        uplink: Option<&str>,
+        address: impl Into<IpAddr>,
+        results: Vec<(String, ProviderResult)>,
+    ) {
+        let address = address.into();
+        let published = results.is_empty() || results.iter().any(|(_, result)| result.success);
+        let key = (
             hostname,
-            HostState {
-                ipv6_address,
-                last_updated: std::time::SystemTime::now(),
-            },
+            uplink.map(str::to_string),
+            RecordType::for_address(address),
         );
+        let now = std::time::SystemTime::now();
+
+        let mut cache = self.cache.write().await;
+        let state = match cache.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(_) if !published => return,
+            Entry::Vacant(entry) => {
+                self.changes.send_modify(|changes| *changes += 1);
+                entry.insert(HostState {
+                    address,
+                    last_updated: now,
+                    providers: BTreeMap::new(),
+                    ptr: BTreeMap::new(),
+                })
+            }
+        };
+        if published {
+            if state.address != address {
+                self.changes.send_modify(|changes| *changes += 1);
+            }
+            state.address = address;
+            state.last_updated = now;
+        }
+        state.providers.extend(results);
+    }
+
+    /// Records the outcome of publishing the PTR record of the AAAA record
+    /// of `hostname` on `uplink` to each provider. Does nothing unless the
+    /// AAAA record is published.
+    pub async fn update_ptr_results(
+        &self,
+        hostname: &str,
+        uplink: Option<&str>,
+        results: Vec<(String, ProviderResult)>,
+    ) {
+        let key = (
+            hostname.to_string(),
+            uplink.map(str::to_string),
+            RecordType::Aaaa,
+        );
+        let mut cache = self.cache.write().await;
+        if let Some(state) = cache.get_mut(&key) {
+            state.ptr.extend(results);
+        }
     }
 
+    /// Number of changes to the published records so far, e.g. to derive a
+    /// zone serial.
+    pub fn changes(&self) -> u64 {
+        *self.changes.borrow()
+    }
+
+    /// Receiver that is notified whenever the published records change.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Removes the records matching `remove`, counting it as a change if
+    /// any was removed.
+    fn remove_where(
+        &self,
+        cache: &mut HashMap<RecordKey, HostState>,
+        remove: impl Fn(&RecordKey) -> bool,
+    ) {
+        let before = cache.len();
+        cache.retain(|key, _| !remove(key));
+        if cache.len() != before {
+            self.changes.send_modify(|changes| *changes += 1);
+        }
+    }
+
+    /// Forgets all records for `hostname`.
     #[allow(dead_code)]
     pub async fn remove(&self, hostname: &str) {
         let mut cache = self.cache.write().await;
-        cache.remove(hostname);
+        self.remove_where(&mut cache, |(name, _, _)| name == hostname);
     }
 
-    #[allow(dead_code)]
-    pub async fn list_all(&self) -> Vec<(String, HostState)> {
+    /// Forgets the records of one type for `hostname`, on all uplinks.
+    pub async fn remove_record(&self, hostname: &str, record_type: RecordType) {
+        let mut cache = self.cache.write().await;
+        self.remove_where(&mut cache, |(name, _, kind)| {
+            name == hostname && *kind == record_type
+        });
+    }
+
+    /// Forgets the record of `hostname` on `uplink`.
+    pub async fn remove_uplink_record(&self, hostname: &str, uplink: &str) {
+        let mut cache = self.cache.write().await;
+        self.remove_where(&mut cache, |(name, record_uplink, _)| {
+            name == hostname && record_uplink.as_deref() == Some(uplink)
+        });
+    }
+
+    pub async fn list_all(&self) -> Vec<(RecordKey, HostState)> {
         let cache = self.cache.read().await;
         cache.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
     }
+
+    /// Published records of `hostname`.
+    pub async fn list_host(&self, hostname: &str) -> Vec<(RecordKey, HostState)> {
+        let cache = self.cache.read().await;
+        cache
+            .iter()
+            .filter(|((name, _, _), _)| name == hostname)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Last reported prefix per uplink.
+    pub async fn list_prefixes(&self) -> Vec<(Option<String>, Ipv6Prefix)> {
+        let prefixes = self.prefixes.read().await;
+        prefixes.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
 }
 
 impl Default for StateCache {
@@ -69,6 +271,7 @@ impl Default for StateCache {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[tokio::test]
     async fn test_state_cache() {
@@ -77,22 +280,174 @@ mod tests {
         let addr1 = "2001:db8::1".parse::<Ipv6Addr>().unwrap();
         let addr2 = "2001:db8::2".parse::<Ipv6Addr>().unwrap();
 
-        assert!(cache.get(&hostname).await.is_none());
+        assert!(cache.get(&hostname, None, RecordType::Aaaa).await.is_none());
+
+        assert!(cache.has_changed(&hostname, None, addr1).await);
+
+        cache.update(hostname.clone(), None, addr1).await;
+
+        let state = cache.get(&hostname, None, RecordType::Aaaa).await.unwrap();
+        assert_eq!(state.address, addr1);
+
+        assert!(!cache.has_changed(&hostname, None, addr1).await);
+
+        assert!(cache.has_changed(&hostname, None, addr2).await);
+
+        cache.update(hostname.clone(), None, addr2).await;
+        let state = cache.get(&hostname, None, RecordType::Aaaa).await.unwrap();
+        assert_eq!(state.address, addr2);
+    }
+
+    #[tokio::test]
+    async fn test_record_types_are_independent() {
+        let cache = StateCache::new();
+        let hostname = "router.example.com".to_string();
+        let addr6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap();
+        let addr4 = "192.0.2.1".parse::<Ipv4Addr>().unwrap();
+
+        cache.update(hostname.clone(), None, addr6).await;
+        assert!(cache.has_changed(&hostname, None, addr4).await);
+
+        cache.update(hostname.clone(), None, addr4).await;
+        assert!(!cache.has_changed(&hostname, None, addr4).await);
+        assert!(!cache.has_changed(&hostname, None, addr6).await);
 
-        assert!(cache.has_changed(&hostname, addr1).await);
+        cache.remove_record(&hostname, RecordType::A).await;
+        assert!(cache.get(&hostname, None, RecordType::A).await.is_none());
+        assert!(cache.get(&hostname, None, RecordType::Aaaa).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_uplinks_are_independent() {
+        let cache = StateCache::new();
+        let hostname = "nas.example.com".to_string();
+        let isp1 = "2001:db8:1::10".parse::<Ipv6Addr>().unwrap();
+        let isp2 = "2001:db8:2::10".parse::<Ipv6Addr>().unwrap();
 
-        cache.update(hostname.clone(), addr1).await;
+        cache.update(hostname.clone(), Some("isp1"), isp1).await;
+        assert!(cache.has_changed(&hostname, Some("isp2"), isp2).await);
+        assert!(cache.has_changed(&hostname, None, isp1).await);
 
-        let state = cache.get(&hostname).await.unwrap();
-        assert_eq!(state.ipv6_address, addr1);
+        cache.update(hostname.clone(), Some("isp2"), isp2).await;
+        assert!(!cache.has_changed(&hostname, Some("isp1"), isp1).await);
+        assert!(!cache.has_changed(&hostname, Some("isp2"), isp2).await);
 
-        assert!(!cache.has_changed(&hostname, addr1).await);
+        cache.remove_uplink_record(&hostname, "isp1").await;
+        assert!(cache
+            .get(&hostname, Some("isp1"), RecordType::Aaaa)
+            .await
+            .is_none());
+        assert!(cache
+            .get(&hostname, Some("isp2"), RecordType::Aaaa)
+            .await
+            .is_some());
+    }
 
-        assert!(cache.has_changed(&hostname, addr2).await);
+    #[tokio::test]
+    async fn test_provider_results() {
+        let cache = StateCache::new();
+        let hostname = "nas.example.com".to_string();
+        let addr1 = "2001:db8:1::10".parse::<IpAddr>().unwrap();
+        let addr2 = "2001:db8:2::10".parse::<IpAddr>().unwrap();
+
+        // Nothing is published while every provider fails.
+        cache
+            .update_with_results(
+                hostname.clone(),
+                None,
+                addr1,
+                vec![("public".to_string(), ProviderResult::failure(addr1, "down"))],
+            )
+            .await;
+        assert!(cache.get(&hostname, None, RecordType::Aaaa).await.is_none());
+        assert_eq!(cache.changes(), 0);
+
+        cache
+            .update_with_results(
+                hostname.clone(),
+                None,
+                addr1,
+                vec![
+                    ("public".to_string(), ProviderResult::success(addr1)),
+                    (
+                        "private".to_string(),
+                        ProviderResult::failure(addr1, "down"),
+                    ),
+                ],
+            )
+            .await;
+        let state = cache.get(&hostname, None, RecordType::Aaaa).await.unwrap();
+        assert_eq!(state.address, addr1);
+        assert!(state.providers["public"].holds(addr1));
+        assert!(!state.providers["private"].holds(addr1));
+        assert_eq!(cache.changes(), 1);
+
+        // A failed change keeps the address, but remembers the failure.
+        cache
+            .update_with_results(
+                hostname.clone(),
+                None,
+                addr2,
+                vec![("public".to_string(), ProviderResult::failure(addr2, "down"))],
+            )
+            .await;
+        let state = cache.get(&hostname, None, RecordType::Aaaa).await.unwrap();
+        assert_eq!(state.address, addr1);
+        assert_eq!(state.providers["public"].error.as_deref(), Some("down"));
+        assert_eq!(state.providers.len(), 2);
+        assert_eq!(cache.changes(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ptr_results() {
+        let cache = StateCache::new();
+        let addr = "2001:db8:1::10".parse::<IpAddr>().unwrap();
+        let failure = || vec![("public".to_string(), ProviderResult::failure(addr, "down"))];
+
+        // Without a published AAAA record there is nothing to point back.
+        cache
+            .update_ptr_results("nas.example.com", Some("isp1"), failure())
+            .await;
+        assert!(cache.list_all().await.is_empty());
+
+        cache
+            .update("nas.example.com".to_string(), Some("isp1"), addr)
+            .await;
+        cache
+            .update_ptr_results("nas.example.com", Some("isp1"), failure())
+            .await;
+        let state = cache
+            .get("nas.example.com", Some("isp1"), RecordType::Aaaa)
+            .await
+            .unwrap();
+        assert!(!state.ptr["public"].holds(addr));
+
+        cache
+            .update_ptr_results(
+                "nas.example.com",
+                Some("isp1"),
+                vec![("public".to_string(), ProviderResult::success(addr))],
+            )
+            .await;
+        let state = cache
+            .get("nas.example.com", Some("isp1"), RecordType::Aaaa)
+            .await
+            .unwrap();
+        assert!(state.ptr["public"].holds(addr));
+        assert_eq!(cache.changes(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_swap_prefix() {
+        let cache = StateCache::new();
+        let prefix1 = Ipv6Prefix::from_cidr("2001:db8:1::/64").unwrap();
+        let prefix2 = Ipv6Prefix::from_cidr("2001:db8:2::/64").unwrap();
 
-        cache.update(hostname.clone(), addr2).await;
-        let state = cache.get(&hostname).await.unwrap();
-        assert_eq!(state.ipv6_address, addr2);
+        assert_eq!(cache.swap_prefix(None, prefix1).await, None);
+        assert_eq!(cache.swap_prefix(None, prefix2).await, Some(prefix1));
+        assert_eq!(cache.swap_prefix(Some("isp2"), prefix1).await, None);
+        assert_eq!(cache.swap_prefix(None, prefix2).await, Some(prefix2));
+        assert_eq!(cache.list_prefixes().await.len(), 2);
     }
 
     #[tokio::test]
@@ -101,11 +456,11 @@ mod tests {
         let hostname = "device1.example.com".to_string();
         let addr = "2001:db8::1".parse::<Ipv6Addr>().unwrap();
 
-        cache.update(hostname.clone(), addr).await;
-        assert!(cache.get(&hostname).await.is_some());
+        cache.update(hostname.clone(), None, addr).await;
+        assert!(cache.get(&hostname, None, RecordType::Aaaa).await.is_some());
 
         cache.remove(&hostname).await;
-        assert!(cache.get(&hostname).await.is_none());
+        assert!(cache.get(&hostname, None, RecordType::Aaaa).await.is_none());
     }
 
     #[tokio::test]
@@ -114,8 +469,12 @@ mod tests {
         let addr1 = "2001:db8::1".parse::<Ipv6Addr>().unwrap();
         let addr2 = "2001:db8::2".parse::<Ipv6Addr>().unwrap();
 
-        cache.update("device1.example.com".to_string(), addr1).await;
-        cache.update("device2.example.com".to_string(), addr2).await;
+        cache
+            .update("device1.example.com".to_string(), None, addr1)
+            .await;
+        cache
+            .update("device2.example.com".to_string(), None, addr2)
+            .await;
 
         let all = cache.list_all().await;
         assert_eq!(all.len(), 2);
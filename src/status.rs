@@ -0,0 +1,144 @@
+use axum::{extract::State as AxumState, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::dyndns2::AppState;
+use crate::hooks::HookRun;
+use crate::state::{HostState, ProviderResult, RecordKey};
+
+/// Response of `GET /api/v1/status`.
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub prefixes: Vec<PrefixStatus>,
+    pub uplinks: Vec<UplinkStatus>,
+    pub records: Vec<RecordStatus>,
+    pub hooks: Vec<HookStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrefixStatus {
+    pub uplink: Option<String>,
+    pub prefix: String,
+    /// Newer prefix held back until it settled.
+    pub pending_prefix: Option<String>,
+    /// Prefix changes within the last hour.
+    pub changes_last_hour: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UplinkStatus {
+    pub name: String,
+    pub active: bool,
+    /// Down, with records left to withdraw on the next expiry check.
+    pub pending_withdrawal: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordStatus {
+    pub hostname: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub uplink: Option<String>,
+    pub address: IpAddr,
+    /// Seconds since the Unix epoch.
+    pub last_updated: u64,
+    /// Outcome of the last attempt per provider.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub providers: BTreeMap<String, ProviderResult>,
+    /// Outcome of the last attempt to publish the PTR record per provider.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ptr: BTreeMap<String, ProviderResult>,
+}
+
+impl RecordStatus {
+    pub fn new((hostname, uplink, record_type): RecordKey, host_state: HostState) -> Self {
+        Self {
+            hostname,
+            record_type: record_type.to_string(),
+            uplink,
+            address: host_state.address,
+            last_updated: host_state
+                .last_updated
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            providers: host_state.providers,
+            ptr: host_state.ptr,
+        }
+    }
+}
+
+/// Orders records by hostname, record type and uplink.
+pub fn sort_records(records: &mut [RecordStatus]) {
+    records.sort_by(|a, b| {
+        (&a.hostname, &a.record_type, &a.uplink).cmp(&(&b.hostname, &b.record_type, &b.uplink))
+    });
+}
+
+#[derive(Debug, Serialize)]
+pub struct HookStatus {
+    pub command: String,
+    pub per_host: bool,
+    pub last_run: Option<HookRun>,
+}
+
+pub async fn handle_status(AxumState(state): AxumState<AppState>) -> Json<StatusReport> {
+    let now = Instant::now();
+    let mut prefixes = Vec::new();
+    for (uplink, prefix) in state.state_cache.list_prefixes().await {
+        prefixes.push(PrefixStatus {
+            pending_prefix: state
+                .debouncer
+                .pending(uplink.as_deref())
+                .await
+                .map(|pending| pending.to_string()),
+            changes_last_hour: state.flaps.changes(uplink.as_deref(), now).await,
+            uplink,
+            prefix: prefix.to_string(),
+        });
+    }
+    prefixes.sort_by(|a, b| a.uplink.cmp(&b.uplink));
+
+    let active = state.uplinks.active().await;
+    let pending_withdrawal = state.uplinks.pending_withdrawal().await;
+    let uplinks = state
+        .config
+        .uplinks
+        .iter()
+        .map(|uplink| UplinkStatus {
+            name: uplink.name.clone(),
+            active: active.contains(&uplink.name),
+            pending_withdrawal: pending_withdrawal.contains(&uplink.name),
+        })
+        .collect();
+
+    let mut records: Vec<RecordStatus> = state
+        .state_cache
+        .list_all()
+        .await
+        .into_iter()
+        .map(|(key, host_state)| RecordStatus::new(key, host_state))
+        .collect();
+    sort_records(&mut records);
+
+    let hooks = state
+        .config
+        .on_change
+        .iter()
+        .zip(state.hooks.last_runs().await)
+        .map(|(hook, last_run)| HookStatus {
+            command: hook.command.clone(),
+            per_host: hook.per_host,
+            last_run,
+        })
+        .collect();
+
+    Json(StatusReport {
+        prefixes,
+        uplinks,
+        records,
+        hooks,
+    })
+}
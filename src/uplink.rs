@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::dyndns2::{withdraw_uplink, AppState};
+use crate::event::HostChange;
+use crate::provider::Providers;
+use crate::record::RecordType;
+use crate::state::{ProviderResult, StateCache};
+
+/// Longest time between two checks for expired uplink prefixes.
+const MAX_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// State of an uplink that reported its prefix.
+#[derive(Debug, Clone, Copy)]
+enum UplinkState {
+    /// Up, last reported at the given time.
+    Up(Instant),
+    /// Down, but some of its records could not be withdrawn yet.
+    Withdrawing,
+}
+
+/// When each uplink last reported its prefix. Uplinks whose records were
+/// withdrawn are absent.
+#[derive(Debug, Clone, Default)]
+pub struct UplinkTracker {
+    uplinks: Arc<RwLock<HashMap<String, UplinkState>>>,
+}
+
+impl UplinkTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn seen(&self, uplink: &str) {
+        let mut uplinks = self.uplinks.write().await;
+        uplinks.insert(uplink.to_string(), UplinkState::Up(Instant::now()));
+    }
+
+    /// Marks `uplink` as down until [`Self::withdrawn`] confirms that its
+    /// records are gone.
+    pub async fn withdrawing(&self, uplink: &str) {
+        let mut uplinks = self.uplinks.write().await;
+        uplinks.insert(uplink.to_string(), UplinkState::Withdrawing);
+    }
+
+    /// Forgets `uplink` once all its records were withdrawn, unless it
+    /// came back up in the meantime.
+    pub async fn withdrawn(&self, uplink: &str) {
+        let mut uplinks = self.uplinks.write().await;
+        if let Some(UplinkState::Withdrawing) = uplinks.get(uplink) {
+            uplinks.remove(uplink);
+        }
+    }
+
+    pub async fn is_active(&self, uplink: &str) -> bool {
+        matches!(
+            self.uplinks.read().await.get(uplink),
+            Some(UplinkState::Up(_))
+        )
+    }
+
+    /// Names of the uplinks that are currently up.
+    pub async fn active(&self) -> Vec<String> {
+        self.names(|state| matches!(state, UplinkState::Up(_)))
+            .await
+    }
+
+    /// Names of the uplinks whose withdrawal has not succeeded yet.
+    pub async fn pending_withdrawal(&self) -> Vec<String> {
+        self.names(|state| matches!(state, UplinkState::Withdrawing))
+            .await
+    }
+
+    async fn names(&self, filter: impl Fn(&UplinkState) -> bool) -> Vec<String> {
+        self.uplinks
+            .read()
+            .await
+            .iter()
+            .filter(|(_, state)| filter(state))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Returns the uplinks whose prefix outlived `prefix_lifetime_secs`,
+    /// and those whose withdrawal is to be retried.
+    pub async fn expired(&self, config: &Config, now: Instant) -> Vec<String> {
+        let uplinks = self.uplinks.read().await;
+        config
+            .uplinks
+            .iter()
+            .filter(|uplink| match uplinks.get(&uplink.name) {
+                Some(UplinkState::Up(seen)) => uplink
+                    .prefix_lifetime_secs
+                    .is_some_and(|secs| now.duration_since(*seen) >= Duration::from_secs(secs)),
+                Some(UplinkState::Withdrawing) => true,
+                None => false,
+            })
+            .map(|uplink| uplink.name.clone())
+            .collect()
+    }
+}
+
+/// Deletes the AAAA and PTR records every host has on `uplink`. Returns the
+/// withdrawn records and the hostnames that failed.
+pub async fn withdraw(
+    config: &Config,
+    providers: &Providers,
+    state_cache: &StateCache,
+    uplink: &str,
+) -> (Vec<HostChange>, Vec<String>) {
+    let mut withdrawn = Vec::new();
+    let mut failed = Vec::new();
+
+    for host in &config.hosts {
+        let published = state_cache
+            .get(&host.hostname, Some(uplink), RecordType::Aaaa)
+            .await;
+        let remaining: Vec<IpAddr> = state_cache
+            .list_host(&host.hostname)
+            .await
+            .into_iter()
+            .filter(|((_, record_uplink, record_type), _)| {
+                *record_type == RecordType::Aaaa && record_uplink.as_deref() != Some(uplink)
+            })
+            .map(|(_, state)| state.address)
+            .collect();
+
+        let mut succeeded = true;
+        if let (true, Some(published)) = (config.reverse.enabled, &published) {
+            for (name, provider) in providers.for_host(host) {
+                // The PTR record may still point from an older address.
+                let address = match published.ptr.get(name) {
+                    Some(ProviderResult {
+                        address: IpAddr::V6(address),
+                        success: true,
+                        ..
+                    }) => *address,
+                    _ => match published.address {
+                        IpAddr::V6(address) => address,
+                        IpAddr::V4(_) => continue,
+                    },
+                };
+                if let Err(e) = provider.withdraw_ptr(host, address, uplink).await {
+                    warn!(
+                        "Failed to withdraw PTR record of {} from uplink {} at {}: {}",
+                        host.hostname, uplink, name, e
+                    );
+                    succeeded = false;
+                }
+            }
+        }
+
+        // Without providers, the records only live in the state cache.
+        let mut deleted = published.is_some();
+        for (name, provider) in providers.for_host(host) {
+            match provider.withdraw_address(host, uplink, &remaining).await {
+                Ok(provider_deleted) => deleted |= provider_deleted,
+                Err(e) => {
+                    warn!(
+                        "Failed to withdraw {} from uplink {} at {}: {}",
+                        host.hostname, uplink, name, e
+                    );
+                    succeeded = false;
+                }
+            }
+        }
+
+        if !succeeded {
+            failed.push(host.hostname.clone());
+            continue;
+        }
+        state_cache
+            .remove_uplink_record(&host.hostname, uplink)
+            .await;
+        if deleted {
+            withdrawn.push(HostChange::withdrawn(
+                &host.hostname,
+                published.map(|published| published.address),
+            ));
+        }
+    }
+
+    (withdrawn, failed)
+}
+
+/// Periodically withdraws the addresses of uplinks whose prefix expired,
+/// and retries withdrawals that failed.
+pub fn spawn_expiry(state: AppState) {
+    let interval = state
+        .config
+        .uplinks
+        .iter()
+        .filter_map(|uplink| uplink.prefix_lifetime_secs)
+        .map(Duration::from_secs)
+        .fold(MAX_EXPIRY_CHECK_INTERVAL, Duration::min);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            for uplink in state.uplinks.expired(&state.config, Instant::now()).await {
+                if state.uplinks.is_active(&uplink).await {
+                    info!("Prefix of uplink {} expired", uplink);
+                } else {
+                    info!("Retrying withdrawal of uplink {}", uplink);
+                }
+                let response = withdraw_uplink(&state, &uplink).await;
+                info!("Withdrawal of uplink {}: {}", uplink, response);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [cloudflare]
+            api_token = "token"
+            zone_id = "zone"
+            [cloudflare.ownership]
+
+            [[uplinks]]
+            name = "isp1"
+            prefix_lifetime_secs = 60
+
+            [[uplinks]]
+            name = "isp2"
+
+            [[hosts]]
+            hostname = "nas.example.com"
+            interface_id = "::10"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_expired() {
+        let config = config();
+        let tracker = UplinkTracker::new();
+        let now = Instant::now();
+
+        assert!(tracker.expired(&config, now).await.is_empty());
+
+        tracker.seen("isp1").await;
+        tracker.seen("isp2").await;
+        assert!(tracker.is_active("isp1").await);
+        assert!(tracker.expired(&config, Instant::now()).await.is_empty());
+
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(tracker.expired(&config, later).await, vec!["isp1"]);
+
+        tracker.withdrawing("isp1").await;
+        assert!(!tracker.is_active("isp1").await);
+        assert_eq!(tracker.active().await, vec!["isp2"]);
+        assert_eq!(tracker.pending_withdrawal().await, vec!["isp1"]);
+        // A failed withdrawal is retried with the next check.
+        assert_eq!(tracker.expired(&config, Instant::now()).await, vec!["isp1"]);
+
+        tracker.withdrawn("isp1").await;
+        assert!(tracker.pending_withdrawal().await.is_empty());
+        assert!(tracker.expired(&config, later).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_withdrawn_keeps_uplink_that_came_back() {
+        let tracker = UplinkTracker::new();
+
+        tracker.withdrawing("isp2").await;
+        tracker.seen("isp2").await;
+        tracker.withdrawn("isp2").await;
+        assert!(tracker.is_active("isp2").await);
+        assert!(tracker.pending_withdrawal().await.is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::config::WebhookConfig;
+use crate::error::{Ddns6Error, Result};
+use crate::event::ChangeEvent;
+use crate::provider::http_client;
+
+/// Header carrying `sha256=<hex>`, the HMAC-SHA256 of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Ddns6-Signature";
+
+/// Posts change events to the configured webhooks in the background.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    client: Client,
+    webhooks: Arc<Vec<WebhookConfig>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(webhooks: &[WebhookConfig]) -> Self {
+        Self {
+            client: http_client(),
+            webhooks: Arc::new(webhooks.to_vec()),
+        }
+    }
+
+    /// Delivers `event` to every webhook without waiting for the result.
+    pub fn notify(&self, event: &ChangeEvent) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize change event: {}", e);
+                return;
+            }
+        };
+
+        for webhook in self.webhooks.iter().cloned() {
+            let client = self.client.clone();
+            let body = body.clone();
+            tokio::spawn(async move {
+                if let Err(e) = deliver(&client, &webhook, body).await {
+                    error!("Giving up on webhook {}: {}", webhook.url, e);
+                }
+            });
+        }
+    }
+}
+
+/// Posts `body`, retrying failed attempts with exponential backoff.
+async fn deliver(client: &Client, webhook: &WebhookConfig, body: Vec<u8>) -> Result<()> {
+    let mut delay = Duration::from_millis(webhook.retry_delay_ms);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        match post(client, webhook, body.clone()).await {
+            Ok(()) => {
+                info!("Delivered change event to webhook {}", webhook.url);
+                return Ok(());
+            }
+            Err(e) if attempt <= webhook.retries => {
+                warn!(
+                    "Webhook {} failed (attempt {}), retrying in {:?}: {}",
+                    webhook.url, attempt, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn post(client: &Client, webhook: &WebhookConfig, body: Vec<u8>) -> Result<()> {
+    let mut request = client
+        .post(&webhook.url)
+        .timeout(Duration::from_secs(webhook.timeout_secs))
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = &webhook.secret {
+        request = request.header(SIGNATURE_HEADER, signature(secret.expose(), &body));
+    }
+
+    debug!("Posting change event to webhook {}", webhook.url);
+    let response = request.body(body).send().await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Ddns6Error::Webhook(format!(
+            "{} responded with {}",
+            webhook.url, status
+        )));
+    }
+
+    Ok(())
+}
+
+/// Computes the signature header value for `body`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+}
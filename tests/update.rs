@@ -0,0 +1,1642 @@
+//! End-to-end tests: the axum app from `http::create_server` talking to a
+//! mocked Cloudflare API.
+
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use wiremock::matchers::{bearer_token, body_partial_json, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use ddns6::config::{Config, HookConfig, WebhookConfig};
+use ddns6::http;
+use ddns6::ipv6;
+use ddns6::webhook;
+
+const ZONE: &str = "zone-id";
+const RECORDS_PATH: &str = "/client/v4/zones/zone-id/dns_records";
+
+fn config(cloudflare: &MockServer, hosts: &[(&str, &str)]) -> Config {
+    config_with(cloudflare, &format!("zone_id = \"{}\"", ZONE), hosts)
+}
+
+/// Builds a configuration with extra TOML appended to the `[cloudflare]` table.
+fn config_with(cloudflare: &MockServer, extra: &str, hosts: &[(&str, &str)]) -> Config {
+    let mut toml = format!(
+        r#"
+        [server]
+        bind_address = "127.0.0.1:0"
+
+        [cloudflare]
+        api_url = "{}/client/v4"
+        api_token = "test-token"
+        {}
+        "#,
+        cloudflare.uri(),
+        extra
+    );
+
+    for (hostname, interface_id) in hosts {
+        toml.push_str(&format!(
+            "\n[[hosts]]\nhostname = \"{}\"\ninterface_id = \"{}\"\n",
+            hostname, interface_id
+        ));
+    }
+
+    Config::from_toml(&toml).unwrap()
+}
+
+/// Starts the daemon on an ephemeral port and returns its base URL.
+async fn start_daemon(config: Config) -> String {
+    let app = http::create_server(Arc::new(config)).await.unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+
+    format!("http://{}", addr)
+}
+
+async fn update(daemon: &str, prefix: &str) -> String {
+    reqwest::get(format!("{}/update?prefix={}", daemon, prefix))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+fn envelope(result: Value) -> Value {
+    json!({
+        "success": true,
+        "errors": [],
+        "messages": [],
+        "result": result,
+    })
+}
+
+fn record(id: &str, name: &str, content: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "AAAA",
+        "name": name,
+        "content": content,
+        "ttl": 300,
+        "proxied": false,
+    })
+}
+
+async fn mock_lookup(cloudflare: &MockServer, hostname: &str, records: Vec<Value>) {
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .and(query_param("type", "AAAA"))
+        .and(query_param("name", hostname))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!(records))))
+        .mount(cloudflare)
+        .await;
+}
+
+#[tokio::test]
+async fn test_health_check() {
+    let cloudflare = MockServer::start().await;
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    let body = reqwest::get(format!("{}/", daemon))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(body, "OK");
+}
+
+#[tokio::test]
+async fn test_creates_missing_record() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "device1.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(bearer_token("test-token"))
+        .and(body_partial_json(json!({
+            "type": "AAAA",
+            "name": "device1.example.com",
+            "content": "2a02:8070:1234:5678::1",
+            "ttl": 300,
+            "proxied": false,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "new-record",
+            "device1.example.com",
+            "2a02:8070:1234:5678::1",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1234:5678::abcd").await,
+        "good device1.example.com=2a02:8070:1234:5678::1"
+    );
+}
+
+#[tokio::test]
+async fn test_updates_existing_record() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(
+        &cloudflare,
+        "device1.example.com",
+        vec![record("existing", "device1.example.com", "2a02:8070:1::1")],
+    )
+    .await;
+
+    Mock::given(method("PATCH"))
+        .and(path(format!("{}/existing", RECORDS_PATH)))
+        .and(body_partial_json(json!({
+            "content": "2a02:8070:2::1",
+            "proxied": false,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "existing",
+            "device1.example.com",
+            "2a02:8070:2::1",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "good device1.example.com=2a02:8070:2::1"
+    );
+}
+
+#[tokio::test]
+async fn test_unchanged_prefix_returns_nochg() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "device1.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "new-record",
+            "device1.example.com",
+            "2a02:8070:1::1",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good device1.example.com=2a02:8070:1::1"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::ffff").await,
+        "nochg device1.example.com=2a02:8070:1::1"
+    );
+}
+
+#[tokio::test]
+async fn test_partial_failure() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "device1.example.com", vec![]).await;
+    mock_lookup(&cloudflare, "device2.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({ "name": "device1.example.com" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-1",
+            "device1.example.com",
+            "2a02:8070:1::1",
+        ))))
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({ "name": "device2.example.com" })))
+        .respond_with(ResponseTemplate::new(500).set_body_string("internal error"))
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(
+        &cloudflare,
+        &[
+            ("device1.example.com", "::1"),
+            ("device2.example.com", "::2"),
+        ],
+    ))
+    .await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "partial success: device1.example.com=2a02:8070:1::1 | failed: device2.example.com"
+    );
+}
+
+#[tokio::test]
+async fn test_auth_error() {
+    let cloudflare = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "success": false,
+            "errors": [{ "code": 10000, "message": "Authentication error" }],
+            "messages": [],
+            "result": null,
+        })))
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "911 Failed to update: device1.example.com"
+    );
+
+    // Failed updates are not cached, so the next request retries.
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "911 Failed to update: device1.example.com"
+    );
+}
+
+#[tokio::test]
+async fn test_api_error_in_successful_response() {
+    let cloudflare = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": false,
+            "errors": [{ "code": 9109, "message": "Invalid access token" }],
+            "messages": [],
+            "result": null,
+        })))
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "911 Failed to update: device1.example.com"
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_json() {
+    let cloudflare = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_string("{not json"))
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "911 Failed to update: device1.example.com"
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_prefix() {
+    let cloudflare = MockServer::start().await;
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "not-an-ip").await,
+        "911 Invalid IPv6 address"
+    );
+    assert!(cloudflare.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_rejects_non_global_prefix() {
+    let cloudflare = MockServer::start().await;
+    let daemon = start_daemon(config(&cloudflare, &[("device1.example.com", "::1")])).await;
+
+    assert_eq!(
+        update(&daemon, "fe80::1").await,
+        "badprefix Prefix is not globally routable: fe80::1 is a link-local address"
+    );
+    assert_eq!(
+        update(&daemon, "fd12:3456:789a::1").await,
+        "badprefix Prefix is not globally routable: fd12:3456:789a::1 is a unique local address"
+    );
+    assert!(cloudflare.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_zone_resolved_by_name() {
+    let cloudflare = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/client/v4/zones"))
+        .and(query_param("name", "example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([
+            { "id": ZONE, "name": "example.com" }
+        ]))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    mock_lookup(&cloudflare, "device1.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "new-record",
+            "device1.example.com",
+            "2a02:8070:1::1",
+        ))))
+        .expect(2)
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config_with(
+        &cloudflare,
+        r#"zones = ["example.com"]"#,
+        &[("device1.example.com", "::1")],
+    ))
+    .await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good device1.example.com=2a02:8070:1::1"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "good device1.example.com=2a02:8070:2::1"
+    );
+}
+
+#[tokio::test]
+async fn test_refuses_unmarked_record() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(
+        &cloudflare,
+        "device1.example.com",
+        vec![record("hand-made", "device1.example.com", "2a02:8070:1::1")],
+    )
+    .await;
+
+    Mock::given(method("PATCH"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config_with(
+        &cloudflare,
+        &format!("zone_id = \"{}\"\n[cloudflare.ownership]", ZONE),
+        &[("device1.example.com", "::1")],
+    ))
+    .await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "911 Failed to update: device1.example.com"
+    );
+}
+
+#[tokio::test]
+async fn test_cleanup_deletes_only_marked_stale_records() {
+    let cloudflare = MockServer::start().await;
+    let marked = |id: &str, name: &str, content: &str| {
+        let mut record = record(id, name, content);
+        record["comment"] = json!("managed-by=ddns6");
+        record
+    };
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .and(query_param("type", "AAAA"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([
+            marked("configured", "nas.example.com", "2a02:8070:1::10"),
+            marked("stale", "old.example.com", "2a02:8070:1::20"),
+            record("hand-made", "manual.example.com", "2a02:8070:1::30"),
+        ]))))
+        .mount(&cloudflare)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .and(query_param("type", "A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([]))))
+        .mount(&cloudflare)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/stale", RECORDS_PATH)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!({"id": "stale"}))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/configured", RECORDS_PATH)))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&cloudflare)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/hand-made", RECORDS_PATH)))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&cloudflare)
+        .await;
+
+    start_daemon(config_with(
+        &cloudflare,
+        &format!(
+            "zone_id = \"{}\"\n[cloudflare.ownership]\n\n[cleanup]\nenabled = true",
+            ZONE
+        ),
+        &[("nas.example.com", "::10")],
+    ))
+    .await;
+
+    // A records are listed once all AAAA records have been handled.
+    for _ in 0..100 {
+        let requests = cloudflare.received_requests().await.unwrap();
+        if requests
+            .iter()
+            .any(|request| request.url.query().is_some_and(|q| q.contains("type=A&")))
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_cleanup_deletes_stale_powerdns_record_sets() {
+    let cloudflare = MockServer::start().await;
+    let powerdns = MockServer::start().await;
+    let zone_path = "/api/v1/servers/localhost/zones/example.com.";
+    let rrset = |name: &str, record_type: &str, content: &str, comment: &str| {
+        json!({
+            "name": name,
+            "type": record_type,
+            "records": [{"content": content, "disabled": false}],
+            "comments": [{"content": comment, "account": "ddns6"}],
+        })
+    };
+    Mock::given(method("GET"))
+        .and(path(zone_path))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rrsets": [
+                rrset("nas.example.com.", "AAAA", "2a02:8070:1::10", "managed-by=ddns6"),
+                rrset("nas.example.com.", "A", "192.0.2.10", "managed-by=ddns6"),
+                rrset("old.example.com.", "AAAA", "2a02:8070:1::20", "managed-by=ddns6"),
+                rrset("manual.example.com.", "AAAA", "2a02:8070:1::30", "added by hand"),
+            ],
+        })))
+        .mount(&powerdns)
+        .await;
+    for (name, record_type, deletions) in [
+        ("old.example.com.", "AAAA", 1),
+        ("nas.example.com.", "A", 1),
+        ("nas.example.com.", "AAAA", 0),
+        ("manual.example.com.", "AAAA", 0),
+    ] {
+        Mock::given(method("PATCH"))
+            .and(path(zone_path))
+            .and(body_partial_json(json!({
+                "rrsets": [{"name": name, "type": record_type, "changetype": "DELETE"}],
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(deletions)
+            .mount(&powerdns)
+            .await;
+    }
+
+    // Without ownership, the Cloudflare records are left alone.
+    let providers = format!(
+        r#"zone_id = "{}"
+
+        [cleanup]
+        enabled = true
+
+        [[providers]]
+        name = "internal"
+        type = "powerdns"
+        api_url = "{}"
+        api_key = "pdns-key"
+        zones = ["example.com"]
+        "#,
+        ZONE,
+        powerdns.uri()
+    );
+    start_daemon(config_with(
+        &cloudflare,
+        &providers,
+        &[("nas.example.com", "::10")],
+    ))
+    .await;
+
+    for _ in 0..100 {
+        let requests = powerdns.received_requests().await.unwrap();
+        if requests
+            .iter()
+            .filter(|request| request.method == wiremock::http::Method::PATCH)
+            .count()
+            == 2
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(cloudflare.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_a_record_from_myip_and_peer() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "router.example.com", vec![]).await;
+
+    Mock::given(method("GET"))
+        .and(path(RECORDS_PATH))
+        .and(query_param("type", "A"))
+        .and(query_param("name", "router.example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([]))))
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({ "type": "AAAA" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-6",
+            "router.example.com",
+            "2a02:8070:1::1",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({ "type": "A" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-4",
+            "router.example.com",
+            "192.0.2.1",
+        ))))
+        .expect(2)
+        .mount(&cloudflare)
+        .await;
+
+    let config = config_with(
+        &cloudflare,
+        &format!(
+            "zone_id = \"{}\"\n[[hosts]]\nhostname = \"router.example.com\"\ninterface_id = \"::1\"\nipv4 = true",
+            ZONE
+        ),
+        &[],
+    );
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::&myip=192.0.2.1").await,
+        "good router.example.com=2a02:8070:1::1, router.example.com=192.0.2.1"
+    );
+
+    // Without an explicit address, the peer address (127.0.0.1) is used.
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good router.example.com=127.0.0.1"
+    );
+}
+
+#[tokio::test]
+async fn test_view_records_use_static_prefix() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    mock_lookup(&cloudflare, "nas.lan.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({
+            "name": "nas.example.com",
+            "content": "2a02:8070:1::10",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-public",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({
+            "name": "nas.lan.example.com",
+            "content": "fd12:3456:789a:1::10",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-lan",
+            "nas.lan.example.com",
+            "fd12:3456:789a:1::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let config = config_with(
+        &cloudflare,
+        &format!(
+            r#"zone_id = "{}"
+
+            [[views]]
+            name = "lan"
+            prefix = "fd12:3456:789a:1::/64"
+
+            [[hosts]]
+            hostname = "nas.example.com"
+            interface_id = "::10"
+
+            [[hosts.views]]
+            view = "lan"
+            hostname = "nas.lan.example.com"
+            "#,
+            ZONE
+        ),
+        &[],
+    );
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10, nas.lan.example.com=fd12:3456:789a:1::10"
+    );
+
+    // The view address does not depend on the reported prefix.
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::ffff").await,
+        "nochg nas.example.com=2a02:8070:1::10, nas.lan.example.com=fd12:3456:789a:1::10"
+    );
+}
+
+#[tokio::test]
+async fn test_uplinks_publish_and_withdraw_separately() {
+    let cloudflare = MockServer::start().await;
+
+    let mut isp1_record = record("record-isp1", "nas.example.com", "2a02:8070:1::10");
+    isp1_record["comment"] = json!("managed-by=ddns6 iid=::10 uplink=isp1");
+    mock_lookup(&cloudflare, "nas.example.com", vec![isp1_record]).await;
+
+    // isp2 gets its own record instead of taking over the one of isp1.
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .and(body_partial_json(json!({
+            "content": "2a02:8070:2::10",
+            "comment": "managed-by=ddns6 iid=::10 uplink=isp2",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-isp2",
+            "nas.example.com",
+            "2a02:8070:2::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("PATCH"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/record-isp1", RECORDS_PATH)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(envelope(json!({ "id": "record-isp1" }))),
+        )
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let config = config_with(
+        &cloudflare,
+        &format!(
+            r#"zone_id = "{}"
+            [cloudflare.ownership]
+
+            [[uplinks]]
+            name = "isp1"
+
+            [[uplinks]]
+            name = "isp2"
+            "#,
+            ZONE
+        ),
+        &[("nas.example.com", "::10")],
+    );
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks)
+        .await;
+    let mut config = config;
+    config.webhooks = vec![WebhookConfig {
+        url: hooks.uri(),
+        secret: None,
+        timeout_secs: 10,
+        retries: 0,
+        retry_delay_ms: 10,
+    }];
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::&uplink=isp2").await,
+        "good nas.example.com=2a02:8070:2::10"
+    );
+    assert_eq!(
+        update(&daemon, "&uplink=isp1&offline=yes").await,
+        "good nas.example.com"
+    );
+
+    let mut requests = Vec::new();
+    for _ in 0..100 {
+        requests = hooks.received_requests().await.unwrap();
+        if requests.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(requests.len(), 2, "withdrawal was not sent to the webhook");
+    let withdrawal = requests
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .find(|event| event["uplink"] == "isp1")
+        .unwrap();
+    assert_eq!(withdrawal["new_prefix"], Value::Null);
+    assert_eq!(withdrawal["hosts"][0]["hostname"], "nas.example.com");
+    assert_eq!(withdrawal["hosts"][0]["new_address"], Value::Null);
+    assert_eq!(
+        update(&daemon, "2a02:8070:3::&uplink=isp3").await,
+        "911 Invalid DynDNS2 request: Unknown uplink isp3"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:3::").await,
+        "911 Invalid DynDNS2 request: Missing uplink parameter"
+    );
+}
+
+#[tokio::test]
+async fn test_failed_uplink_withdrawal_is_retried() {
+    let cloudflare = MockServer::start().await;
+
+    let mut isp1_record = record("record-isp1", "nas.example.com", "2a02:8070:1::10");
+    isp1_record["comment"] = json!("managed-by=ddns6 iid=::10 uplink=isp1");
+    mock_lookup(&cloudflare, "nas.example.com", vec![isp1_record]).await;
+
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/record-isp1", RECORDS_PATH)))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/record-isp1", RECORDS_PATH)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(envelope(json!({ "id": "record-isp1" }))),
+        )
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let config = config_with(
+        &cloudflare,
+        &format!(
+            r#"zone_id = "{}"
+            [cloudflare.ownership]
+
+            [[uplinks]]
+            name = "isp1"
+            prefix_lifetime_secs = 1
+            "#,
+            ZONE
+        ),
+        &[("nas.example.com", "::10")],
+    );
+    let daemon = start_daemon(config).await;
+
+    assert!(update(&daemon, "&uplink=isp1&offline=yes")
+        .await
+        .starts_with("911"));
+    let status: Value = reqwest::get(format!("{}/api/v1/status", daemon))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["uplinks"][0]["active"], false);
+    assert_eq!(status["uplinks"][0]["pending_withdrawal"], true);
+
+    // The expiry check retries the withdrawal until it succeeds.
+    let mut status = Value::Null;
+    for _ in 0..50 {
+        status = reqwest::get(format!("{}/api/v1/status", daemon))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if status["uplinks"][0]["pending_withdrawal"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(status["uplinks"][0]["pending_withdrawal"], false);
+}
+
+#[tokio::test]
+async fn test_webhook_retried_and_signed() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-1",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .mount(&cloudflare)
+        .await;
+
+    let hooks = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&hooks)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&hooks)
+        .await;
+
+    let mut config = config(&cloudflare, &[("nas.example.com", "::10")]);
+    config.webhooks = vec![WebhookConfig {
+        url: format!("{}/hook", hooks.uri()),
+        secret: Some("s3cret".into()),
+        timeout_secs: 10,
+        retries: 3,
+        retry_delay_ms: 10,
+    }];
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+
+    let mut requests = Vec::new();
+    for _ in 0..100 {
+        requests = hooks.received_requests().await.unwrap();
+        if requests.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(requests.len(), 2, "webhook was not retried");
+
+    let delivered = &requests[1];
+    assert_eq!(
+        delivered.headers[webhook::SIGNATURE_HEADER],
+        webhook::signature("s3cret", &delivered.body).as_str()
+    );
+
+    let event: Value = serde_json::from_slice(&delivered.body).unwrap();
+    assert_eq!(event["old_prefix"], Value::Null);
+    assert_eq!(event["new_prefix"], "2a02:8070:1::/64");
+    assert_eq!(event["hosts"][0]["hostname"], "nas.example.com");
+    assert_eq!(event["hosts"][0]["old_address"], Value::Null);
+    assert_eq!(event["hosts"][0]["new_address"], "2a02:8070:1::10");
+}
+
+#[tokio::test]
+async fn test_status_reports_records_and_hooks() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-1",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .mount(&cloudflare)
+        .await;
+
+    let mut config = config(&cloudflare, &[("nas.example.com", "::10")]);
+    config.on_change = vec![HookConfig {
+        command: "test \"$DDNS6_NEW_PREFIX\" = 2a02:8070:1::/64 && exit 2".to_string(),
+        per_host: false,
+        timeout_secs: 5,
+    }];
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        status = reqwest::get(format!("{}/api/v1/status", daemon))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if !status["hooks"][0]["last_run"].is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(status["prefixes"][0]["prefix"], "2a02:8070:1::/64");
+    assert_eq!(status["records"][0]["hostname"], "nas.example.com");
+    assert_eq!(status["records"][0]["type"], "AAAA");
+    assert_eq!(status["records"][0]["address"], "2a02:8070:1::10");
+
+    let last_run = &status["hooks"][0]["last_run"];
+    assert_eq!(last_run["exit_code"], 2);
+    assert_eq!(last_run["success"], false);
+}
+
+#[tokio::test]
+async fn test_settle_window_publishes_last_prefix() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    for (content, calls) in [
+        ("2a02:8070:1::10", 1),
+        ("2a02:8070:2::10", 0),
+        ("2a02:8070:3::10", 1),
+    ] {
+        Mock::given(method("POST"))
+            .and(path(RECORDS_PATH))
+            .and(body_partial_json(json!({ "content": content })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+                "record-1",
+                "nas.example.com",
+                content,
+            ))))
+            .expect(calls)
+            .mount(&cloudflare)
+            .await;
+    }
+
+    let mut config = config(&cloudflare, &[("nas.example.com", "::10")]);
+    config.debounce.settle_secs = 1;
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "good pending 2a02:8070:2::/64"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:3::").await,
+        "good pending 2a02:8070:3::/64"
+    );
+
+    let status = |daemon: String| async move {
+        reqwest::get(format!("{}/api/v1/status", daemon))
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap()
+    };
+
+    let report = status(daemon.clone()).await;
+    assert_eq!(report["prefixes"][0]["prefix"], "2a02:8070:1::/64");
+    assert_eq!(report["prefixes"][0]["pending_prefix"], "2a02:8070:3::/64");
+    assert_eq!(report["prefixes"][0]["changes_last_hour"], 2);
+
+    // The prefix is swapped before the record is published, so wait for the
+    // record.
+    let mut report = Value::Null;
+    for _ in 0..100 {
+        report = status(daemon.clone()).await;
+        if report["records"][0]["address"] == "2a02:8070:3::10" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(report["prefixes"][0]["prefix"], "2a02:8070:3::/64");
+    assert!(report["prefixes"][0]["pending_prefix"].is_null());
+    assert_eq!(report["records"][0]["address"], "2a02:8070:3::10");
+}
+
+#[tokio::test]
+async fn test_history_records_prefixes_and_outcomes() {
+    let cloudflare = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-1",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .mount(&cloudflare)
+        .await;
+
+    let daemon = start_daemon(config(&cloudflare, &[("nas.example.com", "::10")])).await;
+
+    update(&daemon, "2a02:8070:1::").await;
+    update(&daemon, "2a02:8070:1::1").await;
+    update(&daemon, "2a02:8070:2::").await;
+
+    let history: Value = reqwest::get(format!("{}/api/v1/history", daemon))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let entries = history.as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["prefix"], "2a02:8070:1::/64");
+    assert_eq!(entries[0]["reports"], 2);
+    assert!(entries[0]["source"]
+        .as_str()
+        .unwrap()
+        .starts_with("http 127.0.0.1"));
+    assert_eq!(entries[0]["hosts"][0]["hostname"], "nas.example.com");
+    assert_eq!(entries[0]["hosts"][0]["address"], "2a02:8070:1::10");
+    assert_eq!(entries[0]["hosts"][0]["outcome"], "updated");
+    assert_eq!(entries[1]["prefix"], "2a02:8070:2::/64");
+
+    let first_seen = entries[0]["first_seen"].as_u64().unwrap();
+    let before: Value = reqwest::get(format!(
+        "{}/api/v1/history?until={}",
+        daemon,
+        first_seen - 1
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(before, json!([]));
+}
+
+#[tokio::test]
+async fn test_ptr_record_follows_prefix() {
+    const REVERSE_ZONE: &str = "0.7.0.8.2.0.a.2.ip6.arpa";
+    const REVERSE_RECORDS_PATH: &str = "/client/v4/zones/reverse-zone/dns_records";
+    let cloudflare = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/client/v4/zones"))
+        .and(query_param("name", REVERSE_ZONE))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([
+            { "id": "reverse-zone", "name": REVERSE_ZONE }
+        ]))))
+        .mount(&cloudflare)
+        .await;
+
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "record-1",
+            "nas.example.com",
+            "2a02:8070:2::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let old_name = ipv6::reverse_name("2a02:8070:1::10".parse().unwrap());
+    let new_name = ipv6::reverse_name("2a02:8070:2::10".parse().unwrap());
+    let ptr = |id: &str, name: &str, comment: &str| {
+        json!({
+            "id": id,
+            "type": "PTR",
+            "name": name,
+            "content": "nas.example.com",
+            "ttl": 300,
+            "comment": comment,
+        })
+    };
+
+    Mock::given(method("GET"))
+        .and(path(REVERSE_RECORDS_PATH))
+        .and(query_param("type", "PTR"))
+        .and(query_param("name", new_name.as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([]))))
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(REVERSE_RECORDS_PATH))
+        .and(body_partial_json(json!({
+            "type": "PTR",
+            "name": new_name,
+            "content": "nas.example.com",
+            "proxied": false,
+            "comment": "managed-by=ddns6",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(ptr(
+            "ptr-new",
+            &new_name,
+            "managed-by=ddns6",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    // Only the marked PTR record of the previous address is removed.
+    Mock::given(method("GET"))
+        .and(path(REVERSE_RECORDS_PATH))
+        .and(query_param("type", "PTR"))
+        .and(query_param("content", "nas.example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(json!([
+            ptr("ptr-old", &old_name, "managed-by=ddns6"),
+            ptr("ptr-manual", &old_name, "added by hand"),
+            ptr("ptr-new", &new_name, "managed-by=ddns6"),
+        ]))))
+        .mount(&cloudflare)
+        .await;
+
+    Mock::given(method("DELETE"))
+        .and(path(format!("{}/ptr-old", REVERSE_RECORDS_PATH)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(envelope(json!({ "id": "ptr-old" }))),
+        )
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let config = config_with(
+        &cloudflare,
+        &format!(
+            r#"zone_id = "{}"
+            zones = ["{}"]
+            [cloudflare.ownership]
+
+            [reverse]
+            enabled = true
+            "#,
+            ZONE, REVERSE_ZONE
+        ),
+        &[("nas.example.com", "::10")],
+    );
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "good nas.example.com=2a02:8070:2::10"
+    );
+}
+
+/// A DNS query for the AAAA records of `name`.
+fn aaaa_query(name: &str) -> Vec<u8> {
+    let mut query = vec![0xab, 0xcd, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.extend_from_slice(&[0, 0, 28, 0, 1]);
+    query
+}
+
+/// The RCODE and the addresses in the answer section of `response`, which
+/// must answer `query` with AAAA records only.
+fn aaaa_answers(query: &[u8], response: &[u8]) -> (u8, Vec<std::net::Ipv6Addr>) {
+    assert_eq!(&response[..2], &query[..2]);
+    let count = u16::from_be_bytes([response[6], response[7]]) as usize;
+    // Answers follow the echoed question and start with a 2-byte name pointer.
+    let mut offset = query.len();
+    let mut addresses = Vec::new();
+    for _ in 0..count {
+        let octets: [u8; 16] = response[offset + 12..offset + 28].try_into().unwrap();
+        addresses.push(octets.into());
+        offset += 28;
+    }
+    (response[3] & 0x0f, addresses)
+}
+
+#[tokio::test]
+async fn test_dns_server_without_provider() {
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let dns_addr = format!("127.0.0.1:{}", port);
+    let config = Config::from_toml(&format!(
+        r#"
+        [server]
+        bind_address = "127.0.0.1:0"
+
+        [dns]
+        bind_address = "{}"
+        zones = ["example.com"]
+
+        [[hosts]]
+        hostname = "nas.example.com"
+        interface_id = "::10"
+        "#,
+        dns_addr
+    ))
+    .unwrap();
+    let daemon = start_daemon(config).await;
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let ask = |name: &str| {
+        let query = aaaa_query(name);
+        let socket = &socket;
+        let dns_addr = &dns_addr;
+        async move {
+            socket.send_to(&query, dns_addr).await.unwrap();
+            let mut buf = [0; 512];
+            let len = socket.recv(&mut buf).await.unwrap();
+            aaaa_answers(&query, &buf[..len])
+        }
+    };
+
+    // Configured hosts are unknown until a prefix was reported.
+    assert_eq!(ask("nas.example.com").await, (3, vec![]));
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+    assert_eq!(
+        ask("nas.example.com").await,
+        (0, vec!["2a02:8070:1::10".parse().unwrap()])
+    );
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:2::").await,
+        "good nas.example.com=2a02:8070:2::10"
+    );
+
+    // The same answer over TCP, with its 2-byte length prefix.
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let query = aaaa_query("nas.example.com");
+    let mut stream = tokio::net::TcpStream::connect(&dns_addr).await.unwrap();
+    stream.write_u16(query.len() as u16).await.unwrap();
+    stream.write_all(&query).await.unwrap();
+    let len = stream.read_u16().await.unwrap();
+    let mut response = vec![0; len as usize];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        aaaa_answers(&query, &response),
+        (0, vec!["2a02:8070:2::10".parse().unwrap()])
+    );
+}
+
+#[tokio::test]
+async fn test_zone_file_output() {
+    let dir = std::env::temp_dir().join(format!("ddns6-e2e-output-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let zone_path = dir.join("dynamic.zone");
+    let config = Config::from_toml(&format!(
+        r#"
+        [server]
+        bind_address = "127.0.0.1:0"
+
+        [[outputs]]
+        format = "zone"
+        path = "{}"
+        ttl = 60
+
+        [[hosts]]
+        hostname = "nas.example.com"
+        interface_id = "::10"
+        "#,
+        zone_path.display()
+    ))
+    .unwrap();
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+
+    let expected = "nas.example.com.\t60\tIN\tAAAA\t2a02:8070:1::10\n";
+    let mut written = String::new();
+    for _ in 0..50 {
+        written = std::fs::read_to_string(&zone_path).unwrap_or_default();
+        if written.contains(expected) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(written.contains(expected), "{}", written);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fan_out_retries_failed_provider() {
+    let cloudflare = MockServer::start().await;
+    let powerdns = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "new-record",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    let zone_path = "/api/v1/servers/localhost/zones/example.com.";
+    Mock::given(method("GET"))
+        .and(path(zone_path))
+        .and(query_param("rrset_name", "nas.example.com."))
+        .and(query_param("rrset_type", "AAAA"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "example.com.",
+            "rrsets": [],
+        })))
+        .mount(&powerdns)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(zone_path))
+        .respond_with(ResponseTemplate::new(500).set_body_string("database locked"))
+        .up_to_n_times(1)
+        .mount(&powerdns)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(zone_path))
+        .and(wiremock::matchers::header("X-API-Key", "pdns-key"))
+        .and(body_partial_json(json!({
+            "rrsets": [{
+                "name": "nas.example.com.",
+                "type": "AAAA",
+                "changetype": "REPLACE",
+                "records": [{"content": "2a02:8070:1::10", "disabled": false}],
+                "comments": [{"content": "managed-by=ddns6"}],
+            }],
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&powerdns)
+        .await;
+
+    let providers = format!(
+        r#"zone_id = "{}"
+
+        [[providers]]
+        name = "internal"
+        type = "powerdns"
+        api_url = "{}"
+        api_key = "pdns-key"
+        zones = ["example.com"]
+        "#,
+        ZONE,
+        powerdns.uri()
+    );
+    let daemon = start_daemon(config_with(
+        &cloudflare,
+        &providers,
+        &[("nas.example.com", "::10")],
+    ))
+    .await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "partial success: nas.example.com=2a02:8070:1::10 | failed: nas.example.com@internal"
+    );
+
+    let status: Value = reqwest::get(format!("{}/api/v1/status", daemon))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let providers = &status["records"][0]["providers"];
+    assert_eq!(providers["cloudflare"]["success"], true);
+    assert_eq!(providers["internal"]["success"], false);
+
+    // Only the provider that failed is retried.
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "good nas.example.com=2a02:8070:1::10"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "nochg nas.example.com=2a02:8070:1::10"
+    );
+}
+
+#[tokio::test]
+async fn test_ptr_record_retried_after_failure() {
+    let cloudflare = MockServer::start().await;
+    let powerdns = MockServer::start().await;
+    mock_lookup(&cloudflare, "nas.example.com", vec![]).await;
+    Mock::given(method("POST"))
+        .and(path(RECORDS_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(envelope(record(
+            "new-record",
+            "nas.example.com",
+            "2a02:8070:1::10",
+        ))))
+        .expect(1)
+        .mount(&cloudflare)
+        .await;
+
+    const REVERSE_ZONE: &str = "0.7.0.8.2.0.a.2.ip6.arpa";
+    let forward_path = "/api/v1/servers/localhost/zones/example.com.";
+    let reverse_path = format!("/api/v1/servers/localhost/zones/{}.", REVERSE_ZONE);
+    let ptr_name = format!(
+        "{}.",
+        ipv6::reverse_name("2a02:8070:1::10".parse().unwrap())
+    );
+
+    for zone_path in [forward_path, reverse_path.as_str()] {
+        Mock::given(method("GET"))
+            .and(path(zone_path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "rrsets": [] })))
+            .mount(&powerdns)
+            .await;
+    }
+    Mock::given(method("PATCH"))
+        .and(path(forward_path))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&powerdns)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(reverse_path.as_str()))
+        .respond_with(ResponseTemplate::new(500).set_body_string("database locked"))
+        .up_to_n_times(1)
+        .mount(&powerdns)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(reverse_path.as_str()))
+        .and(body_partial_json(json!({
+            "rrsets": [{
+                "name": ptr_name,
+                "type": "PTR",
+                "changetype": "REPLACE",
+                "records": [{"content": "nas.example.com.", "disabled": false}],
+                "comments": [{"content": "managed-by=ddns6"}],
+            }],
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&powerdns)
+        .await;
+
+    let providers = format!(
+        r#"zone_id = "{}"
+
+        [reverse]
+        enabled = true
+
+        [[providers]]
+        name = "internal"
+        type = "powerdns"
+        api_url = "{}"
+        api_key = "pdns-key"
+        zones = ["example.com", "{}"]
+        "#,
+        ZONE,
+        powerdns.uri(),
+        REVERSE_ZONE
+    );
+    let daemon = start_daemon(config_with(
+        &cloudflare,
+        &providers,
+        &[("nas.example.com", "::10")],
+    ))
+    .await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "partial success: nas.example.com=2a02:8070:1::10 | failed: nas.example.com"
+    );
+    let status: Value = reqwest::get(format!("{}/api/v1/status", daemon))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["records"][0]["ptr"]["internal"]["success"], false);
+
+    // The AAAA records are unchanged, but the missing PTR record is retried
+    // once.
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "nochg nas.example.com=2a02:8070:1::10"
+    );
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "nochg nas.example.com=2a02:8070:1::10"
+    );
+}
+
+#[tokio::test]
+async fn test_http_provider_templates() {
+    let api = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path("/api/v1/domains/example.com/rrsets/nas/AAAA/"))
+        .and(wiremock::matchers::header(
+            "Authorization",
+            "Token desec-token",
+        ))
+        .and(wiremock::matchers::header(
+            "Content-Type",
+            "application/json",
+        ))
+        .and(body_partial_json(json!({
+            "subname": "nas",
+            "type": "AAAA",
+            "ttl": 3600,
+            "records": ["2a02:8070:1::10"],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "subname": "nas",
+            "type": "AAAA",
+            "ttl": 3600,
+            "records": ["2a02:8070:1::10"],
+        })))
+        .expect(1)
+        .mount(&api)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/api/v1/domains/example.com/rrsets/tv/AAAA/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"records": []})))
+        .expect(1)
+        .mount(&api)
+        .await;
+
+    let config = Config::from_toml(&format!(
+        r#"
+        [server]
+        bind_address = "127.0.0.1:0"
+
+        [[providers]]
+        name = "desec"
+        type = "http"
+        zones = ["example.com"]
+        ttl = 3600
+
+        [providers.update]
+        method = "PUT"
+        url = "{}/api/v1/domains/{{zone}}/rrsets/{{subname}}/{{type}}/"
+        headers = {{ Authorization = "Token desec-token" }}
+        body = '{{"subname": "{{subname}}", "type": "{{type}}", "ttl": {{ttl}}, "records": {{addresses}}}}'
+        checks = [{{ path = "$.records[0]", equals = "{{address}}" }}]
+
+        [[hosts]]
+        hostname = "nas.example.com"
+        interface_id = "::10"
+
+        [[hosts]]
+        hostname = "tv.example.com"
+        interface_id = "::20"
+        "#,
+        api.uri()
+    ))
+    .unwrap();
+    let daemon = start_daemon(config).await;
+
+    assert_eq!(
+        update(&daemon, "2a02:8070:1::").await,
+        "partial success: nas.example.com=2a02:8070:1::10 | failed: tv.example.com"
+    );
+}